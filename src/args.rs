@@ -14,7 +14,11 @@ pub struct Cli {
 #[command(version, about, long_about = None)]
 pub enum Commands {
 	/// Starts the node
-	StartNode(StartNodeCommand)
+	StartNode(StartNodeCommand),
+	/// Starts a light client that only syncs the block headers
+	StartLightClient(StartLightClientCommand),
+	/// Syncs the block headers and checks with a merkle proof from the peers that a transaction is in a block
+	VerifyTransaction(VerifyTransactionCommand),
 }
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
@@ -26,4 +30,44 @@ pub struct StartNodeCommand {
 	/// A file containing a list of trusted peers
 	#[arg(short, long)]
 	pub trusted_peers_file: Option<PathBuf>, // FIXME: Make this a file in the app data
-}
\ No newline at end of file
+
+	/// A JSON file describing the genesis of the chain. The main network is used if not given
+	#[arg(short, long)]
+	pub genesis_file: Option<PathBuf>,
+}
+#[derive(Parser)]
+#[command(version, about, long_about = None)]
+pub struct StartLightClientCommand {
+	/// A file containing a list of trusted peers. The light client needs full nodes to sync from
+	#[arg(short, long)]
+	pub trusted_peers_file: Option<PathBuf>,
+
+	/// A JSON file describing the genesis of the chain. The main network is used if not given
+	#[arg(short, long)]
+	pub genesis_file: Option<PathBuf>,
+}
+#[derive(Parser)]
+#[command(version, about, long_about = None)]
+pub struct VerifyTransactionCommand {
+	/// The id of the transaction, in hexadecimal
+	#[arg(value_parser = parse_hash)]
+	pub txid: [u8; 32],
+
+	/// The hash of the block that includes the transaction, in hexadecimal
+	#[arg(value_parser = parse_hash)]
+	pub block_hash: [u8; 32],
+
+	/// A file containing a list of trusted peers. The proof is requested from full nodes
+	#[arg(short, long)]
+	pub trusted_peers_file: Option<PathBuf>,
+
+	/// A JSON file describing the genesis of the chain. The main network is used if not given
+	#[arg(short, long)]
+	pub genesis_file: Option<PathBuf>,
+}
+
+/// Parses a hash written in hexadecimal
+fn parse_hash(hash: &str) -> Result<[u8; 32], String> {
+	let bytes = hex::decode(hash).map_err(|err| err.to_string())?;
+	bytes.try_into().map_err(|bytes: Vec<u8>| format!("A hash has 32 bytes, not {}", bytes.len()))
+}
@@ -0,0 +1,50 @@
+use serde::{Deserialize, Serialize};
+
+use crate::crypto::hash::hash;
+
+/// The randomness of the leader lottery.
+/// The evolving nonce mixes the VRF output of every block, and the nonce of an epoch is derived from the evolving nonce when the epoch starts,
+/// so the leaders of an epoch can not be known before the previous epoch ends
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct EpochNonces {
+	/// The epoch of the last block
+	pub epoch: u64,
+	/// The nonce of the epoch of the last block
+	pub epoch_nonce: [u8; 32],
+	/// The nonce after mixing the VRF output of the last block
+	pub evolving_nonce: [u8; 32],
+}
+impl EpochNonces {
+	/// The nonces of the genesis block, derived from the seed of the genesis specification
+	pub fn genesis(seed: [u8; 32]) -> Self {
+		Self {
+			epoch: 0,
+			epoch_nonce: derive_epoch_nonce(&seed, 0),
+			evolving_nonce: seed,
+		}
+	}
+	/// Returns the nonce of the given epoch when the next block is added.
+	/// Returns None if the epoch is before the epoch of the last block, as its nonce is not known anymore
+	pub fn get_epoch_nonce(&self, epoch: u64) -> Option<[u8; 32]> {
+		if epoch == self.epoch {
+			Some(self.epoch_nonce)
+		} else if epoch > self.epoch {
+			Some(derive_epoch_nonce(&self.evolving_nonce, epoch))
+		} else {
+			None
+		}
+	}
+	/// Returns the nonces after adding a block of the given epoch with the given VRF output
+	pub fn next(&self, epoch: u64, vrf: &[u8; 32]) -> Self {
+		let epoch_nonce = self.get_epoch_nonce(epoch).unwrap_or(self.epoch_nonce);
+		Self {
+			epoch: epoch.max(self.epoch),
+			epoch_nonce,
+			evolving_nonce: hash(&[self.evolving_nonce, *vrf].concat()),
+		}
+	}
+}
+/// The nonce of an epoch depends on the epoch number, so that epochs without blocks have different nonces
+fn derive_epoch_nonce(evolving_nonce: &[u8; 32], epoch: u64) -> [u8; 32] {
+	hash(&[&evolving_nonce[..], &epoch.to_be_bytes()].concat())
+}
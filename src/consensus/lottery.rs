@@ -13,6 +13,9 @@ impl Lottery {
 	/// node_stake is the stake of the node that runs the lottery
 	/// total_staked is the total amount of coins staked
 	pub fn run_lottery(current_slot: u64, active_slot_coefficient: f32, last_epoch_hash: &[u8; 32], proving_key: &VrfSk, node_stake: u64, total_staked: u64) -> Option<([u8; 32], VrfProof)> {
+		if node_stake == 0 || total_staked == 0 {
+			return None; // Nodes without stake can not be leaders
+		}
 		let mut input = last_epoch_hash.to_vec();
 		input.append(&mut current_slot.to_be_bytes().to_vec());
 		let (lottery_number, proof) = prove(&input, proving_key);
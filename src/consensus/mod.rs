@@ -1,6 +1,8 @@
 use num_bigint::BigUint;
 
 pub mod lottery;
+pub mod stake;
+pub mod epoch_nonce;
 
 
 const SIZE: usize = 32;
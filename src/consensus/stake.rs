@@ -0,0 +1,60 @@
+use serde::{Deserialize, Serialize};
+
+use crate::core::address::P2PKHAddress;
+use crate::core::genesis::GenesisStakeholder;
+use crate::core::utxo::UTXO;
+
+/// The stake of a stakeholder: the sum of the UTxOs owned by its address
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Stake {
+	pub vrf_public_key: [u8; 32],
+	pub address: P2PKHAddress,
+	pub amount: u64,
+}
+
+/// The stake of every stakeholder at some point of the chain. The lottery of an epoch is run with the snapshot taken when the epoch starts
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct StakeDistribution {
+	stakes: Vec<Stake>,
+}
+impl StakeDistribution {
+	/// Calculates the stake of the given stakeholders from the given UTxOs. Coins that are not owned by a stakeholder are not staked
+	pub fn from_utxos<'a>(utxos: impl IntoIterator<Item = &'a UTXO>, stakeholders: &[GenesisStakeholder]) -> Self {
+		// TODO: Allow registering new stakeholders with transactions
+		let mut stakes: Vec<Stake> = stakeholders.iter().map(|stakeholder| Stake {
+			vrf_public_key: stakeholder.vrf_public_key,
+			address: stakeholder.address,
+			amount: 0,
+		}).collect();
+		let mut distribution = Self { stakes };
+		for utxo in utxos {
+			distribution.add_utxo(utxo);
+		}
+		distribution
+	}
+	/// Adds the UTxO to the stake of its owner, if it is a stakeholder
+	pub fn add_utxo(&mut self, utxo: &UTXO) {
+		if let Some(stake) = self.stakes.iter_mut().find(|stake| stake.address == utxo.recipient_address) {
+			stake.amount = stake.amount.saturating_add(utxo.amount);
+		}
+	}
+	/// Removes the UTxO from the stake of its owner, if it is a stakeholder
+	pub fn remove_utxo(&mut self, utxo: &UTXO) {
+		if let Some(stake) = self.stakes.iter_mut().find(|stake| stake.address == utxo.recipient_address) {
+			stake.amount = stake.amount.saturating_sub(utxo.amount);
+		}
+	}
+	/// Returns the stake of the stakeholder with the given VRF key, 0 if it is not a stakeholder
+	pub fn get_stake(&self, vrf_public_key: &[u8; 32]) -> u64 {
+		self.stakes.iter()
+			.filter(|stake| stake.vrf_public_key == *vrf_public_key)
+			.map(|stake| stake.amount)
+			.sum()
+	}
+	pub fn get_total_stake(&self) -> u64 {
+		self.stakes.iter().map(|stake| stake.amount).sum()
+	}
+	pub fn get_stakes(&self) -> &[Stake] {
+		&self.stakes
+	}
+}
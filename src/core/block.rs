@@ -1,28 +1,86 @@
 use std::collections::HashSet;
+use std::fmt::{Display, Formatter};
+
 use serde::{Deserialize, Serialize};
 use serde_big_array::BigArray;
 
 use crate::consensus::lottery::Lottery;
 use crate::core::address::P2PKHAddress;
 use crate::core::blockchain::BlockChain;
+use crate::core::encoding::{BLOCK_HASH_DOMAIN, hash_with_domain};
 use crate::core::Hashable;
-use crate::core::utxo::transaction::Transaction;
-use crate::crypto::hash::merkle::calculate_merkle_root;
+use crate::core::utxo::transaction::{Transaction, TxError};
+use crate::crypto::hash::merkle::{calculate_merkle_root, MerkleProof};
 use crate::crypto::vrf::{VrfPk, VrfProof};
+use crate::network::standard::serialize_bincode;
+
+/// The version of the header format. Headers with other versions are rejected
+pub const BLOCK_HEADER_VERSION: u16 = 1;
 
 #[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
 pub struct BlockHeader {
 	pub hash: [u8; 32],
+	pub version: u16,
+	/// The protocol version of the node that forged the block
+	pub protocol_version: u32,
 	pub height: usize,
 	pub previous_hash: [u8; 32],
 	pub slot: u64,
 	pub merkle_root: [u8; 32],
+	/// The merkle root of the witness hashes of the transactions, so that the block also commits to their signatures
+	pub witness_root: [u8; 32],
+	/// The size in bytes of the serialized transactions of the block
+	pub body_size: u64,
 	pub vrf: [u8; 32],
 	#[serde(with = "BigArray")]
 	pub vrf_proof: [u8; 96],
 	pub forger_vrf_public_key: [u8; 32],
 	pub forger_address: P2PKHAddress,
 }
+impl BlockHeader {
+	/// The hash of the block, which only depends on the fields of the header.
+	/// The body is committed through the merkle root, the witness root and the body size
+	pub fn calculate_hash(&self) -> [u8; 32] {
+		hash_with_domain(BLOCK_HASH_DOMAIN, self)
+	}
+	/// Checks what can be checked of the header without the body nor the chain: the version and the hash
+	pub fn verify(&self) -> Result<(), BlockError> {
+		if self.version != BLOCK_HEADER_VERSION {
+			return Err(BlockError::UnsupportedVersion(self.version));
+		}
+		if self.calculate_hash() != self.hash {
+			return Err(BlockError::InvalidHash);
+		}
+		Ok(())
+	}
+	/// Checks that the transaction with the given id is in the body of the block, using only the header and the merkle proof
+	pub fn verify_transaction_proof(&self, txid: &[u8; 32], proof: &MerkleProof) -> bool {
+		proof.verify(txid, &self.merkle_root)
+	}
+	/// Checks that the VRF proof of the header was generated by the forger's VRF key for the header's slot.
+	/// Does not check if the VRF output actually wins the lottery.
+	pub fn verify_vrf_proof(&self, last_epoch_hash: &[u8; 32]) -> bool {
+		if let Ok(vrf_pk) = VrfPk::from_bytes(&self.forger_vrf_public_key) {
+			Lottery::verify_vrf_lottery(self.slot, last_epoch_hash, self.vrf, self.vrf_proof, &vrf_pk)
+		} else {
+			false
+		}
+	}
+	/// Checks that the VRF proof of the header is valid for its slot and the given epoch nonce,
+	/// and that the forger, with the given stake, won the lottery of the slot
+	pub fn verify_leader(&self, active_slot_coefficient: f32, last_epoch_hash: [u8; 32], node_stake: u64, total_staked: u64) -> Result<(), BlockError> {
+		if !self.verify_vrf_proof(&last_epoch_hash) {
+			return Err(BlockError::InvalidVRF);
+		}
+		if node_stake == 0 || total_staked == 0 {
+			return Err(BlockError::NotLeader);
+		}
+		if !Lottery::is_win(node_stake as f64 / total_staked as f64, active_slot_coefficient, self.vrf) {
+			return Err(BlockError::NotLeader);
+		}
+		Ok(())
+	}
+}
 pub type BlockContent = Vec<Transaction>;
 #[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
 pub struct Block {
@@ -31,13 +89,17 @@ pub struct Block {
 }
 
 impl Block {
-	pub fn new(height: usize, transactions: Vec<Transaction>, slot: u64, previous_hash: [u8; 32], reward_address: P2PKHAddress, forger_vrf_public_key: [u8; 32], vrf: [u8; 32], vrf_proof: &VrfProof) -> Self {
+	pub fn new(height: usize, transactions: Vec<Transaction>, slot: u64, previous_hash: [u8; 32], reward_address: P2PKHAddress, forger_vrf_public_key: [u8; 32], vrf: [u8; 32], vrf_proof: &VrfProof, protocol_version: u32) -> Self {
 		let header = BlockHeader {
 			hash: [0u8; 32],
+			version: BLOCK_HEADER_VERSION,
+			protocol_version,
 			height,
 			previous_hash,
 			slot,
 			merkle_root: [0u8; 32],
+			witness_root: [0u8; 32],
+			body_size: 0,
 			vrf,
 			vrf_proof: vrf_proof.to_bytes(),
 			forger_vrf_public_key,
@@ -47,85 +109,160 @@ impl Block {
 		block.update_hash();
 		block
 	}
-	pub fn genesis() -> Self {
-		
-		// TODO: Choose extra entropy better
-		const EXTRA_ENTROPY:  [u8; 32] = [60, 92, 162, 110, 82, 120, 10, 250, 102, 233, 226, 182, 114, 155, 80, 178, 35, 57, 107, 9, 122, 187, 253, 38, 160, 225, 171, 15, 110, 230, 47, 21];
-		
-		let header = BlockHeader {
-			hash: [0u8; 32],
-			height: 0,
-			previous_hash: EXTRA_ENTROPY,
-			slot: 0u64,
-			merkle_root: [0u8; 32],
-			vrf: [0u8; 32],
-			vrf_proof: [0u8; 96],
-			forger_vrf_public_key: [0u8; 32],
-			forger_address: P2PKHAddress::null(),
-		};
-		let mut block = Block {
-			transactions: vec![],
-			header,
-		};
-		block.update_hash();
-		block
+	/// Checks that the VRF proof of the block is valid for the block's slot and epoch nonce,
+	/// and that the forger, with the given stake, won the lottery of the slot
+	pub fn verify_vrf(&self, active_slot_coefficient: f32, last_epoch_hash: [u8; 32], node_stake: u64, total_staked: u64) -> Result<(), BlockError> {
+		self.header.verify_leader(active_slot_coefficient, last_epoch_hash, node_stake, total_staked)
 	}
-	pub fn verify_vrf(&self, current_slot: u64, active_slot_coefficient: f32, last_epoch_hash: [u8; 32], node_stake: u64, total_staked: u64) -> bool {
-		let vrf =  self.header.vrf;
-		let vrf_proof =  self.header.vrf_proof;
-		if let Ok(vrf_pk) = VrfPk::from_bytes(&self.header.forger_vrf_public_key) {
-			Lottery::verify_vrf_lottery(current_slot, &last_epoch_hash, vrf, vrf_proof, &vrf_pk)
-		} else {
-			false
-		}
+	/// Returns the size in bytes of the serialized transactions
+	pub fn calculate_body_size(&self) -> u64 {
+		serialize_bincode(&self.transactions).map(|data| data.len() as u64).unwrap_or(u64::MAX)
 	}
 	pub fn calculate_merkle_tree(&self) -> [u8; 32]{
-
-		let mut hashes: Vec<[u8; 32]> = Vec::new();
-		for tx in &self.transactions {
-			hashes.push(tx.calculate_hash());
-		}
-		calculate_merkle_root(hashes)
+		calculate_merkle_root(self.get_transaction_hashes())
+	}
+	/// Returns the proof that the transaction with the given id is in the block, or None if it is not
+	pub fn get_transaction_proof(&self, txid: &[u8; 32]) -> Option<MerkleProof> {
+		let hashes = self.get_transaction_hashes();
+		let index = hashes.iter().position(|hash| hash == txid)?;
+		MerkleProof::generate(&hashes, index)
+	}
+	fn get_transaction_hashes(&self) -> Vec<[u8; 32]> {
+		self.transactions.iter().map(|tx| tx.calculate_hash()).collect()
+	}
+	/// Returns the merkle root of the witness hashes of the transactions
+	pub fn calculate_witness_root(&self) -> [u8; 32] {
+		calculate_merkle_root(self.transactions.iter().map(|tx| tx.calculate_witness_hash()).collect())
+	}
+	/// Returns whether the transactions match the merkle root and the witness root of the header
+	pub fn matches_header(&self) -> bool {
+		self.calculate_merkle_tree() == self.header.merkle_root && self.calculate_witness_root() == self.header.witness_root
 	}
 	
-	/// Returns whether the block is correct and has no inconsistencies
-	pub fn is_correct(&self) -> bool {
-		let is_hash_correct = self.calculate_hash() == self.header.hash;
-		let is_merkle_tree_correct = self.calculate_merkle_tree() == self.header.merkle_root;
-		if !(is_merkle_tree_correct && is_hash_correct) {
-			return false;
+	/// Checks that the block is correct and has no inconsistencies
+	pub fn is_correct(&self) -> Result<(), BlockError> {
+		self.header.verify()?;
+		if self.calculate_merkle_tree() != self.header.merkle_root {
+			return Err(BlockError::InvalidMerkleRoot);
+		}
+		if self.calculate_witness_root() != self.header.witness_root {
+			return Err(BlockError::InvalidWitnessRoot);
+		}
+		if self.calculate_body_size() != self.header.body_size {
+			return Err(BlockError::InvalidBodySize);
 		}
 		let mut input_tx_list = HashSet::new();
-		for tx in &self.transactions {
+		for (i, tx) in self.transactions.iter().enumerate() {
 			// CHECKS IF THERE ARE TWO INPUTS USING SAME OUTPUT
 			for input in &tx.input_list {
 				if !input_tx_list.insert(input.calculate_hash()) {
-					return false;
+					return Err(BlockError::DuplicateInput);
 				}
 			}
-			let is_transaction_valid = tx.is_valid_heuristic();
-
-			if !is_transaction_valid {
-				return false;
+			// The first transaction is the coinbase, with a single output and no signatures, and no other input may spend nothing
+			if i == 0 {
+				if !tx.is_coinbase() || tx.output_list.len() != 1 {
+					return Err(BlockError::InvalidCoinbase);
+				}
+			} else if tx.has_coinbase_input() {
+				return Err(BlockError::InvalidCoinbase);
+			} else {
+				tx.is_valid_heuristic().map_err(|err| BlockError::InvalidTransaction(i, err))?;
 			}
 		}
-		true
+		Ok(())
 	}
 }
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum BlockValidity {
-	/// Meaning is not valid
-	NotValid, 
-	/// /// Meaning that it is better than the current last block of the blockchain and should REPLACE it
+	/// Meaning that it was better than the current last block of the blockchain and REPLACED it
 	BetterThanLastBlock, 
+	/// Meaning that it was added at the end of the blockchain
 	Valid,
+	/// Meaning that it belongs to a fork that is not better than the blockchain and was kept as a candidate
+	Fork,
 }
-pub enum InvalidityReason { // TODO
-	/// Last hash is different
-	WrongContext, 
+/// Why a block was rejected
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum BlockError {
+	/// The previous hash is not the hash of the last block
+	WrongParent, 
+	/// The height is not the next one of the parent
+	WrongHeight,
+	/// The header version is not supported
+	UnsupportedVersion(u16),
+	/// The hash of the header does not match its content
 	InvalidHash,
-	/// The VRF was not valid
+	/// The merkle root does not match the transactions
+	InvalidMerkleRoot,
+	/// The witness root does not match the signatures of the transactions
+	InvalidWitnessRoot,
+	/// The body size of the header does not match the transactions
+	InvalidBodySize,
+	/// Two inputs of the block spend the same output
+	DuplicateInput,
+	/// The VRF proof does not verify against the forger's key for the slot and epoch nonce
 	InvalidVRF,
-	/// There is some transaction that is not valid in the block
-	InvalidTransaction, 
-	TooLarge,
-}
\ No newline at end of file
+	/// The VRF output is not under the threshold given by the forger's stake
+	NotLeader,
+	/// The slot is not after the slot of the parent
+	SlotNotIncreasing,
+	/// The slot has not started yet
+	FutureSlot,
+	/// The epoch of the block is before the epoch of the parent
+	UnknownEpochNonce,
+	/// The first transaction is not the coinbase paying the block reward
+	InvalidCoinbase,
+	/// The transaction at the given index is not valid
+	InvalidTransaction(usize, TxError), 
+	/// The body of the block is bigger than the maximum block body size. Contains the size in bytes
+	TooLarge(usize),
+	/// The block is already in the chain or is a known candidate
+	AlreadyKnown,
+	/// The parent of the block is not known
+	Orphan,
+	/// The block forks from the chain deeper than the security parameter allows
+	TooDeepFork,
+	/// The block is the tip of a better fork, but some block of the fork is not valid
+	ReorganizationFailed,
+	/// The block forks from the chain but there is no room for more candidates
+	TooManyCandidates,
+	/// The block could not be written to the database
+	StorageFailure,
+}
+impl BlockError {
+	/// Whether the block can never be valid, which is checked from its bytes alone, whatever the state of the chain and the version of the node.
+	/// Peers that send such blocks misbehave
+	pub fn is_provably_invalid(&self) -> bool {
+		matches!(self, BlockError::InvalidHash | BlockError::InvalidMerkleRoot | BlockError::InvalidWitnessRoot | BlockError::InvalidBodySize
+			| BlockError::DuplicateInput | BlockError::TooLarge(_))
+	}
+}
+impl Display for BlockError {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		match self {
+			BlockError::WrongParent => write!(f, "The previous hash is not the hash of the last block"),
+			BlockError::WrongHeight => write!(f, "The height is not the next one of the parent"),
+			BlockError::UnsupportedVersion(version) => write!(f, "The header version {} is not supported", version),
+			BlockError::InvalidHash => write!(f, "The hash does not match the header"),
+			BlockError::InvalidMerkleRoot => write!(f, "The merkle root does not match the transactions"),
+			BlockError::InvalidWitnessRoot => write!(f, "The witness root does not match the signatures of the transactions"),
+			BlockError::InvalidBodySize => write!(f, "The body size does not match the transactions"),
+			BlockError::DuplicateInput => write!(f, "Two inputs of the block spend the same output"),
+			BlockError::InvalidVRF => write!(f, "The VRF proof is not valid"),
+			BlockError::NotLeader => write!(f, "The forger is not a leader of the slot"),
+			BlockError::SlotNotIncreasing => write!(f, "The slot is not after the slot of the parent"),
+			BlockError::FutureSlot => write!(f, "The slot has not started yet"),
+			BlockError::UnknownEpochNonce => write!(f, "The epoch nonce of the slot is unknown"),
+			BlockError::InvalidCoinbase => write!(f, "The coinbase transaction is not valid"),
+			BlockError::InvalidTransaction(index, err) => write!(f, "The transaction {} is not valid: {}", index, err),
+			BlockError::TooLarge(size) => write!(f, "The block body is too large ({} bytes)", size),
+			BlockError::AlreadyKnown => write!(f, "The block is already known"),
+			BlockError::Orphan => write!(f, "The parent of the block is unknown"),
+			BlockError::TooDeepFork => write!(f, "The block forks deeper than the security parameter"),
+			BlockError::ReorganizationFailed => write!(f, "The fork of the block contains invalid blocks"),
+			BlockError::TooManyCandidates => write!(f, "There are too many forks to keep the block"),
+			BlockError::StorageFailure => write!(f, "The block could not be written to the database"),
+		}
+	}
+}
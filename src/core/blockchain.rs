@@ -1,12 +1,49 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
 
-use crate::core::block::{Block, BlockHeader};
+use crate::consensus::epoch_nonce::EpochNonces;
+use crate::consensus::stake::StakeDistribution;
+use crate::core::address::P2PKHAddress;
+use crate::core::block::{Block, BlockError, BlockHeader, BlockValidity};
+use crate::core::genesis::GenesisSpec;
 use crate::core::parameters::Parameters;
-use crate::core::utxo::transaction::Transaction;
+use crate::core::utxo::transaction::{Transaction, TxError};
 use crate::core::utxo::UTXO;
+use crate::core::utxo::view::UtxoView;
 use crate::data_storage::blockchain_storage::chain_database::ChainDB;
 use crate::data_storage::blockchain_storage::mempool_database::MempoolDB;
+use crate::data_storage::blockchain_storage::reorg_journal::ReorgJournal;
+use crate::data_storage::blockchain_storage::stake_database::StakeDB;
 use crate::data_storage::blockchain_storage::undo_items::{UndoBlock, UndoTransaction};
 use crate::data_storage::blockchain_storage::utxo_database::UTXODB;
+use crate::data_storage::BaseDirectory;
+use crate::network::standard::serialize_bincode;
+
+/// The maximum amount of headers sent in answer to a block locator
+pub(crate) const MAX_HEADERS: usize = 2048;
+
+/// Returns the hashes of the best chain that let a peer find the last common block: the last blocks one by one,
+/// then with a doubling step, always ending with the genesis block
+pub(crate) fn build_block_locator(best_height: usize, get_hash_by_height: impl Fn(usize) -> Option<[u8; 32]>) -> Vec<[u8; 32]> {
+	const DENSE_BLOCKS: usize = 10;
+	let mut locator = vec![];
+	let mut height = best_height;
+	let mut step = 1;
+	loop {
+		if let Some(hash) = get_hash_by_height(height) {
+			locator.push(hash);
+		}
+		if height == 0 {
+			break;
+		}
+		if locator.len() >= DENSE_BLOCKS {
+			step *= 2;
+		}
+		height = height.saturating_sub(step);
+	}
+	locator
+}
 
 #[derive(Clone)]
 pub struct BlockChain {
@@ -14,26 +51,94 @@ pub struct BlockChain {
 	pub utxo_set: UTXODB,
 	pub(crate) mempool: MempoolDB,
 	pub(crate) parameters: Parameters,
+	/// Valid blocks that are not part of the best chain, by hash. These are the forks that may replace the best chain
+	candidate_blocks: HashMap<[u8; 32], Block>,
+	/// The hash of the genesis block, which identifies the chain
+	chain_id: [u8; 32],
+	/// The specification from which the chain was created
+	pub(crate) genesis: GenesisSpec,
+	/// The stake distribution of each epoch
+	stake_snapshots: StakeDB,
+	/// The stake distribution of the current UTxO set. It is updated with the UTxOs of each block that is applied or undone,
+	/// so that the stake of an epoch without snapshot does not need a scan of the whole UTxO set
+	current_stake: StakeDistribution,
+	/// The current slot of the node, shared with the node's clock
+	current_slot: Option<Arc<AtomicU64>>,
+	/// The directory where the chain is stored, which also keeps the journal of the changes of the best chain
+	base_directory: String,
 }
 
 impl BlockChain {
-	pub fn init(parameters: Parameters) -> Self {
-		let chain = ChainDB::default();
-		BlockChain { chain, utxo_set: UTXODB::genesis(parameters), mempool: Default::default(), parameters }
+	pub fn init(genesis: GenesisSpec) -> anyhow::Result<Self> {
+		Self::open(&BaseDirectory::get_base_directory(), genesis)
+	}
+	/// Opens the blockchain stored in the given base directory instead of the default one, creating it from the genesis specification if it does not exist.
+	/// Fails if the stored chain was created from another genesis.
+	/// If the node stopped while changing the best chain, the chain goes back to the blocks it had before the change
+	pub fn open(base_directory: &str, genesis: GenesisSpec) -> anyhow::Result<Self> {
+		let genesis_block = genesis.get_block();
+		let chain = ChainDB::open(base_directory, &genesis_block);
+		let chain_id = chain.get_hash_by_height(0).ok_or_else(|| anyhow::anyhow!("The stored chain has no genesis block"))?;
+		if chain_id != genesis_block.header.hash {
+			return Err(anyhow::anyhow!("The stored chain was created from a different genesis: {} instead of {}", hex::encode(chain_id), hex::encode(genesis_block.header.hash)));
+		}
+		let utxo_set = UTXODB::genesis(base_directory, &genesis, chain.get_length());
+		let mut blockchain = BlockChain {
+			chain,
+			utxo_set,
+			mempool: MempoolDB::open(base_directory),
+			parameters: genesis.parameters,
+			candidate_blocks: HashMap::new(),
+			chain_id,
+			genesis,
+			stake_snapshots: StakeDB::open(base_directory),
+			current_stake: StakeDistribution::default(),
+			current_slot: None,
+			base_directory: base_directory.to_string(),
+		};
+		blockchain.recover_interrupted_change()?;
+		blockchain.current_stake = blockchain.calculate_stake();
+		if blockchain.get_height() == 0 {
+			// The stake of the first epoch is the genesis distribution
+			blockchain.stake_snapshots.insert(0, &blockchain.get_stake_distribution(0));
+		}
+		Ok(blockchain)
+	}
+	/// Returns the nonce used in the lottery of the given epoch for the next block.
+	/// Returns None if the epoch is before the epoch of the last block
+	pub fn get_epoch_nonce(&self, epoch: u64) -> Option<[u8; 32]> {
+		self.chain.get_nonces().get_epoch_nonce(epoch)
+	}
+	/// Returns the stake distribution used in the lottery of the given epoch.
+	/// It is the stake at the start of the epoch, that is, before applying its first block.
+	/// If the epoch has no blocks yet, it is the stake of the current UTxO set.
+	/// Only the stakeholders of the genesis specification have stake: the sum of the UTxOs owned by their addresses.
+	/// Coins owned by any other address are not staked
+	pub fn get_stake_distribution(&self, epoch: u64) -> StakeDistribution {
+		self.stake_snapshots.get(epoch).unwrap_or_else(|| self.current_stake.clone())
+	}
+	/// Calculates the stake distribution from the whole UTxO set
+	fn calculate_stake(&self) -> StakeDistribution {
+		StakeDistribution::from_utxos(&self.utxo_set.get_all(), &self.genesis.stake_distribution)
+	}
+	/// Returns the id of the chain, which is the hash of the genesis block.
+	/// Signatures commit to it so that transactions can not be replayed in another network
+	pub fn get_chain_id(&self) -> [u8; 32] {
+		self.chain_id
 	}
 	pub fn get_utxo_list(&self, txid: &[u8; 32]) -> Option<Vec<UTXO>>{
 		self.utxo_set.get(txid)
 	}
 	
 	/// Validates and adds the transaction to the memory pool if valid.
-	/// Returns whether the tx was added or not
-	pub fn add_transaction_to_mempool(&mut self, tx: &Transaction) -> bool{
-		let is_valid = tx.is_valid(self);
-		if is_valid {
-			self.mempool.insert(tx) && is_valid
-		} else {
-			false
+	/// Returns why the tx was not added otherwise
+	pub fn add_transaction_to_mempool(&mut self, tx: &Transaction) -> Result<(), TxError> {
+		if self.mempool.contains(&tx.id) {
+			return Err(TxError::AlreadyKnown);
 		}
+		tx.is_valid(self)?;
+		self.mempool.insert(tx);
+		Ok(())
 	}
 	pub fn get_context(&self) -> String {
 		let last_block_hash = self.get_last_block().header.hash;
@@ -42,8 +147,86 @@ impl BlockChain {
 	pub fn get_block_at(&self, height: usize) -> Option<Block> {
 		self.chain.get_block_by_height(height)
 	}
-	pub fn get_block_by(&self, hash: [u8; 32]) -> Option<&Block> {
-		todo!()
+	pub fn get_block_by(&self, hash: [u8; 32]) -> Option<Block> {
+		self.chain.get_block(hash)
+	}
+	pub fn contains_block(&self, hash: &[u8; 32]) -> bool {
+		self.chain.contains_block(hash)
+	}
+	/// Returns the hash of the block of the best chain that contains the transaction
+	pub fn get_transaction_block(&self, txid: &[u8; 32]) -> Option<[u8; 32]> {
+		self.chain.get_transaction_block(txid)
+	}
+	/// Returns the transaction with the given id, either from the mempool or confirmed in the best chain
+	pub fn get_transaction(&self, txid: &[u8; 32]) -> Option<Transaction> {
+		if let Some(tx) = self.mempool.get(txid) {
+			return Some(tx);
+		}
+		let block = self.chain.get_block(self.get_transaction_block(txid)?)?;
+		block.transactions.into_iter().find(|tx| tx.id == *txid)
+	}
+	/// Returns the hashes of the best chain going back from the tip. The first blocks are dense and after that
+	/// the step doubles each time, so that a peer can find the last common block with few hashes.
+	/// The genesis block is always the last hash.
+	pub fn get_block_locator(&self) -> Vec<[u8; 32]> {
+		build_block_locator(self.get_height(), |height| self.chain.get_hash_by_height(height))
+	}
+	/// Checks that the given headers are a valid continuation of a block of the best chain.
+	/// Each header must hash to its own hash, point to the previous one, have consecutive heights, strictly increasing slots,
+	/// an allowed body size and a VRF proof valid for its slot with the epoch nonce of its own branch.
+	/// The block bodies and the stake of the forger are not checked here.
+	pub fn is_header_chain_valid(&self, headers: &[BlockHeader]) -> bool {
+		self.check_header_chain(headers).is_ok()
+	}
+	/// Checks the headers like `is_header_chain_valid`, returning why the first invalid header is not valid
+	pub fn check_header_chain(&self, headers: &[BlockHeader]) -> Result<(), BlockError> {
+		let Some(first) = headers.first() else {
+			return Ok(());
+		};
+		let mut parent = self.chain.get_block(first.previous_hash).map(|block| block.header).ok_or(BlockError::Orphan)?;
+		let mut nonces = self.get_nonces_after(&parent.hash).ok_or(BlockError::Orphan)?;
+		for header in headers {
+			header.verify()?;
+			self.check_body_size(header)?;
+			if header.previous_hash != parent.hash {
+				return Err(BlockError::WrongParent);
+			}
+			if header.height != parent.height + 1 {
+				return Err(BlockError::WrongHeight);
+			}
+			if header.slot <= parent.slot {
+				return Err(BlockError::SlotNotIncreasing);
+			}
+			if self.is_slot_in_future(header.slot) {
+				return Err(BlockError::FutureSlot);
+			}
+			let epoch = self.parameters.technical_parameters.get_epoch(header.slot);
+			let epoch_nonce = nonces.get_epoch_nonce(epoch).ok_or(BlockError::UnknownEpochNonce)?;
+			if !header.verify_vrf_proof(&epoch_nonce) {
+				return Err(BlockError::InvalidVRF);
+			}
+			nonces = nonces.next(epoch, &header.vrf);
+			parent = header.clone();
+		}
+		Ok(())
+	}
+	/// Returns the nonces of the lottery after the given block, which is either in the best chain or a candidate.
+	/// The nonces after an older block of the best chain are the nonces that the undo block of the next block restores,
+	/// and the nonces after a candidate are the ones after its fork point updated with the VRF outputs of its branch
+	fn get_nonces_after(&self, hash: &[u8; 32]) -> Option<EpochNonces> {
+		if *hash == self.get_last_block().header.hash {
+			return Some(self.chain.get_nonces());
+		}
+		if let Some(block) = self.chain.get_block(*hash) {
+			let next = self.chain.get_hash_by_height(block.header.height + 1)?;
+			return self.chain.get_undo_block(&next).ok()?.map(|undo_block| undo_block.previous_nonces);
+		}
+		let branch = self.get_fork_branch(hash);
+		let mut nonces = self.get_nonces_after(&branch.first()?.header.previous_hash)?;
+		for block in &branch {
+			nonces = nonces.next(self.parameters.technical_parameters.get_epoch(block.header.slot), &block.header.vrf);
+		}
+		Some(nonces)
 	}
 
 	fn get_last_common_block(&self, others: &Vec<[u8; 32]>) -> Option<Block> {
@@ -74,7 +257,6 @@ impl BlockChain {
 		if let Some(last_common) = self.get_last_common_block(others){
 			let height = last_common.header.height;
 			let mut result = vec![];
-			const MAX_HEADERS: usize = 2048;
 			for i in 0..MAX_HEADERS {
 				if let Some(block) = self.chain.get_block_by_height(height + i) {
 					result.push(block.header);
@@ -95,106 +277,491 @@ impl BlockChain {
 	pub fn print_debug(&self) {
 		self.chain.print_debug();
 	}
-	pub fn add_block(&mut self, new_block: &Block) -> bool {
-		if new_block.is_valid(self) { // TODO: In this line maybe test for the other cases too
-			// Todo: some more checks and add block to blockchain
-			// Todo: Check if block has higher VRF and it does not diverge more than 3k/f
-			// Todo: build up the utxo set. PROBABLY DONE
-
-			let mut undo_block = UndoBlock {
-				height: new_block.header.height,
-				original_hash: new_block.header.hash,
-				undo_transactions: vec![],
-			};
-			for tx in &new_block.transactions {
-
-				self.mempool.remove(tx);
-
-				let mut undo_transaction = UndoTransaction {
-					original_tx_id: tx.id,
-					removed_utxos: vec![],
-				};
-
-				for input in &tx.input_list {
-					if let Some(utxos) = self.utxo_set.get(&input.prev_txid) {
-						// This finds the utxo that the input was referring to
-						if let Some(&utxo) = utxos.iter().find(|utxo| utxo.output_index == input.output_index) {
-							undo_transaction.removed_utxos.push(utxo); // Add it to the undo transaction
-						}
-					}
-					// Remove the utxo from the UTXOset
-					self.utxo_set.remove_utxo(&input.prev_txid, input.output_index);
-				}
-				// Add the undo transaction to the undo block
-				undo_block.undo_transactions.push(undo_transaction);
-
-				let mut utxo_list = Vec::new();
-				for (i, output) in tx.output_list.iter().enumerate() {
-					let utxo = UTXO{
-						txid: tx.id,
-						output_index: i,
-						amount: output.amount,
-						recipient_address: output.address,
-					};
-					utxo_list.push(utxo);
-				}
+	/// Adds the block to the chain following the fork choice rule: the longest chain wins and, for chains
+	/// of the same length, the one whose last block has the lowest VRF output.
+	/// Blocks that do not extend the best chain are kept as candidates and, if their fork becomes better than the best chain
+	/// and does not diverge more than `security_parameter` blocks, the chain is reorganized to that fork.
+	pub fn add_block(&mut self, new_block: &Block) -> Result<BlockValidity, BlockError> {
+		let hash = new_block.header.hash;
+		if self.contains_block(&hash) || self.candidate_blocks.contains_key(&hash) {
+			return Err(BlockError::AlreadyKnown);
+		}
+		let last_block = self.get_last_block();
+		let validity = if new_block.header.previous_hash == last_block.header.hash {
+			self.is_block_valid(new_block).and_then(|()| {
+				self.extend_chain(new_block)?;
+				self.prune_candidates();
+				Ok(BlockValidity::Valid)
+			})
+		} else {
+			self.add_candidate_block(new_block, &last_block)
+		};
+		if let Err(err) = &validity {
+			log::debug!("Block {} is not valid: {}", hex::encode(hash), err);
+		}
+		validity
+	}
+	fn add_candidate_block(&mut self, new_block: &Block, last_block: &Block) -> Result<BlockValidity, BlockError> {
+		let k = self.parameters.technical_parameters.security_parameter;
+		let parent = self.get_known_block(&new_block.header.previous_hash).ok_or(BlockError::Orphan)?;
+		if new_block.header.height != parent.header.height + 1 {
+			return Err(BlockError::WrongHeight);
+		}
+		if new_block.header.slot <= parent.header.slot {
+			return Err(BlockError::SlotNotIncreasing);
+		}
+		if self.is_slot_in_future(new_block.header.slot) {
+			return Err(BlockError::FutureSlot);
+		}
+		new_block.is_correct()?;
+		self.check_candidate_leader(new_block)?;
+		self.make_room_for_candidate(new_block)?;
+		self.candidate_blocks.insert(new_block.header.hash, new_block.clone());
 
+		let branch = self.get_fork_branch(&new_block.header.hash);
+		let fork_point_height = branch[0].header.height - 1;
+		if last_block.header.height - fork_point_height > k {
+			// Diverges too much from the best chain, it can never replace it
+			self.candidate_blocks.remove(&new_block.header.hash);
+			return Err(BlockError::TooDeepFork);
+		}
+		if !Self::is_better_than(&new_block.header, &last_block.header) {
+			return Ok(BlockValidity::Fork);
+		}
+		self.switch_to_branch(&branch)?;
+		log::info!("Chain reorganized to block {} at height {}", hex::encode(new_block.header.hash), new_block.header.height);
+		Ok(BlockValidity::BetterThanLastBlock)
+	}
+	/// Returns whether the chain ending in `header` should be preferred over the chain ending in `other`
+	pub(crate) fn is_better_than(header: &BlockHeader, other: &BlockHeader) -> bool {
+		header.height > other.height || (header.height == other.height && header.vrf < other.vrf)
+	}
+	/// Returns a block that is either in the best chain or a candidate
+	fn get_known_block(&self, hash: &[u8; 32]) -> Option<Block> {
+		self.candidate_blocks.get(hash).cloned().or_else(|| self.get_block_by(*hash))
+	}
+	/// Returns the candidate blocks from the fork point with the best chain (excluded) up to the given candidate block, in order
+	fn get_fork_branch(&self, tip: &[u8; 32]) -> Vec<Block> {
+		let mut branch = vec![];
+		let mut current = tip;
+		while let Some(block) = self.candidate_blocks.get(current) {
+			branch.push(block.clone());
+			current = &block.header.previous_hash;
+		}
+		branch.reverse();
+		branch
+	}
+	/// Evicts the lowest candidate that is not an ancestor of the new block, together with its descendants, when the candidates are full.
+	/// Fails if no candidate lower than the new block can be evicted
+	fn make_room_for_candidate(&mut self, new_block: &Block) -> Result<(), BlockError> {
+		let max_candidate_blocks = self.parameters.network_parameters.max_candidate_blocks;
+		if self.candidate_blocks.len() < max_candidate_blocks {
+			return Ok(());
+		}
+		self.prune_candidates();
+		if self.candidate_blocks.len() < max_candidate_blocks {
+			return Ok(());
+		}
+		let ancestors: HashSet<[u8; 32]> = self.get_fork_branch(&new_block.header.previous_hash).iter()
+			.map(|block| block.header.hash)
+			.collect();
+		let lowest = self.candidate_blocks.values()
+			.filter(|block| !ancestors.contains(&block.header.hash))
+			.min_by_key(|block| block.header.height)
+			.map(|block| (block.header.hash, block.header.height));
+		match lowest {
+			Some((hash, height)) if height < new_block.header.height => {
+				self.remove_candidate_and_descendants(&hash);
+				Ok(())
 			}
-			// TODO: Add fees to the fee pool
-
-
-			self.chain.push_block_to_end(&new_block.clone(), &undo_block).expect("Unable to write block to database");
-			return true;
+			_ => Err(BlockError::TooManyCandidates),
 		}
-		false
 	}
-	pub fn is_block_valid(&self, block: &Block) -> bool {
-		// TODO
+	/// Rolls back the best chain to the fork point of the branch and applies the branch.
+	/// The change is journaled first, so that the original chain is restored if some block of the branch turns out to be invalid,
+	/// if the database fails or if the node stops in the middle. The invalid part of the branch is discarded.
+	/// Returns an error if the chain was not switched to the branch
+	fn switch_to_branch(&mut self, branch: &[Block]) -> Result<(), BlockError> {
+		let fork_point = branch[0].header.previous_hash;
+		let original_blocks: Vec<Block> = (branch[0].header.height..=self.get_height())
+			.filter_map(|height| self.get_block_at(height))
+			.collect();
+		let journal = ReorgJournal { fork_point, original_blocks: original_blocks.clone() };
+		if let Err(err) = journal.save(&self.base_directory) {
+			log::error!("Unable to write the journal of the reorganization: {}", err);
+			return Err(BlockError::StorageFailure);
+		}
+		let invalid_block = match self.roll_back_to(&fork_point).and_then(|()| self.apply_valid_blocks(branch)) {
+			Ok(None) => None,
+			Ok(Some(invalid)) => Some(invalid),
+			Err(err) => {
+				log::error!("Unable to reorganize the chain: {}", err);
+				self.recover_after_failed_change();
+				return Err(BlockError::StorageFailure);
+			}
+		};
+		if let Some(invalid) = invalid_block {
+			self.remove_candidate_and_descendants(&branch[invalid].header.hash);
+			self.recover_after_failed_change();
+			return Err(BlockError::ReorganizationFailed);
+		}
+		if let Err(err) = ReorgJournal::remove(&self.base_directory) {
+			log::error!("Unable to remove the journal of the reorganization: {}", err);
+		}
 
+		for block in branch {
+			self.candidate_blocks.remove(&block.header.hash);
+		}
+		for block in original_blocks {
+			self.candidate_blocks.insert(block.header.hash, block);
+		}
+		self.remove_invalid_mempool_transactions();
+		self.prune_candidates();
+		Ok(())
+	}
+	/// Applies the blocks in order while they are valid. Returns the index of the first invalid block, if any
+	fn apply_valid_blocks(&mut self, blocks: &[Block]) -> anyhow::Result<Option<usize>> {
+		for (i, block) in blocks.iter().enumerate() {
+			if let Err(err) = self.is_block_valid(block) {
+				log::debug!("Block {} of the fork is not valid: {}", hex::encode(block.header.hash), err);
+				return Ok(Some(i));
+			}
+			self.apply_block(block)?;
+		}
+		Ok(None)
+	}
+	/// Applies a valid block on top of the best chain. The change is journaled, so that a block that was not completely written
+	/// is undone if the database fails or when the chain is opened again
+	fn extend_chain(&mut self, new_block: &Block) -> Result<(), BlockError> {
+		let journal = ReorgJournal { fork_point: new_block.header.previous_hash, original_blocks: vec![] };
+		let result = journal.save(&self.base_directory)
+			.and_then(|()| self.apply_block(new_block))
+			.and_then(|()| ReorgJournal::remove(&self.base_directory));
+		if let Err(err) = result {
+			log::error!("Unable to add block {} to the chain: {}", hex::encode(new_block.header.hash), err);
+			self.recover_after_failed_change();
+			return Err(BlockError::StorageFailure);
+		}
+		Ok(())
+	}
+	/// Restores the chain recorded in the journal after a failed change. If that fails too, the journal is kept so that
+	/// the chain is restored when it is opened again
+	fn recover_after_failed_change(&mut self) {
+		if let Err(err) = self.recover_interrupted_change() {
+			log::error!("Unable to restore the chain, it will be restored when the node starts again: {}", err);
+		}
+		// The UTxOs of a block that was not completely applied may have been counted twice
+		self.current_stake = self.calculate_stake();
+		self.remove_invalid_mempool_transactions();
+	}
+	/// Brings the best chain back to the original blocks of the change recorded in the journal, if there is one, and removes the journal
+	fn recover_interrupted_change(&mut self) -> anyhow::Result<()> {
+		let Some(journal) = ReorgJournal::load(&self.base_directory)? else {
+			return Ok(());
+		};
+		log::warn!("Restoring the chain after an interrupted change at block {}", hex::encode(journal.fork_point));
+		self.roll_back_to(&journal.fork_point)?;
+		for block in &journal.original_blocks {
+			self.apply_block(block)?;
+		}
+		ReorgJournal::remove(&self.base_directory)
+	}
+	/// Undoes the blocks of the best chain until the given block is the last one
+	fn roll_back_to(&mut self, fork_point: &[u8; 32]) -> anyhow::Result<()> {
+		if !self.contains_block(fork_point) {
+			return Err(anyhow::anyhow!("Block {} is not in the best chain", hex::encode(fork_point)));
+		}
+		while self.get_last_block().header.hash != *fork_point {
+			self.revert_last_block()?;
+		}
+		Ok(())
+	}
+	fn remove_candidate_and_descendants(&mut self, hash: &[u8; 32]) {
+		let mut removed = HashSet::from([*hash]);
+		self.candidate_blocks.remove(hash);
+		loop {
+			let descendants: Vec<[u8; 32]> = self.candidate_blocks.values()
+				.filter(|block| removed.contains(&block.header.previous_hash))
+				.map(|block| block.header.hash)
+				.collect();
+			if descendants.is_empty() {
+				break;
+			}
+			for descendant in descendants {
+				self.candidate_blocks.remove(&descendant);
+				removed.insert(descendant);
+			}
+		}
+	}
+	/// Removes the candidates that are too deep to ever replace the best chain, together with their descendants
+	fn prune_candidates(&mut self) {
+		let k = self.parameters.technical_parameters.security_parameter;
 		let height = self.get_height();
-		let is_block_correct = block.is_correct();
-		if !is_block_correct {
-			return false
+		let too_deep: Vec<[u8; 32]> = self.candidate_blocks.values()
+			.filter(|block| block.header.height + k < height)
+			.map(|block| block.header.hash)
+			.collect();
+		for hash in too_deep {
+			self.remove_candidate_and_descendants(&hash);
 		}
+	}
+	/// Returns how many blocks outside the best chain are kept as candidates
+	#[cfg(test)]
+	pub(crate) fn get_candidate_count(&self) -> usize {
+		self.candidate_blocks.len()
+	}
+	/// Removes from the mempool the transactions that are no longer valid, for example after a reorganization
+	fn remove_invalid_mempool_transactions(&mut self) {
+		let invalid: Vec<Transaction> = self.mempool.get_map().iter()
+			.filter(|tx| tx.is_valid(self).is_err())
+			.cloned()
+			.collect();
+		for tx in &invalid {
+			self.mempool.remove(tx);
+		}
+	}
+	/// Pushes the block at the end of the chain and applies it to the UTxO set. The block must be valid.
+	/// The block and its undo block are written before the UTxO set changes, and undoing a block is idempotent,
+	/// so a block that was not completely applied can always be undone
+	fn apply_block(&mut self, new_block: &Block) -> anyhow::Result<()> {
+		let technical_parameters = self.parameters.technical_parameters;
+		let epoch = technical_parameters.get_epoch(new_block.header.slot);
+		if epoch > technical_parameters.get_epoch(self.get_last_block().header.slot) {
+			// The first block of the epoch, the stake must be saved before the block changes it
+			self.stake_snapshots.insert(epoch, &self.current_stake);
+		}
+		let height = new_block.header.height;
+		let previous_nonces = self.chain.get_nonces();
+		let mut undo_block = UndoBlock {
+			height,
+			original_hash: new_block.header.hash,
+			undo_transactions: vec![],
+			previous_nonces,
+		};
+		// The UTxO lists changed by the block, as they are after each transaction
+		let mut changed_utxos: HashMap<[u8; 32], Vec<UTXO>> = HashMap::new();
+		for (i, tx) in new_block.transactions.iter().enumerate() {
+			let coinbase_height = if i == 0 { Some(height) } else { None }; // The first transaction is the coinbase
+			let mut undo_transaction = UndoTransaction {
+				original_tx_id: tx.id,
+				removed_utxos: vec![],
+				created_utxos: vec![],
+			};
 
-		// TODO: VERIFY THE VRF
+			let inputs = if coinbase_height.is_some() { &[][..] } else { &tx.input_list[..] }; // The coinbase does not spend anything
+			for input in inputs {
+				let utxos = changed_utxos.entry(input.prev_txid)
+					.or_insert_with(|| self.utxo_set.get(&input.prev_txid).unwrap_or_default());
+				// This finds the utxo that the input was referring to
+				if let Some(position) = utxos.iter().position(|utxo| utxo.output_index == input.output_index) {
+					undo_transaction.removed_utxos.push(utxos.remove(position)); // Add it to the undo transaction
+				}
+			}
 
-		// TODO: Check for leader validity
+			let utxo_list = tx.get_created_utxos(coinbase_height);
+			// Make the outputs spendable
+			changed_utxos.insert(tx.id, utxo_list.clone());
+			undo_transaction.created_utxos = utxo_list;
 
-		// TODO: DOING: I was trying to make so that when the block can replace the last one is valid. Problem: Transactions are bitches bc last block interfeers with that and SHIT FUCK
-		for tx in &block.transactions {
-			if !tx.is_valid(self) {
-				return false
+			// Add the undo transaction to the undo block
+			undo_block.undo_transactions.push(undo_transaction);
+		}
+		self.chain.push_block_to_end(new_block, &undo_block, previous_nonces.next(epoch, &new_block.header.vrf))?;
+		for (txid, utxo_list) in changed_utxos {
+			if utxo_list.is_empty() {
+				self.utxo_set.remove(&txid);
+			} else {
+				self.utxo_set.insert(&txid, utxo_list);
 			}
 		}
+		for undo_transaction in &undo_block.undo_transactions {
+			undo_transaction.removed_utxos.iter().for_each(|utxo| self.current_stake.remove_utxo(utxo));
+			undo_transaction.created_utxos.iter().for_each(|utxo| self.current_stake.add_utxo(utxo));
+		}
+		for tx in &new_block.transactions {
+			self.mempool.remove(tx);
+		}
+		Ok(())
+	}
+	/// Checks that the block can be added on top of the best chain: it must be consistent, extend the last block,
+	/// be forged by a leader of its slot and contain a correct coinbase and valid transactions
+	pub fn is_block_valid(&self, block: &Block) -> Result<(), BlockError> {
+		block.is_correct()?;
+		self.check_body_size(&block.header)?;
 
-		if let Some(previous) = self.get_block_at(height - 1) {
-			let is_previous_hash_correct = block.header.previous_hash == previous.header.hash;
-			if !is_previous_hash_correct {
-				return false;
-			}
+		let previous = self.get_last_block();
+		if block.header.previous_hash != previous.header.hash {
+			return Err(BlockError::WrongParent);
+		}
+		if block.header.height != previous.header.height + 1 {
+			return Err(BlockError::WrongHeight);
+		}
+		if block.header.slot <= previous.header.slot {
+			return Err(BlockError::SlotNotIncreasing);
+		}
+		if self.is_slot_in_future(block.header.slot) {
+			return Err(BlockError::FutureSlot);
 		}
 
-		let is_height_correct = block.header.height == self.get_height();
-		if !is_height_correct {
-			return false
+		self.check_leader(block)?;
+
+		// The first transaction must be the coinbase, paying exactly the block reward to the forger
+		let Some((coinbase, transactions)) = block.transactions.split_first() else {
+			return Err(BlockError::InvalidCoinbase);
+		};
+		// Transactions can spend the outputs created by the previous transactions of the block
+		let mut utxos = UtxoView::new(&self.utxo_set);
+		for (i, tx) in transactions.iter().enumerate() {
+			tx.is_valid_in(self, &utxos).map_err(|err| BlockError::InvalidTransaction(i + 1, err))?;
+			utxos.apply(tx, None);
 		}
-		true
-		// TODO: NOW
+		let reward = self.get_block_reward(block.header.height, transactions).ok_or(BlockError::InvalidCoinbase)?;
+		let expected_coinbase = Transaction::coinbase(block.header.height, block.header.forger_address, reward);
+		if *coinbase != expected_coinbase {
+			return Err(BlockError::InvalidCoinbase);
+		}
+		Ok(())
+	}
+	/// Checks that the forger of the block was a leader of its slot, with the epoch nonce of the branch of its parent
+	/// and the stake distribution of its epoch in the best chain
+	fn check_leader(&self, block: &Block) -> Result<(), BlockError> {
+		let epoch = self.parameters.technical_parameters.get_epoch(block.header.slot);
+		let epoch_nonce = self.get_branch_epoch_nonce(&block.header)?;
+		let stake_distribution = self.get_stake_distribution(epoch);
+		let forger_stake = stake_distribution.get_stake(&block.header.forger_vrf_public_key);
+		block.verify_vrf(self.parameters.technical_parameters.active_slot_coefficient, epoch_nonce, forger_stake, stake_distribution.get_total_stake())
+	}
+	/// Checks the leader proof of a block that does not extend the best chain. The stake distribution of the best chain is only
+	/// the one of the branch of the block for the epoch of the fork point, as the branch may have moved stake since then.
+	/// In later epochs only the VRF proof is checked here, and the stake is checked when switching to the branch
+	fn check_candidate_leader(&self, block: &Block) -> Result<(), BlockError> {
+		let technical_parameters = self.parameters.technical_parameters;
+		let previous_hash = block.header.previous_hash;
+		let fork_point = self.get_fork_branch(&previous_hash).first().map_or(previous_hash, |first| first.header.previous_hash);
+		let fork_point = self.get_block_by(fork_point).ok_or(BlockError::Orphan)?;
+		if technical_parameters.get_epoch(block.header.slot) == technical_parameters.get_epoch(fork_point.header.slot) {
+			return self.check_leader(block);
+		}
+		let epoch_nonce = self.get_branch_epoch_nonce(&block.header)?;
+		if !block.header.verify_vrf_proof(&epoch_nonce) {
+			return Err(BlockError::InvalidVRF);
+		}
+		Ok(())
+	}
+	/// Returns the nonce of the epoch of the header in the branch of its parent
+	fn get_branch_epoch_nonce(&self, header: &BlockHeader) -> Result<[u8; 32], BlockError> {
+		let epoch = self.parameters.technical_parameters.get_epoch(header.slot);
+		let nonces = self.get_nonces_after(&header.previous_hash).ok_or(BlockError::Orphan)?;
+		nonces.get_epoch_nonce(epoch).ok_or(BlockError::UnknownEpochNonce)
 	}
+	/// Checks that the body size committed in the header is not bigger than the maximum block body size
+	fn check_body_size(&self, header: &BlockHeader) -> Result<(), BlockError> {
+		if header.body_size > self.parameters.network_parameters.max_block_body_size as u64 {
+			return Err(BlockError::TooLarge(header.body_size as usize));
+		}
+		Ok(())
+	}
+	/// Returns whether the slot has not started yet according to the clock of the node, allowing for the maximum clock drift.
+	/// Without clock no slot is in the future
+	pub fn is_slot_in_future(&self, slot: u64) -> bool {
+		self.current_slot.as_ref().is_some_and(|current_slot| {
+			self.parameters.technical_parameters.is_slot_in_future(slot, current_slot.load(Ordering::Relaxed))
+		})
+	}
+	/// Sets the clock used to reject blocks from slots that have not started yet
+	pub fn set_current_slot(&mut self, current_slot: Arc<AtomicU64>) {
+		self.current_slot = Some(current_slot);
+	}
+	/// Reverts the last block of the chain, leaving the UTxO set and the chain as they were before applying it.
+	/// The transactions of the block are put back into the mempool.
+	/// Returns whether the block was undone. Only the last block of the chain can be undone.
 	pub fn undo_block(&mut self, block: &Block) -> bool {
-		if self.get_last_block().header.hash == block.header.hash {
-			if let Ok(Some(undo_block)) = self.chain.get_undo_block(&block.header.hash) {
-				let transactions = &block.transactions;
-				for t in transactions {
-					self.mempool.insert(t);
-				}
-				for undo_txs in undo_block.undo_transactions {
-					self.utxo_set.insert(&undo_txs.original_tx_id, undo_txs.removed_utxos);
-					self.utxo_set.remove(&undo_txs.original_tx_id);
-				}
+		if self.get_last_block().header.hash != block.header.hash {
+			return false;
+		}
+		if let Err(err) = self.revert_last_block() {
+			log::error!("Unable to undo block {}: {}", hex::encode(block.header.hash), err);
+			return false;
+		}
+		true
+	}
+	/// Reverts the last block of the chain and returns it. Each step can be repeated, so a revert that was interrupted can be done again
+	fn revert_last_block(&mut self) -> anyhow::Result<Block> {
+		let block = self.get_last_block();
+		let hash = block.header.hash;
+		let parent = self.get_block_by(block.header.previous_hash)
+			.ok_or_else(|| anyhow::anyhow!("The genesis block can not be undone"))?;
+		let undo_block = self.chain.get_undo_block(&hash)?
+			.ok_or_else(|| anyhow::anyhow!("Unable to find the undo block of block {}", hex::encode(hash)))?;
+		// Transactions are reverted in reverse order, as a transaction may spend the outputs of a previous one in the same block
+		for undo_txs in undo_block.undo_transactions.into_iter().rev() {
+			for utxo in undo_txs.created_utxos {
+				self.utxo_set.remove_utxo(&utxo.txid, utxo.output_index); // Remove the outputs created by the transaction
+				self.current_stake.remove_utxo(&utxo);
+			}
+			for utxo in undo_txs.removed_utxos {
+				self.utxo_set.restore_utxo(utxo);
+				self.current_stake.add_utxo(&utxo);
+			}
+		}
+		let technical_parameters = self.parameters.technical_parameters;
+		let epoch = technical_parameters.get_epoch(block.header.slot);
+		if epoch > technical_parameters.get_epoch(parent.header.slot) {
+			self.stake_snapshots.remove(epoch); // The epoch has no blocks anymore
+		}
+		self.chain.set_nonces(undo_block.previous_nonces);
+		if self.chain.pop_block()?.is_none() {
+			return Err(anyhow::anyhow!("Unable to remove block {} from the chain", hex::encode(hash)));
+		}
+		for tx in block.transactions.iter().skip(1) { // The coinbase is only valid in its block
+			self.mempool.insert(tx);
+		}
+		Ok(block)
+	}
+	/// Returns the fee that a signed transaction with the given amount of inputs and outputs should pay to be accepted
+	pub fn estimate_fee(&self, input_count: usize, output_count: usize) -> u64 {
+		self.parameters.economic_parameters.minimum_fee(Transaction::estimate_size(input_count, output_count))
+	}
+	/// Returns the amount that the forger of a block at the given height with the given transactions (coinbase excluded) receives.
+	/// This is the block subsidy plus the fees of all the transactions, which can spend the outputs of the previous ones.
+	/// Returns None if the reward does not fit in 64 bits
+	pub fn get_block_reward(&self, height: usize, transactions: &[Transaction]) -> Option<u64> {
+		let mut reward = self.parameters.economic_parameters.block_subsidy(height);
+		let mut utxos = UtxoView::new(&self.utxo_set);
+		for tx in transactions {
+			reward = reward.checked_add(tx.get_fee_in(&utxos))?;
+			utxos.apply(tx, None);
+		}
+		Some(reward)
+	}
+	/// Returns the transactions of a new block at the given height on top of the best chain: the coinbase paying to the forger,
+	/// followed by the mempool transactions that pay the most per byte and fit in the body of the block.
+	/// Returns None if the reward does not fit in 64 bits
+	pub fn get_block_transactions(&self, height: usize, forger_address: P2PKHAddress) -> Option<Vec<Transaction>> {
+		let max_body_size = self.parameters.network_parameters.max_block_body_size;
+		// The transactions are stored after the length of the list, and the coinbase takes the most room with the highest reward
+		let length_prefix_size = serialize_bincode(&(max_body_size as u64)).map(|data| data.len()).unwrap_or(usize::MAX);
+		let mut body_size = length_prefix_size.saturating_add(Transaction::coinbase(height, forger_address, u64::MAX).size());
+
+		let mut candidates: Vec<(Transaction, usize, u64)> = self.mempool.get_map().iter()
+			.filter(|tx| tx.is_valid(self).is_ok())
+			.map(|tx| (tx.clone(), tx.size(), tx.get_fee(self)))
+			.collect();
+		candidates.sort_by_key(|(_, size, fee)| std::cmp::Reverse(fee / (*size).max(1) as u64));
+
+		let mut transactions = vec![];
+		let mut spent_inputs = HashSet::new();
+		for (tx, size, _) in candidates {
+			let is_conflicting = tx.input_list.iter().any(|input| spent_inputs.contains(&(input.prev_txid, input.output_index)));
+			if is_conflicting || body_size.saturating_add(size) > max_body_size {
+				continue;
 			}
+			body_size += size;
+			spent_inputs.extend(tx.input_list.iter().map(|input| (input.prev_txid, input.output_index)));
+			transactions.push(tx);
 		}
-		false
+		let reward = self.get_block_reward(height, &transactions)?;
+		transactions.insert(0, Transaction::coinbase(height, forger_address, reward));
+		Some(transactions)
 	}
 }
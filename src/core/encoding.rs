@@ -0,0 +1,105 @@
+//! Canonical binary encoding of the consensus objects, used for everything that is hashed: transaction ids, block hashes and signature hashes.
+//! It does not depend on serde, so that it never changes when the storage or the wire format changes.
+//!
+//! - Integers are big-endian with a fixed width: `u8`, `u16`, `u32` and `u64`. Heights and indexes (`usize`) are encoded as `u64`
+//! - Fixed-size byte arrays (hashes, addresses, VRF keys and proofs) are written as they are
+//! - Variable-length byte strings are prefixed with their length as `u64`
+//! - Lists are prefixed with their amount of elements as `u64`
+//! - Every hash is prefixed with a domain tag, encoded as a byte string, so that hashes of different objects can never collide
+
+use crate::core::block::BlockHeader;
+use crate::core::utxo::{Input, Output};
+use crate::core::utxo::transaction::Transaction;
+use crate::crypto::hash::hash;
+
+/// Domain tag of transaction ids
+pub const TXID_DOMAIN: &[u8] = b"TNS/txid";
+/// Domain tag of block hashes
+pub const BLOCK_HASH_DOMAIN: &[u8] = b"TNS/block";
+/// Domain tag of witness hashes, the hashes of the signed transactions
+pub const WITNESS_DOMAIN: &[u8] = b"TNS/witness";
+
+pub trait CanonicalEncode {
+	/// Appends the canonical encoding of the object to the data
+	fn encode(&self, data: &mut Vec<u8>);
+	fn to_canonical_bytes(&self) -> Vec<u8> {
+		let mut data = vec![];
+		self.encode(&mut data);
+		data
+	}
+}
+
+pub fn write_u64(data: &mut Vec<u8>, value: u64) {
+	data.extend_from_slice(&value.to_be_bytes());
+}
+/// Writes the bytes prefixed by their length
+pub fn write_bytes(data: &mut Vec<u8>, bytes: &[u8]) {
+	write_u64(data, bytes.len() as u64);
+	data.extend_from_slice(bytes);
+}
+/// Writes the elements prefixed by their amount
+pub fn write_list<T: CanonicalEncode>(data: &mut Vec<u8>, list: &[T]) {
+	write_u64(data, list.len() as u64);
+	for element in list {
+		element.encode(data);
+	}
+}
+/// Hashes the canonical encoding of the object prefixed by the domain tag
+pub fn hash_with_domain<T: CanonicalEncode>(domain: &[u8], object: &T) -> [u8; 32] {
+	let mut data = vec![];
+	write_bytes(&mut data, domain);
+	object.encode(&mut data);
+	hash(&data)
+}
+
+impl CanonicalEncode for Output {
+	fn encode(&self, data: &mut Vec<u8>) {
+		write_u64(data, self.amount);
+		data.extend_from_slice(&self.address.address);
+	}
+}
+/// The signature and the signature hash type are not encoded, so that the id of a transaction does not change when it is signed
+impl CanonicalEncode for Input {
+	fn encode(&self, data: &mut Vec<u8>) {
+		data.extend_from_slice(&self.prev_txid);
+		write_u64(data, self.output_index as u64);
+		write_bytes(data, &self.public_key);
+	}
+}
+/// The id is not encoded, it is the hash of this encoding
+impl CanonicalEncode for Transaction {
+	fn encode(&self, data: &mut Vec<u8>) {
+		data.extend_from_slice(&self.extra_entropy.to_be_bytes());
+		write_list(data, &self.input_list);
+		write_list(data, &self.output_list);
+	}
+}
+/// A transaction together with the signatures and signature hash types of its inputs.
+/// Its hash commits to everything a transaction id leaves out
+pub struct Witness<'a>(pub &'a Transaction);
+impl CanonicalEncode for Witness<'_> {
+	fn encode(&self, data: &mut Vec<u8>) {
+		self.0.encode(data);
+		for input in &self.0.input_list {
+			write_bytes(data, &input.signature);
+			data.push(input.sighash_type.to_byte());
+		}
+	}
+}
+/// The hash is not encoded, it is the hash of this encoding
+impl CanonicalEncode for BlockHeader {
+	fn encode(&self, data: &mut Vec<u8>) {
+		data.extend_from_slice(&self.version.to_be_bytes());
+		data.extend_from_slice(&self.protocol_version.to_be_bytes());
+		data.extend_from_slice(&self.previous_hash);
+		data.extend_from_slice(&self.merkle_root);
+		data.extend_from_slice(&self.witness_root);
+		write_u64(data, self.body_size);
+		write_u64(data, self.slot);
+		write_u64(data, self.height as u64);
+		data.extend_from_slice(&self.vrf);
+		data.extend_from_slice(&self.vrf_proof);
+		data.extend_from_slice(&self.forger_address.address);
+		data.extend_from_slice(&self.forger_vrf_public_key);
+	}
+}
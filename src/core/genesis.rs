@@ -0,0 +1,148 @@
+use std::fs::read_to_string;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::core::address::P2PKHAddress;
+use crate::core::block::{Block, BLOCK_HEADER_VERSION, BlockHeader};
+use crate::core::Hashable;
+use crate::core::parameters::Parameters;
+use crate::core::utxo::{Output, UTXO};
+use crate::core::utxo::transaction::Transaction;
+
+/// The unix time in seconds of the first slot in the main network
+pub(crate) const STARTING_SLOT_SECOND: u64 = 0; // TODO: AT THE END CHANGE THIS NUMBER FOR THE EPOCH SECOND OF THE TIME THE CRYPTO IS RELEASED
+/// The seed of the first epoch nonce in the main network
+// TODO: Choose extra entropy better
+const EPOCH_NONCE_SEED: [u8; 32] = [60, 92, 162, 110, 82, 120, 10, 250, 102, 233, 226, 182, 114, 155, 80, 178, 35, 57, 107, 9, 122, 187, 253, 38, 160, 225, 171, 15, 110, 230, 47, 21];
+
+/// Describes how a chain starts. It is loaded from a JSON file so that private networks with funded addresses can be created
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct GenesisSpec {
+	/// The unix time in seconds at which the slot 0 starts
+	pub start_time: u64,
+	/// The seed of the first epoch nonce, it is also the previous hash of the genesis block
+	#[serde(with = "hex_array")]
+	pub epoch_nonce_seed: [u8; 32],
+	/// Coins that are spendable from the start
+	#[serde(default)]
+	pub allocations: Vec<GenesisAllocation>,
+	/// The initial stakeholders, their stake is also allocated to their address
+	#[serde(default)]
+	pub stake_distribution: Vec<GenesisStakeholder>,
+	#[serde(default)]
+	pub parameters: Parameters,
+}
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct GenesisAllocation {
+	#[serde(with = "address_string")]
+	pub address: P2PKHAddress,
+	pub amount: u64,
+}
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct GenesisStakeholder {
+	#[serde(with = "address_string")]
+	pub address: P2PKHAddress,
+	/// The key with which the stakeholder runs the lottery
+	#[serde(with = "hex_array")]
+	pub vrf_public_key: [u8; 32],
+	pub stake: u64,
+}
+impl GenesisSpec {
+	/// Loads the genesis specification from a JSON file
+	pub fn load(path: &Path) -> anyhow::Result<Self> {
+		let json = read_to_string(path)?;
+		Ok(serde_json::from_str(&json)?)
+	}
+	/// Returns the outputs created at genesis: the allocations followed by the stakes
+	pub fn get_outputs(&self) -> Vec<Output> {
+		let allocations = self.allocations.iter().map(|allocation| Output { amount: allocation.amount, address: allocation.address });
+		let stakes = self.stake_distribution.iter().map(|stakeholder| Output { amount: stakeholder.stake, address: stakeholder.address });
+		allocations.chain(stakes).collect()
+	}
+	/// Returns the transaction that creates the genesis outputs, or None if there are no outputs
+	pub fn get_transaction(&self) -> Option<Transaction> {
+		let outputs = self.get_outputs();
+		if outputs.is_empty() {
+			return None;
+		}
+		Some(Transaction::create_transaction(vec![], outputs, 0))
+	}
+	/// Returns the UTxOs that exist at genesis
+	pub fn get_utxos(&self) -> Vec<UTXO> {
+		let Some(tx) = self.get_transaction() else {
+			return vec![];
+		};
+		tx.output_list.iter().enumerate().map(|(i, output)| UTXO {
+			txid: tx.id,
+			output_index: i,
+			amount: output.amount,
+			recipient_address: output.address,
+			coinbase_height: None,
+		}).collect()
+	}
+	pub fn get_block(&self) -> Block {
+		let header = BlockHeader {
+			hash: [0u8; 32],
+			version: BLOCK_HEADER_VERSION,
+			protocol_version: 0,
+			height: 0,
+			previous_hash: self.epoch_nonce_seed,
+			slot: 0u64,
+			merkle_root: [0u8; 32],
+			witness_root: [0u8; 32],
+			body_size: 0,
+			vrf: [0u8; 32],
+			vrf_proof: [0u8; 96],
+			forger_vrf_public_key: [0u8; 32],
+			forger_address: P2PKHAddress::null(),
+		};
+		let mut block = Block {
+			transactions: self.get_transaction().into_iter().collect(),
+			header,
+		};
+		block.update_hash();
+		block
+	}
+}
+impl Default for GenesisSpec {
+	fn default() -> Self {
+		Self {
+			start_time: STARTING_SLOT_SECOND,
+			epoch_nonce_seed: EPOCH_NONCE_SEED,
+			allocations: vec![],
+			stake_distribution: vec![],
+			parameters: Parameters::default(),
+		}
+	}
+}
+
+/// Serializes byte arrays as hex strings
+mod hex_array {
+	use serde::{Deserialize, Deserializer, Serializer};
+	use serde::de::Error;
+
+	pub fn serialize<S>(bytes: &[u8; 32], serializer: S) -> Result<S::Ok, S::Error> where S: Serializer {
+		serializer.serialize_str(&hex::encode(bytes))
+	}
+	pub fn deserialize<'de, D>(deserializer: D) -> Result<[u8; 32], D::Error> where D: Deserializer<'de> {
+		let string = String::deserialize(deserializer)?;
+		let bytes = hex::decode(string).map_err(Error::custom)?;
+		bytes.try_into().map_err(|_| Error::custom("Expected 32 bytes"))
+	}
+}
+/// Serializes addresses in their readable form
+mod address_string {
+	use serde::{Deserialize, Deserializer, Serializer};
+	use serde::de::Error;
+
+	use crate::core::address::P2PKHAddress;
+
+	pub fn serialize<S>(address: &P2PKHAddress, serializer: S) -> Result<S::Ok, S::Error> where S: Serializer {
+		serializer.serialize_str(&address.to_string())
+	}
+	pub fn deserialize<'de, D>(deserializer: D) -> Result<P2PKHAddress, D::Error> where D: Deserializer<'de> {
+		let string = String::deserialize(deserializer)?;
+		P2PKHAddress::from_string(string).map_err(|err| Error::custom(format!("Invalid address: {:?}", err)))
+	}
+}
@@ -0,0 +1,48 @@
+use std::fmt::{Display, Formatter};
+
+use serde::{Deserialize, Serialize};
+
+use crate::core::keys::load_or_create_secret;
+use crate::crypto::hash::hash;
+use crate::crypto::public_key::PublicKeyAlgorithm;
+
+/// Identifies a node in the network. It is the hash of the public key of its identity
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub struct NodeId(pub [u8; 32]);
+impl NodeId {
+	pub fn from_public_key(public_key: &[u8]) -> Self {
+		Self(hash(public_key))
+	}
+}
+impl Display for NodeId {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		write!(f, "{}", hex::encode(self.0))
+	}
+}
+
+/// The key pair with which a node proves its identity to its peers. It is not related to the keys that hold its stake
+#[derive(Clone, Deserialize, Serialize)]
+pub struct NodeIdentity {
+	signing_key: Vec<u8>,
+	pub(crate) public_key: Vec<u8>,
+}
+impl NodeIdentity {
+	pub fn random() -> Self {
+		let (signing_key, public_key) = PublicKeyAlgorithm::gen_keypair();
+		Self {
+			signing_key,
+			public_key,
+		}
+	}
+	/// Loads the identity of the node from the given base directory, creating a new random identity if it does not exist.
+	/// The identity must be kept between runs so that the peers recognize the node
+	pub fn load(base_directory: &str) -> Self {
+		load_or_create_secret(&format!("{}/node/identity.json", base_directory), "node identity", Self::random)
+	}
+	pub fn id(&self) -> NodeId {
+		NodeId::from_public_key(&self.public_key)
+	}
+	pub fn sign(&self, data: &[u8]) -> Vec<u8> {
+		PublicKeyAlgorithm::sign(&self.signing_key, data).expect("Invalid node identity key")
+	}
+}
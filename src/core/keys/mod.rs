@@ -1,7 +1,18 @@
+use std::fs::{create_dir_all, OpenOptions};
+use std::io::Write;
+#[cfg(unix)]
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use serde::de::DeserializeOwned;
+
 use crate::core::address::P2PKHAddress;
 use crate::crypto::vrf::keygen;
 
-#[derive(Clone)]
+pub mod identity;
+
+#[derive(Clone, Serialize, Deserialize)]
 pub struct NodeKeyChain {
 	/// Used for receiving the reward. (P2PKHAddress, Signing_key, Verifying_key)
 	pub wallet_key_pair: (P2PKHAddress, Vec<u8>, Vec<u8>),
@@ -16,4 +27,31 @@ impl NodeKeyChain {
 			vrf_key_pair: (vrf_sk.to_bytes(), vrf_pk.to_bytes())
 		}
 	}
-}
\ No newline at end of file
+	/// Loads the keys of the node from the given base directory, creating new random keys if they do not exist.
+	/// The keys must be kept between runs so that the node keeps its stake
+	pub fn load(base_directory: &str) -> Self {
+		load_or_create_secret(&format!("{}/node/keys.json", base_directory), "node keys", Self::random)
+	}
+}
+
+/// Loads the secret stored as JSON in the given path, creating it with `create` if the file does not exist.
+/// The file can only be read and written by its owner, and the permissions of files written by older versions are tightened
+pub(crate) fn load_or_create_secret<T: Serialize + DeserializeOwned>(path: &str, name: &str, create: impl FnOnce() -> T) -> T {
+	if Path::new(path).exists() {
+		#[cfg(unix)]
+		std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600)).unwrap_or_else(|_| panic!("Unable to restrict the permissions of the {} file", name));
+		let data = std::fs::read_to_string(path).unwrap_or_else(|_| panic!("Unable to read {}", name));
+		serde_json::from_str(&data).unwrap_or_else(|_| panic!("Unable to deserialize {}", name))
+	} else {
+		let secret = create();
+		create_dir_all(Path::new(path).parent().expect("Unable to get parent directory")).expect("Unable to create directories");
+		let mut options = OpenOptions::new();
+		options.create_new(true).write(true);
+		#[cfg(unix)]
+		options.mode(0o600);
+		let mut file = options.open(path).unwrap_or_else(|_| panic!("Unable to open {} file", name));
+		let data = serde_json::to_string_pretty(&secret).unwrap_or_else(|_| panic!("Unable to serialize {}", name));
+		file.write_all(data.as_bytes()).unwrap_or_else(|_| panic!("Unable to write {}", name));
+		secret
+	}
+}
@@ -0,0 +1,148 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::core::block::{BlockError, BlockHeader};
+use crate::core::blockchain::{build_block_locator, BlockChain};
+use crate::core::genesis::GenesisSpec;
+use crate::core::parameters::Parameters;
+use crate::crypto::hash::merkle::MerkleProof;
+use crate::data_storage::blockchain_storage::header_database::{HeaderDB, StoredHeader};
+use crate::data_storage::BaseDirectory;
+
+/// The chain of a light client: only the headers of the best chain are stored, without the block bodies.
+/// Headers are checked for their hash, links, slots and VRF proofs against the epoch nonces. Without the UTxO set the stake of the forgers
+/// is not known, so the leader threshold is not checked: the client relies on the longest chain instead. Transactions are verified with merkle proofs.
+#[derive(Clone)]
+pub struct LightChain {
+	headers: HeaderDB,
+	pub(crate) parameters: Parameters,
+	/// The hash of the genesis block, which identifies the chain
+	chain_id: [u8; 32],
+	/// The current slot of the client, shared with its clock
+	current_slot: Option<Arc<AtomicU64>>,
+}
+
+impl LightChain {
+	pub fn init(genesis: &GenesisSpec) -> anyhow::Result<Self> {
+		Self::open(&BaseDirectory::get_base_directory(), genesis)
+	}
+	/// Opens the headers stored in the given base directory, creating them from the genesis specification if they do not exist.
+	/// Fails if the stored headers were created from another genesis
+	pub fn open(base_directory: &str, genesis: &GenesisSpec) -> anyhow::Result<Self> {
+		let genesis_header = genesis.get_block().header;
+		let headers = HeaderDB::open(base_directory, &genesis_header);
+		let chain_id = headers.get_hash_by_height(0).ok_or_else(|| anyhow::anyhow!("The stored headers have no genesis header"))?;
+		if chain_id != genesis_header.hash {
+			return Err(anyhow::anyhow!("The stored headers were created from a different genesis: {} instead of {}", hex::encode(chain_id), hex::encode(genesis_header.hash)));
+		}
+		Ok(Self {
+			headers,
+			parameters: genesis.parameters,
+			chain_id,
+			current_slot: None,
+		})
+	}
+	pub fn get_chain_id(&self) -> [u8; 32] {
+		self.chain_id
+	}
+	pub fn get_height(&self) -> usize {
+		self.headers.get_length() - 1
+	}
+	pub fn get_last_header(&self) -> BlockHeader {
+		self.headers.get_best().expect("Light chain has no headers").header
+	}
+	/// Returns the header of the best chain at the given height
+	pub fn get_header_at(&self, height: usize) -> Option<BlockHeader> {
+		self.headers.get_by_height(height).map(|stored| stored.header)
+	}
+	/// Returns the header with the given hash if it is part of the best chain
+	pub fn get_header(&self, hash: &[u8; 32]) -> Option<BlockHeader> {
+		self.headers.get(hash).map(|stored| stored.header)
+	}
+	pub fn contains_header(&self, hash: &[u8; 32]) -> bool {
+		self.headers.contains(hash)
+	}
+	pub fn get_block_locator(&self) -> Vec<[u8; 32]> {
+		build_block_locator(self.get_height(), |height| self.headers.get_hash_by_height(height))
+	}
+	/// Adds headers received from a peer. They must continue a header of the best chain.
+	/// If they fork from it, the best chain is replaced only if the fork ends in a better header and does not roll back more than `security_parameter` headers.
+	/// Headers that are already known are skipped. Returns the amount of headers added to the best chain
+	pub fn add_headers(&mut self, headers: &[BlockHeader]) -> Result<usize, BlockError> {
+		let new_headers: Vec<&BlockHeader> = headers.iter().skip_while(|header| self.contains_header(&header.hash)).collect();
+		let Some(first) = new_headers.first() else {
+			return Ok(0);
+		};
+		let parent = self.headers.get(&first.previous_hash).ok_or(BlockError::Orphan)?;
+		let rolled_back = self.get_height() - parent.header.height;
+		if rolled_back > self.parameters.technical_parameters.security_parameter {
+			return Err(BlockError::TooDeepFork);
+		}
+		let mut validated: Vec<StoredHeader> = Vec::with_capacity(new_headers.len());
+		let mut previous = parent;
+		for &header in &new_headers {
+			let stored = self.validate_header(&previous, header)?;
+			validated.push(stored.clone());
+			previous = stored;
+		}
+		if rolled_back > 0 && !BlockChain::is_better_than(&previous.header, &self.get_last_header()) {
+			return Ok(0);
+		}
+		let storage_error = |err: anyhow::Error| {
+			log::error!("Unable to store headers: {}", err);
+			BlockError::StorageFailure
+		};
+		for _ in 0..rolled_back {
+			self.headers.pop().map_err(storage_error)?;
+		}
+		for stored in &validated {
+			self.headers.push(stored).map_err(storage_error)?;
+		}
+		Ok(validated.len())
+	}
+	/// Checks that the header can follow its parent and returns it with the nonces after it
+	fn validate_header(&self, parent: &StoredHeader, header: &BlockHeader) -> Result<StoredHeader, BlockError> {
+		header.verify()?;
+		if header.previous_hash != parent.header.hash {
+			return Err(BlockError::WrongParent);
+		}
+		if header.height != parent.header.height + 1 {
+			return Err(BlockError::WrongHeight);
+		}
+		if header.slot <= parent.header.slot {
+			return Err(BlockError::SlotNotIncreasing);
+		}
+		if self.is_slot_in_future(header.slot) {
+			return Err(BlockError::FutureSlot);
+		}
+		if header.body_size > self.parameters.network_parameters.max_block_body_size as u64 {
+			return Err(BlockError::TooLarge(header.body_size as usize));
+		}
+		let epoch = self.parameters.technical_parameters.get_epoch(header.slot);
+		let epoch_nonce = parent.nonces.get_epoch_nonce(epoch).ok_or(BlockError::UnknownEpochNonce)?;
+		if !header.verify_vrf_proof(&epoch_nonce) {
+			return Err(BlockError::InvalidVRF);
+		}
+		Ok(StoredHeader {
+			header: header.clone(),
+			nonces: parent.nonces.next(epoch, &header.vrf),
+		})
+	}
+	/// Checks that the transaction is included in the block with the given hash of the best chain.
+	/// Returns the amount of confirmations of the transaction, or None if the proof is not valid
+	pub fn verify_transaction(&self, txid: &[u8; 32], block_hash: &[u8; 32], proof: &MerkleProof) -> Option<usize> {
+		let header = self.get_header(block_hash)?;
+		header.verify_transaction_proof(txid, proof).then(|| self.get_height() - header.height + 1)
+	}
+	/// Returns whether the slot has not started yet according to the clock of the client, allowing for the maximum clock drift.
+	/// Without clock no slot is in the future
+	pub fn is_slot_in_future(&self, slot: u64) -> bool {
+		self.current_slot.as_ref().is_some_and(|current_slot| {
+			self.parameters.technical_parameters.is_slot_in_future(slot, current_slot.load(Ordering::Relaxed))
+		})
+	}
+	/// Sets the clock used to reject headers from slots that have not started yet
+	pub fn set_current_slot(&mut self, current_slot: Arc<AtomicU64>) {
+		self.current_slot = Some(current_slot);
+	}
+}
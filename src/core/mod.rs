@@ -1,9 +1,8 @@
 use std::io::Read;
 
 use crate::core::block::Block;
+use crate::core::encoding::{hash_with_domain, TXID_DOMAIN};
 use crate::core::utxo::transaction::Transaction;
-use crate::crypto::hash::hash;
-use crate::crypto::hash::merkle::calculate_merkle_root;
 
 pub mod blockchain;
 pub mod block;
@@ -11,6 +10,9 @@ pub mod address;
 pub mod utxo;
 pub mod parameters;
 pub mod keys;
+pub mod genesis;
+pub mod encoding;
+pub mod light_chain;
 
 
 pub trait Hashable {
@@ -21,14 +23,13 @@ impl Hashable for Block {
 	/// IMPORTANT
 	/// CHECK VALIDITY OF DATA BEFORE CALCULATING HASH. HASH DOES NOT CHECK FOR ERRORS IN COHERENCE
 	fn calculate_hash(&self) -> [u8; 32]{
-		let header = &self.header;
-		let merkle_tree = self.calculate_merkle_tree();
-		let str = format!("{}.{}.{}.{}.{}.{}.{}.{}", hex::encode(header.previous_hash), hex::encode(merkle_tree), header.slot, header.height, hex::encode(header.vrf), hex::encode(header.vrf_proof), header.forger_address, hex::encode(header.forger_vrf_public_key));
-		hash(str.as_bytes()).as_slice().try_into().expect("Unable to convert hash to byte array")
+		self.header.calculate_hash()
 	}
+	/// Updates the merkle root, the witness root and the body size of the header and then the hash
 	fn update_hash(&mut self) {
-		let merkle_tree = self.calculate_merkle_tree();
-		self.header.merkle_root = merkle_tree;
+		self.header.merkle_root = self.calculate_merkle_tree();
+		self.header.witness_root = self.calculate_witness_root();
+		self.header.body_size = self.calculate_body_size();
 		self.header.hash = self.calculate_hash();
 	}
 }
@@ -36,14 +37,7 @@ impl Hashable for Transaction {
 	/// IMPORTANT
 	/// CHECK VALIDITY OF DATA BEFORE CALCULATING HASH. HASH DOES NOT CHECK FOR ERRORS IN COHERENCE
 	fn calculate_hash(&self) -> [u8; 32] {
-		let input_hash_list = self.input_list.iter().map(|x|x.calculate_hash()).collect();
-		let inputs = hex::encode(calculate_merkle_root(input_hash_list));
-
-		let output_hash_list = self.output_list.iter().map(|x|x.calculate_hash()).collect();
-		let outputs = hex::encode(calculate_merkle_root(output_hash_list));
-
-		let str = format!("{}.{}.{}", inputs, outputs, self.extra_entropy);
-		hash(str.as_bytes())
+		hash_with_domain(TXID_DOMAIN, self)
 	}
 	fn update_hash(&mut self) {
 		self.id = self.calculate_hash();
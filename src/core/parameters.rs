@@ -1,64 +1,105 @@
+use serde::{Deserialize, Serialize};
+
 pub const COIN_NAME: &str = "TENSOR";
 pub const COIN_NAME_ABBREVIATION: &str = "TNS";
 
-#[derive(Clone, Copy, Default)]
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Parameters {
 	pub(crate) network_parameters: NetworkParameters,
 	pub(crate) technical_parameters: TechnicalParameters,
 	pub(crate) economic_parameters: EconomicParameters,
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(default)]
 pub struct TechnicalParameters {
 	pub(crate) slot_duration: u32,
 	// Time in milliseconds
 	pub(crate) epoch_duration: u32,
 	// Time in slot times
 	pub(crate) active_slot_coefficient: f32, // The possibility of a slot having a leader
+	pub(crate) security_parameter: usize, // The maximum amount of blocks that can be rolled back (k)
+	pub(crate) max_clock_drift: u64, // The amount of slots that the clocks of two nodes may differ
 }
 
+impl TechnicalParameters {
+	/// Returns the epoch that the given slot belongs to
+	pub fn get_epoch(&self, slot: u64) -> u64 {
+		slot / self.epoch_duration as u64
+	}
+	/// Returns whether the slot starts after the current slot, allowing for the maximum clock drift
+	pub fn is_slot_in_future(&self, slot: u64, current_slot: u64) -> bool {
+		slot > current_slot.saturating_add(self.max_clock_drift)
+	}
+}
 impl Default for TechnicalParameters {
 	fn default() -> Self {
 		TechnicalParameters { // TODO: Fix this default parameters
 			slot_duration: 1000, // 1 Second
 			epoch_duration: 86400, // 1 Day
 			active_slot_coefficient: 0.05, // 5%
+			security_parameter: 2160,
+			max_clock_drift: 1,
 		}
 	}
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
 pub struct NetworkParameters {
 	pub(crate) max_block_body_size: usize,
 	// Max block body size
 	pub(crate) max_block_header_size: usize,
 	// Max block header size
 	pub(crate) max_tx_size: usize, // Max transaction size
+	pub(crate) max_candidate_blocks: usize, // Max blocks outside the best chain kept as candidates
 }
 
 impl Default for NetworkParameters {
 	fn default() -> Self {
 		// TODO: Fix this default parameters
-		// TODO: Check for this parameters when receiving
 		NetworkParameters {
 			max_block_body_size: 2usize.pow(16), // 65536B -> 64Kib
 			max_block_header_size: 2usize.pow(10), // 1024B -> 1KiB
 			max_tx_size: 2usize.pow(10), // 512B
+			max_candidate_blocks: 1024,
 		}
 	}
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
 pub struct EconomicParameters {
 	pub(crate) fee_per_tx_byte: u32,
+	pub(crate) initial_block_subsidy: u64, // The amount of new coins created in each block at the start
+	pub(crate) subsidy_halving_interval: usize, // The amount of blocks after which the block subsidy is halved
+	pub(crate) coinbase_maturity: usize, // The amount of blocks until the outputs of a coinbase transaction can be spent
+}
+impl EconomicParameters {
+	/// Returns the minimum fee that a transaction of the given size must pay
+	pub fn minimum_fee(&self, tx_size: usize) -> u64 {
+		tx_size as u64 * self.fee_per_tx_byte as u64
+	}
+	/// Returns the amount of new coins that the forger of the block at the given height receives
+	pub fn block_subsidy(&self, height: usize) -> u64 {
+		let halvings = height / self.subsidy_halving_interval;
+		if halvings >= u64::BITS as usize {
+			0
+		} else {
+			self.initial_block_subsidy >> halvings
+		}
+	}
 }
 
 impl Default for EconomicParameters {
 	fn default() -> Self {
 		// TODO: Fix this default parameters
-		// TODO: Actually use this parameter
 		Self {
 			fee_per_tx_byte: 10,
+			initial_block_subsidy: 50_000_000,
+			subsidy_halving_interval: 6_307_200, // About 4 years with 5% of active slots of 1 second
+			coinbase_maturity: 2160, // Same as the security parameter, so that a coinbase can not be spent before it is final
 		}
 	}
 }
\ No newline at end of file
@@ -1,11 +1,16 @@
 use serde::{Deserialize, Serialize};
 
 use crate::core::address::P2PKHAddress;
+use crate::core::encoding::CanonicalEncode;
 use crate::core::blockchain::BlockChain;
+use crate::core::utxo::sighash::SigHashType;
+use crate::core::utxo::transaction::TxError;
+use crate::core::utxo::view::UtxoView;
 use crate::crypto::hash::hash;
-use crate::crypto::public_key::PublicKeyAlgorithm;
 
 pub mod transaction;
+pub mod sighash;
+pub mod view;
 
 #[derive(Clone, Debug, Eq, Hash, Serialize, Deserialize, PartialEq)]
 pub struct Input {
@@ -13,6 +18,9 @@ pub struct Input {
 	pub output_index: usize,
 	pub signature: Vec<u8>,
 	pub public_key: Vec<u8>,
+	/// The parts of the transaction that the signature commits to
+	#[serde(default)]
+	pub sighash_type: SigHashType,
 }
 
 #[derive(Clone, Copy, Debug, Eq, Hash, Serialize, Deserialize, PartialEq)]
@@ -26,38 +34,35 @@ pub struct UTXO {
 	pub output_index: usize,
 	pub amount: u64,
 	pub recipient_address: P2PKHAddress,
+	/// The height of the block that created the UTxO, if it comes from a coinbase transaction
+	#[serde(default)]
+	pub coinbase_height: Option<usize>,
 }
 impl Output {
 	pub fn calculate_hash(&self) -> [u8; 32] {
-		let str = format!("{}.{}", hex::encode(self.address.address), self.amount);
-		hash(str.as_bytes())
+		hash(&self.to_canonical_bytes())
 	}
 }
 impl Input {
 	pub fn calculate_hash(&self) -> [u8; 32] {
-		let str = format!("{}.{}.{}", hex::encode(self.prev_txid), self.output_index, hex::encode(&self.public_key));
-		hash(str.as_bytes())
+		hash(&self.to_canonical_bytes())
 	}
-	pub fn verify_signature(&self) -> bool {
-		// TODO: Check code
-		let hash = self.calculate_hash();
-		let signature =  &self.signature;
-		if PublicKeyAlgorithm::verify(&self.public_key, &hash, signature).is_ok() {
-			return true;
-		}
-		false
-	}
-	pub fn validate(&self, blockchain: &BlockChain) -> bool {
-		if let Some(utxos) = blockchain.get_utxo_list(&self.prev_txid) {
-			if let Some(utxo) = utxos.get(self.output_index) {
-				if utxo.txid == self.prev_txid && utxo.output_index == self.output_index {
-					let derived_key = P2PKHAddress::from(&self.public_key).address;
-					let is_address_correct = derived_key == utxo.recipient_address.address;
-					let is_signature_valid = self.verify_signature();
-					return is_address_correct && is_signature_valid;
-				}
+	/// Checks that the input spends an output of the given UTxO view that is mature and owned by its public key. Returns the spent output
+	pub fn validate(&self, blockchain: &BlockChain, utxos: &UtxoView) -> Result<UTXO, TxError> {
+		let Some(utxo) = utxos.get_utxo(&self.prev_txid, self.output_index) else {
+			return Err(TxError::MissingInput(self.prev_txid, self.output_index));
+		};
+		if let Some(coinbase_height) = utxo.coinbase_height {
+			// The input would be included at the next height at the earliest
+			let is_mature = blockchain.get_height() + 1 >= coinbase_height + blockchain.parameters.economic_parameters.coinbase_maturity;
+			if !is_mature {
+				return Err(TxError::ImmatureCoinbase(self.prev_txid, self.output_index));
 			}
 		}
-		false
+		let derived_key = P2PKHAddress::from(&self.public_key).address;
+		if derived_key != utxo.recipient_address.address {
+			return Err(TxError::WrongOwner(self.prev_txid, self.output_index));
+		}
+		Ok(utxo)
 	}
 }
\ No newline at end of file
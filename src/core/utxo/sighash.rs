@@ -0,0 +1,80 @@
+use serde::{Deserialize, Serialize};
+
+use crate::core::encoding::{write_bytes, write_list, write_u64};
+use crate::core::utxo::{Output, UTXO};
+use crate::core::utxo::transaction::Transaction;
+use crate::crypto::hash::hash;
+
+/// Prefix of every signature hash, so that it can not be confused with any other hash
+const SIGHASH_DOMAIN: &[u8] = b"TNS/sighash";
+
+/// Defines which parts of the transaction are signed by an input
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, Serialize, Deserialize, PartialEq)]
+pub enum SigHashType {
+	/// Signs all the inputs and all the outputs
+	#[default]
+	All,
+	/// Signs all the inputs and only the output with the same index as the input
+	Single,
+	/// Signs only this input and all the outputs, so anyone can add more inputs
+	AllAnyoneCanPay,
+	/// Signs only this input and only the output with the same index as the input
+	SingleAnyoneCanPay,
+}
+impl SigHashType {
+	pub fn to_byte(&self) -> u8 {
+		match self {
+			SigHashType::All => 0x01,
+			SigHashType::Single => 0x03,
+			SigHashType::AllAnyoneCanPay => 0x81,
+			SigHashType::SingleAnyoneCanPay => 0x83,
+		}
+	}
+	pub fn is_single(&self) -> bool {
+		matches!(self, SigHashType::Single | SigHashType::SingleAnyoneCanPay)
+	}
+	pub fn is_anyone_can_pay(&self) -> bool {
+		matches!(self, SigHashType::AllAnyoneCanPay | SigHashType::SingleAnyoneCanPay)
+	}
+}
+
+/// Calculates the hash that the input at `input_index` signs.
+/// `spent_utxos` are the UTxOs spent by every input, in the same order as the inputs, so that the signature commits to the amounts being spent.
+/// `chain_id` identifies the chain, so that a signature can not be replayed in another network.
+/// Returns None if the input does not exist, if there is not a spent UTxO for every input, or if the type is single and there is no output with the same index
+pub fn signature_hash(tx: &Transaction, input_index: usize, spent_utxos: &[UTXO], sighash_type: SigHashType, chain_id: &[u8; 32]) -> Option<[u8; 32]> {
+	let signed_input = tx.input_list.get(input_index)?;
+	if spent_utxos.len() != tx.input_list.len() {
+		return None;
+	}
+	let mut data = vec![];
+	write_bytes(&mut data, SIGHASH_DOMAIN);
+	data.extend_from_slice(chain_id);
+	data.push(sighash_type.to_byte());
+	data.extend_from_slice(&tx.extra_entropy.to_be_bytes());
+
+	let committed_inputs: Vec<usize> = if sighash_type.is_anyone_can_pay() {
+		vec![input_index]
+	} else {
+		(0..tx.input_list.len()).collect()
+	};
+	write_u64(&mut data, committed_inputs.len() as u64);
+	for i in committed_inputs {
+		let (input, utxo) = (&tx.input_list[i], &spent_utxos[i]);
+		data.extend_from_slice(&input.prev_txid);
+		write_u64(&mut data, input.output_index as u64);
+		write_u64(&mut data, utxo.amount);
+		data.extend_from_slice(&utxo.recipient_address.address);
+	}
+
+	write_u64(&mut data, input_index as u64);
+	write_bytes(&mut data, &signed_input.public_key);
+
+	let committed_outputs: &[Output] = if sighash_type.is_single() {
+		std::slice::from_ref(tx.output_list.get(input_index)?)
+	} else {
+		&tx.output_list
+	};
+	write_list(&mut data, committed_outputs);
+	Some(hash(&data))
+}
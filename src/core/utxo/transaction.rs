@@ -1,11 +1,20 @@
 use std::collections::HashSet;
+use std::fmt::{Display, Formatter};
 
 use serde::{Deserialize, Serialize};
 
+use crate::core::address::P2PKHAddress;
 use crate::core::blockchain::BlockChain;
+use crate::core::encoding::{hash_with_domain, Witness, WITNESS_DOMAIN};
 use crate::core::Hashable;
-use crate::core::utxo::{Input, Output};
-use crate::crypto::public_key::{PublicKeyAlgorithm, PublicKeyError};
+use crate::core::utxo::{Input, Output, UTXO};
+use crate::core::utxo::sighash::{signature_hash, SigHashType};
+use crate::core::utxo::view::UtxoView;
+use crate::crypto::public_key::{PUBLIC_KEY_SIZE, PublicKeyAlgorithm, PublicKeyError, SIGNATURE_SIZE};
+use crate::network::standard::serialize_bincode;
+
+/// Transactions must have fewer inputs and fewer outputs than this
+pub const MAX_INPUTS_OR_OUTPUTS: usize = 128;
 
 #[derive(Clone, Debug, Eq, Hash, Serialize, Deserialize, PartialEq)]
 pub struct Transaction {
@@ -15,61 +24,190 @@ pub struct Transaction {
 	pub output_list: Vec<Output>,
 }
 
+/// Why a transaction was rejected
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum TxError {
+	/// Two inputs spend the same output
+	DuplicateInput,
+	/// The transaction has too many inputs or outputs, or is bigger than the maximum transaction size. Contains the size in bytes
+	Oversized(usize),
+	/// The spent output (transaction id and output index) does not exist or was already spent
+	MissingInput([u8; 32], usize),
+	/// The spent output (transaction id and output index) comes from a coinbase that is not mature yet
+	ImmatureCoinbase([u8; 32], usize),
+	/// The public key of the input does not own the spent output (transaction id and output index)
+	WrongOwner([u8; 32], usize),
+	/// The signature of the input at the given index is not valid
+	InvalidSignature(usize),
+	/// The outputs spend more than the inputs
+	ValueMismatch { input_amount: u64, output_amount: u64 },
+	/// The fee is lower than the minimum fee for the size of the transaction
+	InsufficientFee { fee: u64, minimum_fee: u64 },
+	/// Coinbase transactions can only be the first transaction of a block
+	UnexpectedCoinbase,
+	/// The sum of the amounts of the inputs or of the outputs does not fit in 64 bits
+	AmountOverflow,
+	/// The transaction is already in the mempool
+	AlreadyKnown,
+}
+impl Display for TxError {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		match self {
+			TxError::DuplicateInput => write!(f, "Two inputs spend the same output"),
+			TxError::Oversized(size) => write!(f, "The transaction is too big ({} bytes)", size),
+			TxError::MissingInput(txid, index) => write!(f, "The output {}:{} does not exist or was already spent", hex::encode(txid), index),
+			TxError::ImmatureCoinbase(txid, index) => write!(f, "The coinbase output {}:{} is not mature yet", hex::encode(txid), index),
+			TxError::WrongOwner(txid, index) => write!(f, "The output {}:{} is not owned by the public key of the input", hex::encode(txid), index),
+			TxError::InvalidSignature(index) => write!(f, "The signature of the input {} is not valid", index),
+			TxError::ValueMismatch { input_amount, output_amount } => write!(f, "The outputs spend {} but the inputs only have {}", output_amount, input_amount),
+			TxError::InsufficientFee { fee, minimum_fee } => write!(f, "The fee {} is lower than the minimum fee {}", fee, minimum_fee),
+			TxError::UnexpectedCoinbase => write!(f, "Coinbase transactions can only be the first transaction of a block"),
+			TxError::AmountOverflow => write!(f, "The sum of the amounts overflows"),
+			TxError::AlreadyKnown => write!(f, "The transaction is already known"),
+		}
+	}
+}
+
 impl Transaction {
 	pub fn create_transaction(inputs: Vec<Input>, outputs: Vec<Output>, extra_entropy: u16) -> Self {
 		let mut s = Self {
 			id: [0u8; 32],
-			extra_entropy: 0,
-			input_list: vec![],
-			output_list: vec![],
+			extra_entropy,
+			input_list: inputs,
+			output_list: outputs,
 		};
 		s.update_hash();
 		s
 	}
-	pub fn sign_inputs(&mut self, sk: &[u8]) -> Result<(), PublicKeyError> {
-		for input in self.input_list.iter_mut() {
-			let hash = input.calculate_hash();
-			let signature = PublicKeyAlgorithm::sign(&sk, &hash)?;
-			input.signature = signature;
+	/// Creates the coinbase transaction of the block at the given height, that pays the reward to the forger.
+	/// The coinbase has a single input that does not spend anything and references the height, so that every coinbase has a different id
+	pub fn coinbase(height: usize, forger_address: P2PKHAddress, reward: u64) -> Self {
+		let input = Input {
+			prev_txid: [0u8; 32],
+			output_index: height,
+			signature: vec![],
+			public_key: vec![],
+			sighash_type: SigHashType::All,
+		};
+		let output = Output {
+			amount: reward,
+			address: forger_address,
+		};
+		Self::create_transaction(vec![input], vec![output], 0)
+	}
+	/// Returns the hash of the signed transaction. Unlike the id, it changes when a signature or signature hash type changes
+	pub fn calculate_witness_hash(&self) -> [u8; 32] {
+		hash_with_domain(WITNESS_DOMAIN, &Witness(self))
+	}
+	/// Returns whether the transaction has the shape of a coinbase: a single input that does not spend anything
+	pub fn is_coinbase(&self) -> bool {
+		self.input_list.len() == 1 && self.has_coinbase_input()
+	}
+	/// Returns whether some input does not spend anything, like the input of a coinbase
+	pub fn has_coinbase_input(&self) -> bool {
+		self.input_list.iter().any(|input| input.prev_txid == [0u8; 32])
+	}
+	/// Returns the UTxOs that the outputs of the transaction create. `coinbase_height` is the height of the block if the transaction is its coinbase
+	pub fn get_created_utxos(&self, coinbase_height: Option<usize>) -> Vec<UTXO> {
+		self.output_list.iter().enumerate().map(|(i, output)| UTXO {
+			txid: self.id,
+			output_index: i,
+			amount: output.amount,
+			recipient_address: output.address,
+			coinbase_height,
+		}).collect()
+	}
+	/// Signs the input at the given index with the given signature hash type.
+	/// `spent_utxos` are the UTxOs spent by every input of the transaction, in order, and `chain_id` is the id of the chain where the transaction will be sent
+	pub fn sign_input(&mut self, input_index: usize, sk: &[u8], sighash_type: SigHashType, spent_utxos: &[UTXO], chain_id: &[u8; 32]) -> Result<(), PublicKeyError> {
+		let hash = signature_hash(self, input_index, spent_utxos, sighash_type, chain_id)
+			.ok_or_else(|| PublicKeyError::new("Unable to calculate the signature hash of the input"))?;
+		let signature = PublicKeyAlgorithm::sign(sk, &hash)?;
+		let input = &mut self.input_list[input_index];
+		input.sighash_type = sighash_type;
+		input.signature = signature;
+		Ok(())
+	}
+	/// Signs every input committing to the whole transaction
+	pub fn sign_inputs(&mut self, sk: &[u8], spent_utxos: &[UTXO], chain_id: &[u8; 32]) -> Result<(), PublicKeyError> {
+		for i in 0..self.input_list.len() {
+			self.sign_input(i, sk, SigHashType::All, spent_utxos, chain_id)?;
 		}
 		Ok(())
 	}
-	pub fn verify_input_signatures(&self) -> bool {
-		for input in &self.input_list {
-			if !input.verify_signature() {
-				return false;
+	pub fn verify_input_signatures(&self, spent_utxos: &[UTXO], chain_id: &[u8; 32]) -> Result<(), TxError> {
+		for (i, input) in self.input_list.iter().enumerate() {
+			let is_signature_valid = signature_hash(self, i, spent_utxos, input.sighash_type, chain_id)
+				.is_some_and(|hash| PublicKeyAlgorithm::verify(&input.public_key, &hash, &input.signature).is_ok());
+			if !is_signature_valid {
+				return Err(TxError::InvalidSignature(i));
 			}
 		}
-		true
+		Ok(())
 	}
-	pub fn validate_inputs(&self, blockchain: &BlockChain) -> bool {
-		 for input in &self.input_list {
-			 if !input.validate(blockchain) {
-				 return false;
-			 }
-		 }
-		true
+	/// Returns the UTxOs spent by the inputs in the same order, or None if some of them is not in the UTxO set
+	pub fn get_spent_utxos(&self, blockchain: &BlockChain) -> Option<Vec<UTXO>> {
+		self.input_list.iter()
+			.map(|input| blockchain.utxo_set.get_utxo(&input.prev_txid, input.output_index))
+			.collect()
+	}
+	/// Checks that every input spends an output of the UTxO view that is mature and owned by its public key, and that the signatures are valid
+	pub fn validate_inputs(&self, blockchain: &BlockChain, utxos: &UtxoView) -> Result<(), TxError> {
+		let mut spent_utxos = vec![];
+		for input in &self.input_list {
+			spent_utxos.push(input.validate(blockchain, utxos)?);
+		}
+		self.verify_input_signatures(&spent_utxos, &blockchain.get_chain_id())
 	}
-	pub fn do_sum(&self, blockchain: &BlockChain) -> bool {
-		let mut spent = 0;
-		for output in &self.output_list {
-			spent += output.amount;
+	/// Checks that the inputs can pay for the outputs and that whatever is left, the fee, is at least the minimum fee for the size of the transaction
+	pub fn validate_amounts(&self, blockchain: &BlockChain, utxos: &UtxoView) -> Result<(), TxError> {
+		let input_amount = self.get_input_amount(utxos)?;
+		let output_amount = self.get_output_amount()?;
+		if input_amount < output_amount {
+			return Err(TxError::ValueMismatch { input_amount, output_amount });
 		}
-		let mut budget = 0;
-		for input in & self.input_list {
-			if let Some(utxos) = blockchain.get_utxo_list(&input.prev_txid) {
-				if let Some(utxo) = utxos.get(input.output_index) {
-					budget += utxo.amount;
-				}
+		let fee = input_amount - output_amount;
+		let minimum_fee = blockchain.parameters.economic_parameters.minimum_fee(self.size());
+		if fee < minimum_fee {
+			return Err(TxError::InsufficientFee { fee, minimum_fee });
+		}
+		Ok(())
+	}
+	/// Returns the sum of the amounts of the UTxOs of the view spent by the inputs
+	pub fn get_input_amount(&self, utxos: &UtxoView) -> Result<u64, TxError> {
+		let mut budget = 0u64;
+		for input in &self.input_list {
+			if let Some(utxo) = utxos.get_utxo(&input.prev_txid, input.output_index) {
+				budget = budget.checked_add(utxo.amount).ok_or(TxError::AmountOverflow)?;
 			}
 		}
-		budget == spent
+		Ok(budget)
+	}
+	/// Returns the sum of the amounts of the outputs
+	pub fn get_output_amount(&self) -> Result<u64, TxError> {
+		self.output_list.iter().try_fold(0u64, |total, output| total.checked_add(output.amount).ok_or(TxError::AmountOverflow))
+	}
+	/// Returns the fee that the transaction pays to the forger, which is what is left of the inputs after paying the outputs
+	pub fn get_fee(&self, blockchain: &BlockChain) -> u64 {
+		self.get_fee_in(&UtxoView::new(&blockchain.utxo_set))
+	}
+	/// Returns the fee that the transaction pays when its inputs are looked up in the given UTxO view.
+	/// Transactions whose amounts overflow pay nothing
+	pub fn get_fee_in(&self, utxos: &UtxoView) -> u64 {
+		match (self.get_input_amount(utxos), self.get_output_amount()) {
+			(Ok(input_amount), Ok(output_amount)) => input_amount.saturating_sub(output_amount),
+			_ => 0,
+		}
 	}
-	pub fn is_valid_heuristic(&self) -> bool {
-		let is_tx_size_valid = self.input_list.len() < 128 && self.output_list.len() < 128;
-		let are_inputs_unique = self.are_inputs_unique();
-		let are_signatures_valid = self.verify_input_signatures();
-		are_signatures_valid && are_inputs_unique && is_tx_size_valid
+	/// Checks what can be checked without the UTxO set: the amount of inputs and outputs and that the inputs are unique
+	pub fn is_valid_heuristic(&self) -> Result<(), TxError> {
+		if self.input_list.len() >= MAX_INPUTS_OR_OUTPUTS || self.output_list.len() >= MAX_INPUTS_OR_OUTPUTS {
+			return Err(TxError::Oversized(self.size()));
+		}
+		if !self.are_inputs_unique() {
+			return Err(TxError::DuplicateInput);
+		}
+		Ok(())
 	}
 	pub fn are_inputs_unique(&self) -> bool {
 		let mut output_indexes = HashSet::new();
@@ -80,12 +218,64 @@ impl Transaction {
 		}
 		true
 	}
-	/// Checks if the transaction's signature is valid, if the hash is valid and if the sender can afford to send this transaction
-	pub fn is_valid(&self, blockchain: &BlockChain) -> bool {
-		// TODO: CHECK FOR THE FEE OUTPUT OR SMT
-		self.is_valid_heuristic() && self.do_sum(blockchain) && self.validate_inputs(blockchain)
+	/// Checks if the transaction's signature is valid, if the hash is valid, if the sender can afford to send this transaction
+	/// and if it pays at least the minimum fee
+	pub fn is_valid(&self, blockchain: &BlockChain) -> Result<(), TxError> {
+		self.is_valid_in(blockchain, &UtxoView::new(&blockchain.utxo_set))
+	}
+	/// Checks the transaction like `is_valid`, looking up the spent outputs in the given UTxO view
+	pub fn is_valid_in(&self, blockchain: &BlockChain, utxos: &UtxoView) -> Result<(), TxError> {
+		if self.has_coinbase_input() {
+			return Err(TxError::UnexpectedCoinbase);
+		}
+		self.is_valid_heuristic()?;
+		let size = self.size();
+		if size > blockchain.parameters.network_parameters.max_tx_size {
+			return Err(TxError::Oversized(size));
+		}
+		self.validate_inputs(blockchain, utxos)?;
+		self.validate_amounts(blockchain, utxos)
 	}
+	/// Returns the size in bytes of the serialized transaction, which is what the fee is paid for
 	pub fn size(&self) -> usize {
-		0 // FIXME
+		serialize_bincode(self).map(|data| data.len()).unwrap_or(usize::MAX)
+	}
+	/// Returns the maximum size that a signed transaction with the given amount of inputs and outputs can have.
+	/// It is calculated from the size of a single input and output, without building the transaction
+	pub fn estimate_size(input_count: usize, output_count: usize) -> usize {
+		let input = Input {
+			prev_txid: [u8::MAX; 32],
+			output_index: usize::MAX,
+			signature: vec![0u8; SIGNATURE_SIZE],
+			public_key: vec![0u8; PUBLIC_KEY_SIZE],
+			sighash_type: SigHashType::SingleAnyoneCanPay,
+		};
+		let output = Output {
+			amount: u64::MAX,
+			address: P2PKHAddress::null(),
+		};
+		let empty = Self {
+			id: [0u8; 32],
+			extra_entropy: u16::MAX,
+			input_list: vec![],
+			output_list: vec![],
+		};
+		let input_size = serialize_bincode(&input).map(|data| data.len()).unwrap_or(usize::MAX);
+		let output_size = serialize_bincode(&output).map(|data| data.len()).unwrap_or(usize::MAX);
+		// The lists are prefixed with their length, which takes one byte when they are empty
+		(empty.size() - 2)
+			.saturating_add(length_prefix_size(input_count))
+			.saturating_add(length_prefix_size(output_count))
+			.saturating_add(input_count.saturating_mul(input_size))
+			.saturating_add(output_count.saturating_mul(output_size))
 	}
-}
\ No newline at end of file
+}
+/// Returns the size of the variable length integer that prefixes a list with the given length
+fn length_prefix_size(length: usize) -> usize {
+	match length as u64 {
+		0..=250 => 1,
+		251..=0xFFFF => 3,
+		0x10000..=0xFFFF_FFFF => 5,
+		_ => 9,
+	}
+}
@@ -0,0 +1,44 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::core::utxo::transaction::Transaction;
+use crate::core::utxo::UTXO;
+use crate::data_storage::blockchain_storage::utxo_database::UTXODB;
+
+/// The UTxO set as the transactions of a block see it: the outputs spent by the previous transactions of the block
+/// are gone and the outputs they created can be spent. The UTxO set itself is not modified
+pub struct UtxoView<'a> {
+	utxo_set: &'a UTXODB,
+	/// The outputs (transaction id and output index) spent by the transactions applied to the view
+	spent: HashSet<([u8; 32], usize)>,
+	/// The outputs created by the transactions applied to the view
+	created: HashMap<([u8; 32], usize), UTXO>,
+}
+impl<'a> UtxoView<'a> {
+	pub fn new(utxo_set: &'a UTXODB) -> Self {
+		Self {
+			utxo_set,
+			spent: HashSet::new(),
+			created: HashMap::new(),
+		}
+	}
+	/// Returns the UTxO with the given output index of the given transaction, if it is unspent in the view
+	pub fn get_utxo(&self, txid: &[u8; 32], output_index: usize) -> Option<UTXO> {
+		if self.spent.contains(&(*txid, output_index)) {
+			return None;
+		}
+		self.created.get(&(*txid, output_index)).copied()
+			.or_else(|| self.utxo_set.get_utxo(txid, output_index))
+	}
+	/// Spends the inputs and creates the outputs of the transaction, which is the coinbase if `coinbase_height` is given
+	pub fn apply(&mut self, tx: &Transaction, coinbase_height: Option<usize>) {
+		if coinbase_height.is_none() {
+			for input in &tx.input_list {
+				self.created.remove(&(input.prev_txid, input.output_index));
+				self.spent.insert((input.prev_txid, input.output_index));
+			}
+		}
+		for utxo in tx.get_created_utxos(coinbase_height) {
+			self.created.insert((utxo.txid, utxo.output_index), utxo);
+		}
+	}
+}
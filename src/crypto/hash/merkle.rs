@@ -1,27 +1,93 @@
+use serde::{Deserialize, Serialize};
+
 use crate::crypto::hash::hash;
 
+/// Prefix of the hashes of the leaves, so that an inner node can never be passed as a leaf
+const LEAF_PREFIX: u8 = 0x00;
+/// Prefix of the hashes of the inner nodes
+const NODE_PREFIX: u8 = 0x01;
+
+fn hash_leaf(leaf: &[u8; 32]) -> [u8; 32] {
+	let mut data = Vec::with_capacity(33);
+	data.push(LEAF_PREFIX);
+	data.extend_from_slice(leaf);
+	hash(&data)
+}
+fn hash_node(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+	let mut data = Vec::with_capacity(65);
+	data.push(NODE_PREFIX);
+	data.extend_from_slice(left);
+	data.extend_from_slice(right);
+	hash(&data)
+}
+/// Hashes each pair of nodes of a level. The last node of a level with an odd amount of nodes is moved up as it is, instead of being duplicated
+fn next_level(level: &[[u8; 32]]) -> Vec<[u8; 32]> {
+	level.chunks(2).map(|pair| match pair {
+		[left, right] => hash_node(left, right),
+		[single] => *single,
+		_ => unreachable!(),
+	}).collect()
+}
+
+/// Calculates the root of the merkle tree of the given leaves. The root of an empty tree is all zeros
 pub fn calculate_merkle_root(data: Vec<[u8; 32]>) -> [u8; 32] {
 	if data.is_empty() {
 		return [0u8; 32];
 	}
-
-	if data.len() == 1 {
-		return data[0].clone();
+	let mut level: Vec<[u8; 32]> = data.iter().map(hash_leaf).collect();
+	while level.len() > 1 {
+		level = next_level(&level);
 	}
+	level[0]
+}
 
-	let mut new_data: Vec<[u8; 32]> = Vec::new();
-
-	for chunk in data.chunks(2) {
-		let mut combined_data: Vec<u8> = Vec::new();
-
-		for chunk_data in chunk {
-			combined_data.extend_from_slice(chunk_data);
+/// Proves that a leaf is in a merkle tree: the siblings of the path from the leaf to the root, from the bottom up
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct MerkleProof {
+	pub index: usize,
+	pub leaf_count: usize,
+	pub siblings: Vec<[u8; 32]>,
+}
+impl MerkleProof {
+	/// Generates the proof of the leaf at the given index, or None if there is no such leaf
+	pub fn generate(data: &[[u8; 32]], index: usize) -> Option<Self> {
+		if index >= data.len() {
+			return None;
 		}
-
-		let hash = hash(&combined_data);
-
-		new_data.push(hash);
+		let mut siblings = vec![];
+		let mut level: Vec<[u8; 32]> = data.iter().map(hash_leaf).collect();
+		let mut position = index;
+		while level.len() > 1 {
+			if let Some(sibling) = level.get(position ^ 1) {
+				siblings.push(*sibling);
+			}
+			level = next_level(&level);
+			position /= 2;
+		}
+		Some(MerkleProof { index, leaf_count: data.len(), siblings })
 	}
-
-	calculate_merkle_root(new_data)
-}
\ No newline at end of file
+	/// Calculates the root of the tree from the leaf and the proof. Returns None if the proof does not match the shape of the tree
+	pub fn calculate_root(&self, leaf: &[u8; 32]) -> Option<[u8; 32]> {
+		if self.index >= self.leaf_count {
+			return None;
+		}
+		let mut siblings = self.siblings.iter();
+		let mut node = hash_leaf(leaf);
+		let (mut position, mut level_size) = (self.index, self.leaf_count);
+		while level_size > 1 {
+			let is_moved_up = position == level_size - 1 && level_size % 2 == 1;
+			if !is_moved_up {
+				let sibling = siblings.next()?;
+				node = if position % 2 == 0 { hash_node(&node, sibling) } else { hash_node(sibling, &node) };
+			}
+			position /= 2;
+			level_size = level_size.div_ceil(2);
+		}
+		// Every sibling must be used
+		siblings.next().is_none().then_some(node)
+	}
+	/// Checks that the leaf is in the tree with the given root
+	pub fn verify(&self, leaf: &[u8; 32], root: &[u8; 32]) -> bool {
+		self.calculate_root(leaf).is_some_and(|calculated| calculated == *root)
+	}
+}
@@ -8,6 +8,11 @@ use rand_core::OsRng;
 
 pub struct PublicKeyAlgorithm;
 
+/// The size of a serialized signature
+pub const SIGNATURE_SIZE: usize = 64;
+/// The size of a serialized public key (SEC1 uncompressed)
+pub const PUBLIC_KEY_SIZE: usize = 65;
+
 
 
 impl PublicKeyAlgorithm {
@@ -21,7 +26,7 @@ impl PublicKeyAlgorithm {
 	}
 	pub fn sign(key: &[u8], data: &[u8]) -> Result<Vec<u8>, PublicKeyError> {
 		let mut sk = Self::skey_from_bytes(&key)?;
-		let signature: Signature = sk.sign(&[]);
+		let signature: Signature = sk.sign(data);
 		Ok(signature.to_bytes().to_vec())
 	}
 	///
@@ -81,6 +86,13 @@ impl PublicKeyAlgorithm {
 pub struct PublicKeyError {
 	description: String
 }
+impl PublicKeyError {
+	pub fn new(description: &str) -> Self {
+		PublicKeyError {
+			description: description.to_string(),
+		}
+	}
+}
 impl Display for PublicKeyError {
 	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
 		write!(f, "{:?}", self)
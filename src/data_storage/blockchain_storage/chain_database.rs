@@ -7,7 +7,9 @@ use p256::pkcs8::der::Writer;
 use serde::{Deserialize, Serialize};
 use sled::{Db, IVec};
 
+use crate::consensus::epoch_nonce::EpochNonces;
 use crate::core::block::Block;
+use crate::core::genesis::GenesisSpec;
 use crate::core::Hashable;
 use crate::core::utxo::UTXO;
 use crate::data_storage::BaseDirectory;
@@ -16,21 +18,31 @@ use crate::network::standard::{standard_deserialize, standard_serialize};
 
 #[derive(Clone, Serialize, Deserialize)]
 pub struct ChainMetadata { // TODO: Make sure everything here is updated each and every write
-	/// This is equivalent to the height of the best block **minus one**
+	/// This is equivalent to the height of the best block **plus one**
 	length: usize,
 	best_block: [u8; 32],
+	/// The nonces of the lottery after the best block
+	#[serde(default)]
+	nonces: EpochNonces,
+	/// The location of the metadata file
+	#[serde(skip)]
+	file_location: String,
 }
 impl ChainMetadata {
-	pub fn load() -> Self {
-		let file_location = format!("{}/blockchain/metadata.json", BaseDirectory::get_base_directory());
+	pub fn load(directory: &str) -> Self {
+		let file_location = format!("{}/blockchain/metadata.json", directory);
 		if Path::exists(file_location.as_str().as_ref()) {
 			let data = std::fs::read_to_string(&file_location).unwrap();
-			serde_json::from_str(&data).expect("Unable to deserialize metadata")
+			let mut metadata: Self = serde_json::from_str(&data).expect("Unable to deserialize metadata");
+			metadata.file_location = file_location;
+			metadata
 		} else {
 			File::create(&file_location).unwrap();
 			let metadata = Self {
 				length: 0,
 				best_block: [0u8; 32],
+				nonces: EpochNonces::default(),
+				file_location,
 			};
 			metadata.save();
 			metadata
@@ -47,7 +59,7 @@ impl ChainMetadata {
 		self.best_block
 	}
 	pub fn save(&self) {
-		let mut metadata_file = OpenOptions::new().write(true).create(true).truncate(true).open(format!("{}/blockchain/metadata.json", BaseDirectory::get_base_directory())).expect("Unable to open chain metadata file");
+		let mut metadata_file = OpenOptions::new().write(true).create(true).truncate(true).open(&self.file_location).expect("Unable to open chain metadata file");
 		let new_data = serde_json::to_string_pretty(self).expect("Unable to serialize metadata to json");
 		metadata_file.write(new_data.as_bytes()).expect("Unable to write metadata to file");
 	}
@@ -58,6 +70,8 @@ pub struct ChainDB {
 	index_to_hash_db: Db,
 	undo_block_db: Db,
 	index_undo_block_db: Db,
+	/// The hash of the block of the best chain that contains each transaction, by txid
+	tx_index_db: Db,
 	chain_metadata: ChainMetadata,
 
 }
@@ -68,14 +82,19 @@ impl ChainDB {
 		}
 	}
 	/// Does not check if there is already a block in that index or hash. Must be checked before calling this function.
-	pub fn push_block_to_end(&mut self, block: &Block, undo_block: &UndoBlock) -> anyhow::Result<()> {
+	/// The nonces of the lottery after the block are saved together with the new best block
+	pub fn push_block_to_end(&mut self, block: &Block, undo_block: &UndoBlock, nonces: EpochNonces) -> anyhow::Result<()> {
 		let serialized_block = standard_serialize(&block)?;
 		let hash = block.calculate_hash();
 		self.chain_db.insert(hash, serialized_block)?;
 		self.index_to_hash_db.insert(block.header.height.to_be_bytes(), &hash)?;
+		for tx in &block.transactions {
+			self.tx_index_db.insert(tx.id, &hash)?;
+		}
 		
 		self.chain_db.flush()?;
 		self.index_to_hash_db.flush()?;
+		self.tx_index_db.flush()?;
 		
 		self.index_undo_block_db.insert(undo_block.height.to_be_bytes(), &undo_block.original_hash)?;
 		let serialized_undo = standard_serialize(&undo_block)?;
@@ -86,10 +105,37 @@ impl ChainDB {
 		
 		self.chain_metadata.length += 1;
 		self.chain_metadata.best_block = block.calculate_hash();
+		self.chain_metadata.nonces = nonces;
 		self.chain_metadata.save();
 		Ok(())
 	}
 	
+	/// Removes the best block from the chain together with its undo block and returns it.
+	/// The genesis block can not be removed, in that case None is returned.
+	pub fn pop_block(&mut self) -> anyhow::Result<Option<Block>> {
+		if self.chain_metadata.length <= 1 {
+			return Ok(None);
+		}
+		let Some(block) = self.get_best_block() else {
+			return Ok(None);
+		};
+		let hash = block.header.hash;
+		let height = block.header.height;
+		self.chain_db.remove(hash)?;
+		self.index_to_hash_db.remove(height.to_be_bytes())?;
+		self.undo_block_db.remove(hash)?;
+		self.index_undo_block_db.remove(height.to_be_bytes())?;
+		for tx in &block.transactions {
+			self.tx_index_db.remove(tx.id)?;
+		}
+		self.flush()?;
+
+		self.chain_metadata.length -= 1;
+		self.chain_metadata.best_block = block.header.previous_hash;
+		self.chain_metadata.save();
+		Ok(Some(block))
+	}
+
 	pub fn get_undo_block(&self, block_hash: &[u8; 32]) -> anyhow::Result<Option<UndoBlock>> {
 		if let Some(undo_block) = self.undo_block_db.get(block_hash)? {
 			let undo_block: UndoBlock = standard_deserialize(&undo_block.to_vec())?;
@@ -120,6 +166,18 @@ impl ChainDB {
 		let block = standard_deserialize(&self.chain_db.get(hash).ok()??).ok()?; // This gets the block based on the key (the hash) and serializes it (yeah, there is a lot of "?")
 		block
 	}
+	pub fn get_hash_by_height(&self, height: usize) -> Option<[u8; 32]> {
+		let hash = self.index_to_hash_db.get(height.to_be_bytes()).ok()??;
+		hash.as_ref().try_into().ok()
+	}
+	/// Returns the hash of the block of the best chain that contains the transaction
+	pub fn get_transaction_block(&self, txid: &[u8; 32]) -> Option<[u8; 32]> {
+		let hash = self.tx_index_db.get(txid).ok()??;
+		hash.as_ref().try_into().ok()
+	}
+	pub fn contains_block(&self, hash: &[u8; 32]) -> bool {
+		self.chain_db.contains_key(hash).unwrap_or(false)
+	}
 	fn is_empty(&self) -> bool {
 		self.chain_db.is_empty() || self.index_to_hash_db.is_empty()
 	}
@@ -127,6 +185,14 @@ impl ChainDB {
 	pub fn get_length(&self) -> usize {
 		self.chain_metadata.length
 	}
+	/// Returns the nonces of the lottery after the best block
+	pub fn get_nonces(&self) -> EpochNonces {
+		self.chain_metadata.nonces
+	}
+	pub fn set_nonces(&mut self, nonces: EpochNonces) {
+		self.chain_metadata.nonces = nonces;
+		self.chain_metadata.save();
+	}
 	
 
 	pub fn flush(&mut self) -> sled::Result<()> {
@@ -134,32 +200,54 @@ impl ChainDB {
 		self.index_to_hash_db.flush()?;
 		self.undo_block_db.flush()?;
 		self.index_undo_block_db.flush()?;
+		self.tx_index_db.flush()?;
 		self.chain_metadata.save();
 		
 		Ok(())
 	}
 }
-impl Default for ChainDB {
-	fn default() -> Self {
-		let base_directory = BaseDirectory::get_base_directory();
+impl ChainDB {
+	/// Opens the chain stored in the given base directory, creating it with the given genesis block if it does not exist
+	pub fn open(base_directory: &str, genesis_block: &Block) -> Self {
 		let chain_db = sled::open(format!("{}/blockchain/chain-db", base_directory)).expect("failed to write to database"); // FIXME: Change the file for the actual Db location
 		let index_to_hash_db = sled::open(format!("{}/blockchain/index-db", base_directory)).expect("failed to write to database"); // FIXME: Change the file for the actual Db location
 		let undo_block_db = sled::open(format!("{}/blockchain/undo-db", base_directory)).expect("failed to write to database"); // FIXME: Change the file for the actual Db location
 		let index_undo_block_db = sled::open(format!("{}/blockchain/undo-index-db", base_directory)).expect("failed to write to database"); // FIXME: Change the file for the actual Db location
+		let tx_index_db = sled::open(format!("{}/blockchain/tx-index-db", base_directory)).expect("failed to write to database");
 
-		let chain_metadata = ChainMetadata::load();
+		let chain_metadata = ChainMetadata::load(base_directory);
 		let mut this = Self {
 			chain_db,
 			index_to_hash_db,
 			undo_block_db,
 			index_undo_block_db,
+			tx_index_db,
 			chain_metadata,
 		};
 		if this.is_empty() {
-			
-			this.push_block_to_end(&Block::genesis(), &UndoBlock::genesis()).expect("Unable to insert genesis block");
+			this.push_block_to_end(genesis_block, &UndoBlock::genesis(genesis_block), EpochNonces::genesis(genesis_block.header.previous_hash)).expect("Unable to insert genesis block");
+		} else if this.tx_index_db.is_empty() {
+			this.reindex_transactions().expect("Unable to index the transactions of the chain");
 		}
 		
 		this
 	}
+}
+impl ChainDB {
+	/// Indexes the transactions of every block of the best chain. Chains created before the index existed do not have it
+	fn reindex_transactions(&self) -> anyhow::Result<()> {
+		for height in 0..self.get_length() {
+			let block = self.get_block_by_height(height).ok_or_else(|| anyhow::anyhow!("Missing block at height {}", height))?;
+			for tx in &block.transactions {
+				self.tx_index_db.insert(tx.id, &block.header.hash)?;
+			}
+		}
+		self.tx_index_db.flush()?;
+		Ok(())
+	}
+}
+impl Default for ChainDB {
+	fn default() -> Self {
+		Self::open(&BaseDirectory::get_base_directory(), &GenesisSpec::default().get_block())
+	}
 }
\ No newline at end of file
@@ -0,0 +1,86 @@
+use serde::{Deserialize, Serialize};
+use sled::Db;
+
+use crate::consensus::epoch_nonce::EpochNonces;
+use crate::core::block::BlockHeader;
+use crate::network::standard::{standard_deserialize, standard_serialize};
+
+/// A header of the best chain together with the nonces of the lottery after it
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct StoredHeader {
+	pub header: BlockHeader,
+	pub nonces: EpochNonces,
+}
+
+/// Stores the headers of the best chain, without the block bodies. Used by light clients
+#[derive(Clone)]
+pub struct HeaderDB {
+	header_db: Db,
+	index_to_hash_db: Db,
+}
+impl HeaderDB {
+	/// Opens the headers stored in the given base directory, creating them with the given genesis header if they do not exist
+	pub fn open(base_directory: &str, genesis_header: &BlockHeader) -> Self {
+		let header_db = sled::open(format!("{}/light/header-db", base_directory)).expect("Unable to open / create header database");
+		let index_to_hash_db = sled::open(format!("{}/light/index-db", base_directory)).expect("Unable to open / create header index database");
+		let this = Self {
+			header_db,
+			index_to_hash_db,
+		};
+		if this.index_to_hash_db.is_empty() {
+			let genesis = StoredHeader {
+				header: genesis_header.clone(),
+				nonces: EpochNonces::genesis(genesis_header.previous_hash),
+			};
+			this.push(&genesis).expect("Unable to insert genesis header");
+		}
+		this
+	}
+	/// Does not check that the header follows the best header. Must be checked before calling this function.
+	pub fn push(&self, stored: &StoredHeader) -> anyhow::Result<()> {
+		let hash = stored.header.hash;
+		self.header_db.insert(hash, standard_serialize(stored)?)?;
+		self.index_to_hash_db.insert(stored.header.height.to_be_bytes(), &hash)?;
+		self.flush()?;
+		Ok(())
+	}
+	/// Removes the best header and returns it. The genesis header can not be removed, in that case None is returned.
+	pub fn pop(&self) -> anyhow::Result<Option<StoredHeader>> {
+		if self.get_length() <= 1 {
+			return Ok(None);
+		}
+		let Some(best) = self.get_best() else {
+			return Ok(None);
+		};
+		self.header_db.remove(best.header.hash)?;
+		self.index_to_hash_db.remove(best.header.height.to_be_bytes())?;
+		self.flush()?;
+		Ok(Some(best))
+	}
+	pub fn get_best(&self) -> Option<StoredHeader> {
+		self.get_by_height(self.get_length().checked_sub(1)?)
+	}
+	pub fn get(&self, hash: &[u8; 32]) -> Option<StoredHeader> {
+		let data = self.header_db.get(hash).ok()??;
+		standard_deserialize(&data).map_err(|err| log::error!("Unable to deserialize header: {}", err)).ok()
+	}
+	pub fn get_by_height(&self, height: usize) -> Option<StoredHeader> {
+		self.get(&self.get_hash_by_height(height)?)
+	}
+	pub fn get_hash_by_height(&self, height: usize) -> Option<[u8; 32]> {
+		let hash = self.index_to_hash_db.get(height.to_be_bytes()).ok()??;
+		hash.as_ref().try_into().ok()
+	}
+	pub fn contains(&self, hash: &[u8; 32]) -> bool {
+		self.header_db.contains_key(hash).unwrap_or(false)
+	}
+	/// The amount of headers, which is the height of the best header **plus one**
+	pub fn get_length(&self) -> usize {
+		self.index_to_hash_db.len()
+	}
+	fn flush(&self) -> sled::Result<()> {
+		self.header_db.flush()?;
+		self.index_to_hash_db.flush()?;
+		Ok(())
+	}
+}
@@ -3,6 +3,7 @@ use std::collections::HashSet;
 use sled::Db;
 
 use crate::core::utxo::transaction::Transaction;
+use crate::data_storage::BaseDirectory;
 use crate::network::standard::{standard_deserialize, standard_serialize};
 
 
@@ -12,25 +13,31 @@ pub struct MempoolDB {
 	mempool_db: Db,
 }
 impl MempoolDB {
-	pub fn insert(&mut self, tx: &Transaction) -> bool {
+	pub fn insert(&mut self, tx: &Transaction) {
 		let data = standard_serialize(&tx).expect("Unable to serialize tx");
 		self.mempool.insert(tx.clone());
-		let replaced = self.mempool_db.insert(tx.id, data).expect("TODO: panic message").is_some();
+		self.mempool_db.insert(tx.id, data).expect("TODO: panic message");
 		self.mempool_db.flush().expect("Unable to flush mempool");
-		replaced
 	}
 	pub fn remove(&mut self, tx: &Transaction) {
 		self.mempool.remove(tx);
 		self.mempool_db.remove(tx.id).unwrap();
 		self.mempool_db.flush().unwrap();
 	}
+	pub fn contains(&self, txid: &[u8; 32]) -> bool {
+		self.mempool_db.contains_key(txid).unwrap_or(false)
+	}
+	pub fn get(&self, txid: &[u8; 32]) -> Option<Transaction> {
+		self.mempool.iter().find(|tx| tx.id == *txid).cloned()
+	}
 	pub fn get_map(&self) -> &HashSet<Transaction> {
 		&self.mempool
 	}
 }
-impl Default for MempoolDB {
-	fn default() -> Self {
-		let db = sled::open("./blockchain/mempool-db").unwrap(); // FIXME: Change the file for the actual Db location
+impl MempoolDB {
+	/// Opens the mempool stored in the given base directory
+	pub fn open(base_directory: &str) -> Self {
+		let db = sled::open(format!("{}/blockchain/mempool-db", base_directory)).expect("Unable to open / create mempool");
 		let txs: HashSet<Transaction> = db.iter().filter_map(|tx| {
 			match tx {
 				Ok((_, tx)) => {
@@ -48,4 +55,9 @@ impl Default for MempoolDB {
 			mempool_db: db,
 		}
 	}
+}
+impl Default for MempoolDB {
+	fn default() -> Self {
+		Self::open(&BaseDirectory::get_base_directory())
+	}
 }
\ No newline at end of file
@@ -6,4 +6,7 @@ pub mod chain_database;
 pub mod mempool_database;
 pub mod utxo_database;
 pub mod undo_items;
+pub mod stake_database;
+pub mod header_database;
 
+pub mod reorg_journal;
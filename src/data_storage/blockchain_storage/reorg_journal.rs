@@ -0,0 +1,48 @@
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::core::block::Block;
+use crate::network::standard::{standard_deserialize, standard_serialize};
+
+/// The record of a change of the best chain in progress. It is written before the first block is undone or applied and removed
+/// once the chain is consistent again, so that a node stopped in the middle of the change goes back to the original blocks when it opens the chain
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ReorgJournal {
+	/// The last block shared by the original chain and the new one
+	pub fork_point: [u8; 32],
+	/// The blocks of the original chain after the fork point, in order
+	pub original_blocks: Vec<Block>,
+}
+impl ReorgJournal {
+	fn location(base_directory: &str) -> String {
+		format!("{}/blockchain/reorg-journal.json", base_directory)
+	}
+	/// Returns the journal of the change that was interrupted, if any
+	pub fn load(base_directory: &str) -> anyhow::Result<Option<Self>> {
+		let location = Self::location(base_directory);
+		if !Path::new(&location).exists() {
+			return Ok(None);
+		}
+		let data = std::fs::read(&location)?;
+		Ok(Some(standard_deserialize(&data)?))
+	}
+	/// Writes the journal to a temporary file and then renames it, so that a journal is either complete or missing
+	pub fn save(&self, base_directory: &str) -> anyhow::Result<()> {
+		let location = Self::location(base_directory);
+		let temporary_location = format!("{}.tmp", location);
+		let file = std::fs::File::create(&temporary_location)?;
+		std::io::Write::write_all(&mut &file, &standard_serialize(self)?)?;
+		file.sync_all()?;
+		std::fs::rename(temporary_location, location)?;
+		Ok(())
+	}
+	/// Removes the journal once the change is complete
+	pub fn remove(base_directory: &str) -> anyhow::Result<()> {
+		let location = Self::location(base_directory);
+		if Path::new(&location).exists() {
+			std::fs::remove_file(location)?;
+		}
+		Ok(())
+	}
+}
@@ -0,0 +1,38 @@
+use sled::Db;
+
+use crate::consensus::stake::StakeDistribution;
+use crate::data_storage::BaseDirectory;
+use crate::network::standard::{standard_deserialize, standard_serialize};
+
+/// Stores the stake distribution snapshot of every epoch that has blocks
+#[derive(Clone)]
+pub struct StakeDB {
+	snapshots: Db,
+}
+impl StakeDB {
+	/// Opens the stake snapshots stored in the given base directory
+	pub fn open(base_directory: &str) -> Self {
+		let snapshots = sled::open(format!("{}/blockchain/stake-db", base_directory)).expect("Unable to open / create stake database");
+		Self {
+			snapshots,
+		}
+	}
+	pub fn insert(&self, epoch: u64, distribution: &StakeDistribution) {
+		let data = standard_serialize(distribution).expect("Unable to serialize stake distribution");
+		self.snapshots.insert(epoch.to_be_bytes(), data).expect("Unable to insert to stake database");
+		self.snapshots.flush().expect("Unable to flush");
+	}
+	pub fn get(&self, epoch: u64) -> Option<StakeDistribution> {
+		let data = self.snapshots.get(epoch.to_be_bytes()).expect("Unable to get from stake database")?;
+		standard_deserialize(&data).map_err(|err| log::error!("Unable to deserialize stake distribution: {}", err)).ok()
+	}
+	pub fn remove(&self, epoch: u64) {
+		self.snapshots.remove(epoch.to_be_bytes()).expect("Unable to remove from stake database");
+		self.snapshots.flush().expect("Unable to flush");
+	}
+}
+impl Default for StakeDB {
+	fn default() -> Self {
+		Self::open(&BaseDirectory::get_base_directory())
+	}
+}
@@ -1,14 +1,17 @@
 use serde::{Deserialize, Serialize};
+use crate::consensus::epoch_nonce::EpochNonces;
 use crate::core::block::Block;
 
 use crate::core::utxo::UTXO;
 
-// TODO: Just a reminder, for reversing blocks, remember reversing the coinbase tx too! Or however it works with Cardano / Ouroboros
 #[derive(Clone, Debug, Eq, Hash, Serialize, Deserialize, PartialEq)]
 pub struct UndoTransaction {
 	pub original_tx_id: [u8; 32],
 	/// The UTxOs that the transaction removed to the thing
 	pub removed_utxos: Vec<UTXO>,
+	/// The UTxOs that the transaction created from its outputs
+	#[serde(default)]
+	pub created_utxos: Vec<UTXO>,
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
@@ -16,13 +19,17 @@ pub struct UndoBlock {
 	pub height: usize,
 	pub original_hash: [u8; 32],
 	pub undo_transactions: Vec<UndoTransaction>,
+	/// The nonces of the lottery before the block was added
+	#[serde(default)]
+	pub previous_nonces: EpochNonces,
 }
 impl UndoBlock {
-	pub fn genesis() -> Self {
+	pub fn genesis(genesis_block: &Block) -> Self {
 		Self {
 			height: 0,
-			original_hash: Block::genesis().header.hash,
+			original_hash: genesis_block.header.hash,
 			undo_transactions: vec![],
+			previous_nonces: EpochNonces::default(),
 		}
 	}
 }
\ No newline at end of file
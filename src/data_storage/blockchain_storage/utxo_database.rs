@@ -3,7 +3,7 @@ use std::fs::File;
 
 use sled::Db;
 
-use crate::core::parameters::Parameters;
+use crate::core::genesis::GenesisSpec;
 use crate::core::utxo::UTXO;
 use crate::data_storage::BaseDirectory;
 use crate::network::standard::{standard_deserialize, standard_serialize};
@@ -13,10 +13,22 @@ pub struct UTXODB {
 	utxo_set: Db,
 }
 impl UTXODB {
-	pub fn genesis(parameters: Parameters) -> Self {
-		let utxo_directory = format!("{}/blockchain/utxo-set/", BaseDirectory::get_base_directory());
-		let mut utxo_set = sled::open(utxo_directory).expect("Unable to open / create utxo set");
-		// TODO: Add genesis distribution in here
+	/// Opens the UTxO set stored in the given base directory, adding the genesis UTxOs if the set is new.
+	/// `chain_length` is the amount of blocks of the stored chain: once there are blocks after the genesis block,
+	/// an empty set means that every output was spent and it is not seeded again
+	pub fn genesis(base_directory: &str, genesis: &GenesisSpec, chain_length: usize) -> Self {
+		let this = Self::open(base_directory);
+		if chain_length <= 1 && this.utxo_set.is_empty() {
+			for utxo in genesis.get_utxos() {
+				this.restore_utxo(utxo);
+			}
+		}
+		this
+	}
+	/// Opens the UTxO set stored in the given base directory
+	pub fn open(base_directory: &str) -> Self {
+		let utxo_directory = format!("{}/blockchain/utxo-set/", base_directory);
+		let utxo_set = sled::open(utxo_directory).expect("Unable to open / create utxo set");
 		Self {
 			utxo_set,
 		}
@@ -34,6 +46,28 @@ impl UTXODB {
 		let utxo_list = standard_deserialize(&data).map_err(|err| log::error!("Unable to deserialize UTXO set content: {}", err)).unwrap();
 		Some(utxo_list)
 	}
+	/// Returns every UTxO in the set
+	pub fn get_all(&self) -> Vec<UTXO> {
+		self.utxo_set.iter()
+			.filter_map(|entry| entry.ok())
+			.filter_map(|(_, data)| standard_deserialize::<Vec<UTXO>>(&data).ok())
+			.flatten()
+			.collect()
+	}
+	/// Adds back a single UTxO to the list of its transaction, keeping the list sorted by output index
+	pub fn restore_utxo(&self, utxo: UTXO) {
+		let mut utxo_list = self.get(&utxo.txid).unwrap_or_default();
+		if utxo_list.contains(&utxo) {
+			return;
+		}
+		let position = utxo_list.partition_point(|other| other.output_index < utxo.output_index);
+		utxo_list.insert(position, utxo);
+		self.insert(&utxo.txid, utxo_list);
+	}
+	/// Returns the UTxO with the given output index of the given transaction, if it is unspent
+	pub fn get_utxo(&self, txid: &[u8; 32], output_index: usize) -> Option<UTXO> {
+		self.get(txid)?.into_iter().find(|utxo| utxo.output_index == output_index)
+	}
 	/// Removes all the UTXOs related with some TxID
 	pub fn remove(&self, txid: &[u8; 32]) {
 		self.utxo_set.remove(txid).expect("Unable to remove TxID");
@@ -66,9 +100,6 @@ impl UTXODB {
 }
 impl Default for UTXODB {
 	fn default() -> Self {
-		let db = sled::open(format!("{}/blockchain/utxo-set", BaseDirectory::get_base_directory())).unwrap(); // FIXME: Change the file for the actual Db location
-		Self {
-			utxo_set: db,
-		}
+		Self::open(&BaseDirectory::get_base_directory())
 	}
 }
\ No newline at end of file
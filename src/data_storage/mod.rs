@@ -9,6 +9,7 @@ use crate::core::parameters::COIN_NAME;
 
 pub mod blockchain_storage;
 pub mod node_config_storage;
+pub mod peer_storage;
 
 lazy_static! {
 	static ref BASE_DIRECTORY: Mutex<String> = {
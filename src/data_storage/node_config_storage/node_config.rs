@@ -18,9 +18,52 @@ pub struct NodeConfig {
 	pub listing_port: u16,
 	pub http_scheme: HttpScheme,
 	pub max_peers: usize,
+	/// The maximum amount of peers that can pair up with us from the same /24 IPv4 or /48 IPv6 network,
+	/// so that a single party can not take over the peer list
+	#[serde(default = "default_max_peers_per_subnet")]
+	pub max_peers_per_subnet: usize,
 	/// The amount of peers that will cycle each time
 	pub peer_cycle_count: usize,
 	pub trusted_peers: HashSet<PeerUrl>,
+	/// The amount of random peers to which each new transaction or block is announced
+	#[serde(default = "default_gossip_fanout")]
+	pub gossip_fanout: usize,
+	/// For how long a misbehaving peer is banned, in seconds
+	#[serde(default = "default_ban_duration")]
+	pub ban_duration: u64,
+	/// The amount of requests that a remote IP can send at once before being rate limited
+	#[serde(default = "default_rate_limit_burst")]
+	pub rate_limit_burst: u32,
+	/// The amount of requests per second that a remote IP can sustain
+	#[serde(default = "default_rate_limit_per_second")]
+	pub rate_limit_per_second: u32,
+	/// The maximum amount of requests of a remote IP that are handled at once
+	#[serde(default = "default_max_concurrent_requests")]
+	pub max_concurrent_requests: usize,
+	/// The maximum amount of connections accepted by each worker of the server
+	#[serde(default = "default_max_connections")]
+	pub max_connections: usize,
+}
+fn default_max_peers_per_subnet() -> usize {
+	4
+}
+fn default_gossip_fanout() -> usize {
+	8
+}
+fn default_ban_duration() -> u64 {
+	24 * 60 * 60
+}
+fn default_rate_limit_burst() -> u32 {
+	100
+}
+fn default_rate_limit_per_second() -> u32 {
+	20
+}
+fn default_max_concurrent_requests() -> usize {
+	8
+}
+fn default_max_connections() -> usize {
+	256
 }
 
 impl NodeConfig {
@@ -49,8 +92,15 @@ impl Default for NodeConfig {
 			listing_port: DEFAULT_PORT,
 			http_scheme: HttpScheme::HTTP,
 			max_peers: 128,
+			max_peers_per_subnet: default_max_peers_per_subnet(),
 			peer_cycle_count: 8,
 			trusted_peers: Default::default(),
+			gossip_fanout: default_gossip_fanout(),
+			ban_duration: default_ban_duration(),
+			rate_limit_burst: default_rate_limit_burst(),
+			rate_limit_per_second: default_rate_limit_per_second(),
+			max_concurrent_requests: default_max_concurrent_requests(),
+			max_connections: default_max_connections(),
 		}
 	}
 }
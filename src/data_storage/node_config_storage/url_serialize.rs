@@ -1,22 +1,141 @@
-use std::fmt::Formatter;
+use std::fmt::{Display, Formatter};
+use std::net::{IpAddr, SocketAddr};
 use std::str::FromStr;
 
 use reqwest::Url;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use serde::de::{Error, Visitor};
 
-#[derive(Clone, Eq, PartialEq, Hash)]
+use crate::network::models::HttpScheme;
+
+/// Why an address can not be used to reach a peer
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum AddressError {
+	Unparsable,
+	UnsupportedScheme,
+	MissingHost,
+	/// Unspecified, multicast and broadcast addresses do not point to a single node
+	UnroutableHost,
+	InvalidPort,
+	/// Credentials, paths, queries and fragments are not part of the address of a node
+	UnexpectedParts,
+}
+impl Display for AddressError {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		let str = match self {
+			AddressError::Unparsable => "The address is not a valid URL",
+			AddressError::UnsupportedScheme => "The scheme must be http or https",
+			AddressError::MissingHost => "The address has no host",
+			AddressError::UnroutableHost => "The host does not point to a single node",
+			AddressError::InvalidPort => "The port must not be 0",
+			AddressError::UnexpectedParts => "The address must only have a scheme, a host and a port",
+		};
+		write!(f, "{}", str)
+	}
+}
+
+/// The address of a node. Addresses are always checked and canonical:
+/// `scheme://host:port/` with a lowercase host without trailing dot, IPv4 addresses instead of IPv4-mapped IPv6 addresses
+/// and no default port, so two forms of the same address are equal
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
 pub struct PeerUrl(Url);
 impl PeerUrl {
-	pub fn new(url: Url) -> Self {
-		Self(url)
+	/// Parses an address given by a user or another node
+	pub fn parse(address: &str) -> Result<Self, AddressError> {
+		Self::from_url(Url::parse(address.trim()).map_err(|_| AddressError::Unparsable)?)
 	}
-	pub fn from_url(url: Url) -> Self {
-		Self(url)
+	/// Checks the URL and turns it into its canonical form
+	pub fn from_url(url: Url) -> Result<Self, AddressError> {
+		if url.scheme() != "http" && url.scheme() != "https" {
+			return Err(AddressError::UnsupportedScheme);
+		}
+		if !url.username().is_empty() || url.password().is_some() || url.path() != "/" || url.query().is_some() || url.fragment().is_some() {
+			return Err(AddressError::UnexpectedParts);
+		}
+		let host = match Self::host_ip(&url) {
+			Some(ip) => match Self::routable(canonical_ip(ip))? {
+				IpAddr::V4(ip) => ip.to_string(),
+				IpAddr::V6(ip) => format!("[{}]", ip),
+			},
+			None => {
+				let domain = url.host_str().unwrap_or_default().trim_end_matches('.');
+				if domain.is_empty() {
+					return Err(AddressError::MissingHost);
+				}
+				domain.to_lowercase()
+			}
+		};
+		let port = url.port_or_known_default().ok_or(AddressError::InvalidPort)?;
+		if port == 0 {
+			return Err(AddressError::InvalidPort);
+		}
+		Url::parse(&format!("{}://{}:{}/", url.scheme(), host, port)).map(Self).map_err(|_| AddressError::Unparsable)
+	}
+	/// The address on which a node listens, given the address it connects from and the port it claims to listen on
+	pub fn from_socket(scheme: HttpScheme, ip: IpAddr, port: u16) -> Result<Self, AddressError> {
+		Self::from_host(scheme, &SocketAddr::new(canonical_ip(ip), port).to_string())
+	}
+	/// The address of a node given the host by which it was reached, with the port if it is not the default one,
+	/// like the `Host` header of a request sent to it
+	pub fn from_host(scheme: HttpScheme, host: &str) -> Result<Self, AddressError> {
+		let scheme = match scheme {
+			HttpScheme::HTTP => "http",
+			HttpScheme::HTTPS => "https",
+		};
+		Self::parse(&format!("{}://{}/", scheme, host))
 	}
 	pub fn to_url(&self) -> Url {
 		self.0.clone()
 	}
+	/// The IP of the node, or None if it is known by a DNS name
+	pub fn ip(&self) -> Option<IpAddr> {
+		Self::host_ip(&self.0)
+	}
+	pub fn port(&self) -> Option<u16> {
+		self.0.port_or_known_default()
+	}
+	/// Returns whether the address points to a node listening on `port` in this machine, which has the IPs `own_ips`.
+	/// A node behind another name can only be recognized by its node id
+	pub fn points_to(&self, own_ips: &[IpAddr], port: u16) -> bool {
+		let is_own_host = match self.ip() {
+			Some(ip) => ip.is_loopback() || own_ips.contains(&ip),
+			None => self.0.host_str() == Some("localhost"),
+		};
+		is_own_host && self.port() == Some(port)
+	}
+	fn host_ip(url: &Url) -> Option<IpAddr> {
+		url.host_str()?.trim_start_matches('[').trim_end_matches(']').parse().ok()
+	}
+	fn routable(ip: IpAddr) -> Result<IpAddr, AddressError> {
+		let is_broadcast = match ip {
+			IpAddr::V4(ip) => ip.is_broadcast(),
+			IpAddr::V6(_) => false,
+		};
+		if ip.is_unspecified() || ip.is_multicast() || is_broadcast {
+			return Err(AddressError::UnroutableHost);
+		}
+		Ok(ip)
+	}
+}
+impl FromStr for PeerUrl {
+	type Err = AddressError;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		Self::parse(s)
+	}
+}
+impl Display for PeerUrl {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		write!(f, "{}", self.0)
+	}
+}
+
+/// Turns IPv4-mapped IPv6 addresses, like the ones of IPv4 connections to a dual-stack socket, into IPv4 addresses
+pub fn canonical_ip(ip: IpAddr) -> IpAddr {
+	match ip {
+		IpAddr::V6(v6) => v6.to_ipv4_mapped().map_or(ip, IpAddr::V4),
+		IpAddr::V4(_) => ip,
+	}
 }
 impl Serialize for PeerUrl {
 	fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error> where S: Serializer {
@@ -32,18 +151,10 @@ impl<'de> Visitor<'de> for UrlVisitor {
 	}
 
 	fn visit_string<E>(self, v: String) -> Result<Self::Value, E> where E: Error {
-		if let Ok(url) = Url::from_str(&v) {
-			Ok(PeerUrl(url))
-		} else {
-			Err(Error::custom("Unable to parse string to url"))
-		}
+		self.visit_str(&v)
 	}
 	fn visit_str<E>(self, v: &str) -> Result<Self::Value, E> where E: Error {
-		if let Ok(url) = Url::from_str(v) {
-			Ok(PeerUrl(url))
-		} else {
-			Err(Error::custom("Unable to parse string to url"))
-		}
+		PeerUrl::parse(v).map_err(|err| Error::custom(format!("Unable to parse string to url: {}", err)))
 	}
 }
 impl<'de> Deserialize<'de> for PeerUrl {
@@ -0,0 +1,234 @@
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use sled::Db;
+
+use crate::data_storage::node_config_storage::url_serialize::{canonical_ip, PeerUrl};
+use crate::network::standard::{standard_deserialize, standard_serialize};
+
+/// The scheme of the keys of the peers known only by their host
+const HOST_SCHEME: &str = "host";
+/// The misbehaviour score at which a peer is banned
+pub const BAN_THRESHOLD: u32 = 100;
+/// The maximum amount of stored records. When it is exceeded, the least valuable records make room for the new one
+pub const MAX_RECORDS: usize = 4096;
+/// The amount of records removed at once when the address book is full, so that the records are not scanned on every insert
+pub const EVICTION_BATCH: usize = MAX_RECORDS / 16;
+
+/// What a peer did wrong. Each kind adds a different penalty to its misbehaviour score
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Misbehaviour {
+	/// Sent a block that can never be valid
+	InvalidBlock,
+	/// Sent headers that can never be a valid chain
+	InvalidHeaders,
+	/// Sent a message that could not be decoded
+	MalformedMessage,
+}
+impl Misbehaviour {
+	pub fn penalty(&self) -> u32 {
+		match self {
+			Misbehaviour::InvalidBlock => BAN_THRESHOLD,
+			Misbehaviour::InvalidHeaders => BAN_THRESHOLD / 2,
+			Misbehaviour::MalformedMessage => BAN_THRESHOLD / 5,
+		}
+	}
+}
+
+/// What is known about a peer
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct PeerRecord {
+	/// The unix time in seconds of the last successful request to the peer
+	pub last_seen: Option<u64>,
+	/// The moving average of the time that the peer takes to answer, in milliseconds
+	pub latency_millis: Option<u64>,
+	pub successes: u64,
+	pub failures: u64,
+	/// Grows with every misbehaviour. It is reset when the peer is banned
+	pub misbehaviour: u32,
+	/// The unix time in seconds until which the peer is banned
+	pub banned_until: Option<u64>,
+}
+impl PeerRecord {
+	pub fn is_banned(&self, now: u64) -> bool {
+		self.banned_until.is_some_and(|banned_until| banned_until > now)
+	}
+	/// Higher is better. Peers that answer reliably come first
+	fn score(&self) -> i64 {
+		self.successes as i64 - 2 * self.failures as i64
+	}
+}
+
+/// Stores the peers that the node has known, so that it can connect to them again after restarting,
+/// and bans the peers that misbehave. Peers that are only known by their host (like the sender of a request) are stored with the `host` scheme.
+/// At most `MAX_RECORDS` records are stored
+#[derive(Clone)]
+pub struct AddressBook {
+	peers_db: Db,
+	ban_duration: Duration,
+	/// The amount of stored records, kept apart as counting the records of the database is slow
+	record_count: Arc<AtomicUsize>,
+}
+impl AddressBook {
+	/// Opens the address book stored in the given base directory
+	pub fn open(base_directory: &str, ban_duration: Duration) -> Self {
+		let peers_db = sled::open(format!("{}/node/address-book-db", base_directory)).expect("Unable to open / create address book");
+		let record_count = Arc::new(AtomicUsize::new(peers_db.len()));
+		Self {
+			peers_db,
+			ban_duration,
+			record_count,
+		}
+	}
+	pub fn get(&self, peer: &PeerUrl) -> Option<PeerRecord> {
+		self.get_record(&peer_key(peer))
+	}
+	fn get_record(&self, key: &str) -> Option<PeerRecord> {
+		let data = self.peers_db.get(key).ok()??;
+		standard_deserialize(&data).map_err(|err| log::error!("Unable to deserialize peer record: {}", err)).ok()
+	}
+	/// Adds the peer if it is not known yet
+	pub fn add(&self, peer: &PeerUrl) {
+		if self.get(peer).is_none() {
+			self.update(&peer_key(peer), |_| {});
+		}
+	}
+	/// Records that the peer answered in the given time
+	pub fn record_success(&self, peer: &PeerUrl, latency: Duration) {
+		let latency = latency.as_millis() as u64;
+		self.update(&peer_key(peer), |record| {
+			record.successes += 1;
+			record.last_seen = Some(now());
+			record.latency_millis = Some(record.latency_millis.map_or(latency, |average| (3 * average + latency) / 4));
+		});
+	}
+	/// Records that the peer did not answer or failed to answer
+	pub fn record_failure(&self, peer: &PeerUrl) {
+		self.update(&peer_key(peer), |record| record.failures += 1);
+	}
+	/// Penalizes the peer for misbehaving. Returns whether it has been banned
+	pub fn record_misbehaviour(&self, peer: &PeerUrl, misbehaviour: Misbehaviour) -> bool {
+		self.record_key_misbehaviour(&peer_key(peer), misbehaviour)
+	}
+	fn record_key_misbehaviour(&self, key: &str, misbehaviour: Misbehaviour) -> bool {
+		let ban_duration = self.ban_duration.as_secs();
+		let record = self.update(key, |record| {
+			record.misbehaviour += misbehaviour.penalty();
+			if record.misbehaviour >= BAN_THRESHOLD {
+				record.misbehaviour = 0;
+				record.banned_until = Some(now() + ban_duration);
+			}
+		});
+		record.is_banned(now())
+	}
+	pub fn is_banned(&self, peer: &PeerUrl) -> bool {
+		self.get(peer).is_some_and(|record| record.is_banned(now())) || self.is_host_banned(peer.to_url().host_str().unwrap_or_default())
+	}
+	/// Returns whether the peer known only by the given host is banned
+	pub fn is_host_banned(&self, host: &str) -> bool {
+		host_key(host).is_some_and(|key| self.get_record(&key).is_some_and(|record| record.is_banned(now())))
+	}
+	/// Penalizes a peer known only by its host, like the sender of a request. Returns whether it has been banned
+	pub fn record_host_misbehaviour(&self, host: &str, misbehaviour: Misbehaviour) -> bool {
+		host_key(host).is_some_and(|key| self.record_key_misbehaviour(&key, misbehaviour))
+	}
+	/// Returns at most `n` peers that are not banned, the most reliable and fastest first.
+	/// Peers known only by their host are not returned as they can not be connected to
+	pub fn best_peers(&self, n: usize) -> Vec<PeerUrl> {
+		let now = now();
+		let mut peers: Vec<(PeerUrl, PeerRecord)> = self.peers_db.iter()
+			.filter_map(|entry| {
+				let (key, data) = entry.ok()?;
+				let peer = PeerUrl::parse(std::str::from_utf8(&key).ok()?).ok()?; // Keys of hosts are not valid addresses
+				let record: PeerRecord = standard_deserialize(&data).ok()?;
+				Some((peer, record))
+			})
+			.filter(|(peer, record)| !record.is_banned(now) && !self.is_banned(peer))
+			.collect();
+		peers.sort_by_key(|(_, record)| (std::cmp::Reverse(record.score()), record.latency_millis.unwrap_or(u64::MAX)));
+		peers.into_iter().take(n).map(|(peer, _)| peer).collect()
+	}
+	/// Applies the change to the record with the given key atomically, creating it if it does not exist, and returns the new record.
+	/// The change may be applied more than once if the record is updated concurrently
+	fn update(&self, key: &str, change: impl Fn(&mut PeerRecord)) -> PeerRecord {
+		let mut is_new = false;
+		let result = self.peers_db.update_and_fetch(key, |data| {
+			is_new = data.is_none();
+			let mut record = data.and_then(|data| standard_deserialize(data).ok()).unwrap_or_default();
+			change(&mut record);
+			match standard_serialize(&record) {
+				Ok(data) => Some(data),
+				Err(err) => {
+					log::error!("Unable to serialize peer record: {}", err);
+					data.map(|data| data.to_vec()) // Keep the record as it was
+				}
+			}
+		});
+		if is_new && self.record_count.fetch_add(1, Ordering::Relaxed) >= MAX_RECORDS {
+			self.evict_except(key);
+		}
+		match result {
+			Ok(data) => data.and_then(|data| standard_deserialize(&data).ok()).unwrap_or_default(),
+			Err(err) => {
+				log::error!("Unable to store peer record: {}", err);
+				PeerRecord::default()
+			}
+		}
+	}
+	/// Removes the `EVICTION_BATCH` least valuable records other than the one with the given key: banned peers are kept the longest,
+	/// then the most reliable peers and then the most recently seen ones
+	fn evict_except(&self, key: &str) {
+		let now = now();
+		let mut records: Vec<_> = self.peers_db.iter()
+			.filter_map(|entry| {
+				let (entry_key, data) = entry.ok()?;
+				let record: PeerRecord = standard_deserialize(&data).ok()?;
+				Some((entry_key, (record.is_banned(now), record.score(), record.last_seen.unwrap_or(0))))
+			})
+			.filter(|(entry_key, _)| entry_key.as_ref() != key.as_bytes())
+			.collect();
+		if records.len() > EVICTION_BATCH {
+			records.select_nth_unstable_by_key(EVICTION_BATCH, |(_, value)| *value);
+			records.truncate(EVICTION_BATCH);
+		}
+		for (evicted_key, _) in records {
+			match self.peers_db.remove(evicted_key) {
+				Ok(Some(_)) => {
+					self.record_count.fetch_sub(1, Ordering::Relaxed);
+				}
+				Ok(None) => {}
+				Err(err) => log::error!("Unable to remove peer record: {}", err),
+			}
+		}
+	}
+	/// Returns the amount of stored records
+	pub fn len(&self) -> usize {
+		self.record_count.load(Ordering::Relaxed)
+	}
+}
+
+/// The key of the record of a peer: its canonical address
+fn peer_key(peer: &PeerUrl) -> String {
+	peer.to_url().to_string()
+}
+/// The key of the record of a peer known only by its host, with the host in the same canonical form as in `PeerUrl`
+fn host_key(host: &str) -> Option<String> {
+	let host = host.trim().trim_start_matches('[').trim_end_matches(']');
+	let host = match host.parse::<IpAddr>() {
+		Ok(ip) => match canonical_ip(ip) {
+			IpAddr::V4(ip) => ip.to_string(),
+			IpAddr::V6(ip) => format!("[{}]", ip),
+		},
+		Err(_) => host.trim_end_matches('.').to_lowercase(),
+	};
+	if host.is_empty() || host.contains(['/', '?', '#', '@', ' ']) {
+		return None;
+	}
+	Some(format!("{}://{}", HOST_SCHEME, host))
+}
+fn now() -> u64 {
+	SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards").as_secs()
+}
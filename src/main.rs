@@ -1,17 +1,19 @@
 use std::collections::HashSet;
 use std::fs::{File, read_to_string};
+use std::path::PathBuf;
 use std::time::{Duration, Instant};
 
 use clap::Parser;
-use reqwest::Url;
 use rsntp::{AsyncSntpClient, Config, SntpClient};
 
 use crate::args::{Cli, Commands};
-use crate::core::parameters::Parameters;
+use crate::core::genesis::GenesisSpec;
 use crate::data_storage::node_config_storage::node_config::NodeConfig;
 use crate::data_storage::node_config_storage::url_serialize::PeerUrl;
 use crate::network::models::HttpScheme;
+use crate::network::light_node::LightNode;
 use crate::network::node::{Node};
+use crate::network::protocol::PROTOCOL_VERSION;
 
 // TODO: Check that this is cool https://github.com/advisories/GHSA-r8w9-5wcg-vfj7
 pub mod crypto;
@@ -31,24 +33,43 @@ async fn main() {
 	let cli = Cli::parse();
 	match cli.commands {
 		Commands::StartNode(start_node) => {
-			let mut trusted_peers = HashSet::new();
-			if let Some(path) = start_node.trusted_peers_file {
-				if let Ok(str) = read_to_string(path) {
-
-					let lines = str.lines();
-					for line in lines {
-						if let Ok(url) = Url::parse(line) {
-							trusted_peers.insert(PeerUrl::new(url));
-						} else {
-							log::error!("Unable to parse to URL string {:?} from trusted peers file", line);
-						}
-					}
-				} else {
-					log::error!("Unable to load trusted peer file");
+			let trusted_peers = load_trusted_peers(start_node.trusted_peers_file);
+			let Some(genesis) = load_genesis(start_node.genesis_file) else {
+				return;
+			};
+
+			let mut node = match Node::new(PROTOCOL_VERSION, None, genesis).await {
+				Ok(node) => node,
+				Err(err) => {
+					log::error!("Unable to start the node: {}", err);
+					return;
 				}
+			};
+			for peer in &trusted_peers {
+				node.address_book.add(peer);
 			}
+			node.config.trusted_peers.extend(trusted_peers); // Paired up with on the first discovery
+			node.start();
+
+			tokio::signal::ctrl_c().await.unwrap();
+			log::info!("Shutting down program...");
+			node.shutdown().await;
+			log::info!("Program exited successfully");
+		}
+		Commands::StartLightClient(start_light_client) => {
+			let trusted_peers = load_trusted_peers(start_light_client.trusted_peers_file);
+			let Some(genesis) = load_genesis(start_light_client.genesis_file) else {
+				return;
+			};
 
-			let mut node = Node::new(0, None, Parameters::default()).await;
+			let mut node = match LightNode::new(PROTOCOL_VERSION, None, genesis).await {
+				Ok(node) => node,
+				Err(err) => {
+					log::error!("Unable to start the node: {}", err);
+					return;
+				}
+			};
+			node.peers.write().await.extend(trusted_peers);
 			node.start();
 
 			tokio::signal::ctrl_c().await.unwrap();
@@ -56,5 +77,59 @@ async fn main() {
 			node.shutdown().await;
 			log::info!("Program exited successfully");
 		}
+		Commands::VerifyTransaction(verify_transaction) => {
+			let trusted_peers = load_trusted_peers(verify_transaction.trusted_peers_file);
+			let Some(genesis) = load_genesis(verify_transaction.genesis_file) else {
+				return;
+			};
+
+			let node = match LightNode::new(PROTOCOL_VERSION, None, genesis).await {
+				Ok(node) => node,
+				Err(err) => {
+					log::error!("Unable to start the node: {}", err);
+					return;
+				}
+			};
+			node.peers.write().await.extend(trusted_peers);
+			node.sync_headers().await;
+			match node.verify_transaction(verify_transaction.txid, verify_transaction.block_hash).await {
+				Some(confirmations) => log::info!("The transaction is in the block, with {} confirmations", confirmations),
+				None => log::error!("No peer proved that the transaction is in the block"),
+			}
+		}
+	}
+}
+
+/// Reads the peers of the trusted peers file, one URL per line
+fn load_trusted_peers(path: Option<PathBuf>) -> HashSet<PeerUrl> {
+	let mut trusted_peers = HashSet::new();
+	if let Some(path) = path {
+		if let Ok(str) = read_to_string(path) {
+			let lines = str.lines();
+			for line in lines {
+				match PeerUrl::parse(line) {
+					Ok(url) => {
+						trusted_peers.insert(url);
+					}
+					Err(err) => log::error!("Unable to parse to URL string {:?} from trusted peers file: {}", line, err),
+				}
+			}
+		} else {
+			log::error!("Unable to load trusted peer file");
+		}
+	}
+	trusted_peers
+}
+/// Loads the genesis specification from the file, or the one of the main network if not given
+fn load_genesis(path: Option<PathBuf>) -> Option<GenesisSpec> {
+	match path {
+		Some(path) => match GenesisSpec::load(&path) {
+			Ok(genesis) => Some(genesis),
+			Err(err) => {
+				log::error!("Unable to load genesis file {:?}. Error: {}", path, err);
+				None
+			}
+		},
+		None => Some(GenesisSpec::default()),
 	}
 }
@@ -0,0 +1,159 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
+use rand::{thread_rng, Rng};
+use serde::{Deserialize, Serialize};
+
+use crate::core::keys::identity::{NodeId, NodeIdentity};
+use crate::crypto::hash::hash;
+use crate::crypto::public_key::PublicKeyAlgorithm;
+use crate::data_storage::node_config_storage::url_serialize::PeerUrl;
+use crate::network::limits::host_key;
+use crate::network::models::HttpScheme;
+use crate::network::protocol::VersionInfo;
+use crate::network::standard::serialize_bincode;
+
+/// For how long an issued nonce can be answered
+pub const CHALLENGE_LIFETIME: Duration = Duration::from_secs(30);
+/// The maximum amount of nonces waiting for an answer. When it is reached, the oldest nonce is forgotten
+pub const MAX_PENDING_CHALLENGES: usize = 4096;
+/// The maximum amount of nonces waiting for an answer from a single host. When it is reached, the oldest nonce of the host is forgotten
+pub const MAX_CHALLENGES_PER_HOST: usize = 8;
+
+/// The domain of the proofs in `PairUp` messages
+pub const PAIR_UP_DOMAIN: &str = "pair-up";
+/// The domain of the proofs with which the receiver of a `PairUp` answers
+pub const PAIRED_UP_DOMAIN: &str = "paired-up";
+/// The domain of the proofs in `Unpair` messages
+pub const UNPAIR_DOMAIN: &str = "unpair";
+
+/// Proves that the sender of a message owns a node id, by signing a nonce chosen by the receiver together with the content of the message.
+/// The domain keeps a proof made for one kind of message from being valid for another
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct NodeProof {
+	pub(crate) public_key: Vec<u8>,
+	pub(crate) nonce: [u8; 32],
+	pub(crate) signature: Vec<u8>,
+}
+impl NodeProof {
+	pub fn create(identity: &NodeIdentity, domain: &str, nonce: [u8; 32], content: &[u8]) -> Self {
+		Self {
+			public_key: identity.public_key.clone(),
+			nonce,
+			signature: identity.sign(&Self::signed_hash(domain, &nonce, content)),
+		}
+	}
+	/// Returns the id of the node that made the proof, or None if the signature does not match
+	pub fn verify(&self, domain: &str, content: &[u8]) -> Option<NodeId> {
+		PublicKeyAlgorithm::verify(&self.public_key, &Self::signed_hash(domain, &self.nonce, content), &self.signature).ok()?;
+		Some(NodeId::from_public_key(&self.public_key))
+	}
+	fn signed_hash(domain: &str, nonce: &[u8; 32], content: &[u8]) -> [u8; 32] {
+		let mut data = Vec::with_capacity(domain.len() + 1 + nonce.len() + content.len());
+		data.extend_from_slice(domain.as_bytes());
+		data.push(0);
+		data.extend_from_slice(nonce);
+		data.extend_from_slice(content);
+		hash(&data)
+	}
+}
+
+/// A nonce waiting for an answer
+#[derive(Clone, Debug)]
+struct IssuedChallenge {
+	/// The host that requested the nonce
+	host: IpAddr,
+	expiry: Instant,
+	/// The position of the nonce in the order in which the nonces were issued
+	sequence: u64,
+}
+
+/// The nonces that the node has issued and not yet been answered. Each nonce can only be answered once and before it expires,
+/// so a signed message can not be replayed. A new nonce is always issued: the oldest nonce of the host, or else the oldest nonce,
+/// is forgotten to make room for it, so a host that requests many nonces only makes its own nonces expire sooner
+#[derive(Clone, Debug, Default)]
+pub struct ChallengeCache {
+	issued: HashMap<[u8; 32], IssuedChallenge>,
+	/// The nonces by the order in which they were issued
+	order: BTreeMap<u64, [u8; 32]>,
+	/// The sequences of the nonces issued to each host
+	by_host: HashMap<IpAddr, BTreeSet<u64>>,
+	next_sequence: u64,
+}
+impl ChallengeCache {
+	/// Creates a new nonce for the given IP
+	pub fn issue(&mut self, ip: IpAddr) -> [u8; 32] {
+		let host = host_key(ip);
+		let oldest_of_host = self.by_host.get(&host)
+			.filter(|sequences| sequences.len() >= MAX_CHALLENGES_PER_HOST)
+			.and_then(|sequences| sequences.first().copied());
+		if let Some(sequence) = oldest_of_host {
+			self.forget(sequence);
+		} else if self.issued.len() >= MAX_PENDING_CHALLENGES {
+			if let Some(sequence) = self.order.keys().next().copied() {
+				self.forget(sequence);
+			}
+		}
+		let nonce: [u8; 32] = thread_rng().gen();
+		let sequence = self.next_sequence;
+		self.next_sequence += 1;
+		self.issued.insert(nonce, IssuedChallenge {
+			host,
+			expiry: Instant::now() + CHALLENGE_LIFETIME,
+			sequence,
+		});
+		self.order.insert(sequence, nonce);
+		self.by_host.entry(host).or_default().insert(sequence);
+		nonce
+	}
+	/// Consumes the nonce. Returns whether it was issued by us and has not expired
+	pub fn redeem(&mut self, nonce: &[u8; 32]) -> bool {
+		self.remove(nonce).is_some_and(|issued| issued.expiry > Instant::now())
+	}
+	/// Forgets the nonce issued with the given sequence
+	fn forget(&mut self, sequence: u64) {
+		if let Some(nonce) = self.order.get(&sequence).copied() {
+			self.remove(&nonce);
+		}
+	}
+	fn remove(&mut self, nonce: &[u8; 32]) -> Option<IssuedChallenge> {
+		let issued = self.issued.remove(nonce)?;
+		self.order.remove(&issued.sequence);
+		if let Some(sequences) = self.by_host.get_mut(&issued.host) {
+			sequences.remove(&issued.sequence);
+			if sequences.is_empty() {
+				self.by_host.remove(&issued.host);
+			}
+		}
+		Some(issued)
+	}
+	pub fn len(&self) -> usize {
+		self.issued.len()
+	}
+	pub fn is_empty(&self) -> bool {
+		self.issued.is_empty()
+	}
+}
+
+/// The node to which a message with a proof is sent: the address by which the sender reached it and the public key of its identity.
+/// Proofs are bound to their receiver, so a node in the middle can not pass a nonce of a third node on and use the answer with it
+#[derive(Clone, Copy, Debug)]
+pub struct Receiver<'a> {
+	pub url: &'a PeerUrl,
+	pub public_key: &'a [u8],
+}
+impl Receiver<'_> {
+	fn encode(&self) -> (String, &[u8]) {
+		(self.url.to_string(), self.public_key)
+	}
+}
+
+/// The part of a `PairUp` covered by its proof
+pub fn pair_up_content(version: u32, info: &VersionInfo, method: HttpScheme, port: u16, challenge: &[u8; 32], receiver: Receiver) -> Vec<u8> {
+	serialize_bincode(&(version, info, method, port, challenge, receiver.encode())).expect("Unable to serialize pair up")
+}
+/// The part of an `Unpair` covered by its proof
+pub fn unpair_content(version: u32, method: HttpScheme, port: u16, receiver: Receiver) -> Vec<u8> {
+	serialize_bincode(&(version, method, port, receiver.encode())).expect("Unable to serialize unpair")
+}
@@ -5,9 +5,12 @@ use bincode::{Decode, Encode};
 use log::{debug};
 use serde::{Deserialize, Serialize};
 
+use crate::core::parameters::NetworkParameters;
+use crate::network::limits::PayloadLimit;
 use crate::network::routes::{handshake, p2p, pull_based, push_based};
 
 pub const VERSION_URL: &str = "/version";
+pub const CHALLENGE_URL: &str = "/challenge";
 pub const GET_BLOCKCHAIN_INFO_URL: &str = "/get-blockchain-info";
 pub const NEW_TRANSACTION_URL: &str = "/tx";
 pub const NEW_BLOCK_URL: &str = "/block";
@@ -18,19 +21,33 @@ pub const GET_BLOCKS_URL: &str = "/get-blocks";
 pub const GET_DATA_URL: &str = "/get-data";
 
 pub const GET_HEADERS_URL: &str = "/get-headers";
-pub fn config_routes(config: &mut ServiceConfig) {
+pub const ESTIMATE_FEE_URL: &str = "/estimate-fee";
+pub const GET_TX_PROOF_URL: &str = "/get-tx-proof";
+pub const INV_URL: &str = "/inv";
+/// The maximum amount of items that can be requested at once to `/get-data`
+pub const MAX_GET_DATA_ITEMS: usize = 512;
+/// Registers the routes of the node. Transactions and blocks are limited to the sizes of the network parameters
+pub fn config_routes(config: &mut ServiceConfig, network_parameters: &NetworkParameters) {
 	config
 		.route("/test", web::post().to(test))
 		.route(VERSION_URL, web::get().to(handshake::handle_version))
+		.route(CHALLENGE_URL, web::get().to(handshake::handle_challenge))
 		.route(GET_BLOCKCHAIN_INFO_URL, web::get().to(pull_based::handle_get_blockchain_info))
-		.route(NEW_TRANSACTION_URL, web::post().to(push_based::handle_tx))
-		.route(NEW_BLOCK_URL, web::post().to(push_based::handle_block))
+		.service(web::resource(NEW_TRANSACTION_URL)
+			.app_data(PayloadLimit::transactions(network_parameters))
+			.route(web::post().to(push_based::handle_tx)))
+		.service(web::resource(NEW_BLOCK_URL)
+			.app_data(PayloadLimit::blocks(network_parameters))
+			.route(web::post().to(push_based::handle_block)))
+		.route(INV_URL, web::post().to(push_based::handle_inv))
 		.route(PAIR_UP_URL, web::post().to(p2p::handle_pair_up))
 		.route(UNPAIR_URL, web::delete().to(p2p::handle_unpair))
 		.route(GET_PEERS_URL, web::get().to(p2p::handle_get_peers))
 		.route(GET_BLOCKS_URL, web::get().to(pull_based::handle_get_blocks))
 		.route(GET_DATA_URL, web::get().to(pull_based::handle_get_data))
-		.route(GET_HEADERS_URL, web::get().to(pull_based::handle_get_headers));
+		.route(GET_HEADERS_URL, web::get().to(pull_based::handle_get_headers))
+		.route(ESTIMATE_FEE_URL, web::get().to(pull_based::handle_estimate_fee))
+		.route(GET_TX_PROOF_URL, web::get().to(pull_based::handle_get_tx_proof));
 }
 
 // #[derive(Clone, Deserialize, Serialize)]
@@ -0,0 +1,58 @@
+use std::collections::{HashSet, VecDeque};
+
+use rand::seq::IteratorRandom;
+use rand::thread_rng;
+
+/// The amount of transaction ids and block hashes remembered by the seen cache
+pub const SEEN_CACHE_CAPACITY: usize = 16384;
+
+/// The hashes of the items that were already received or relayed, so that they are not requested nor relayed again.
+/// When it is full, the oldest hashes are forgotten
+#[derive(Clone, Debug)]
+pub struct SeenCache {
+	capacity: usize,
+	order: VecDeque<[u8; 32]>,
+	hashes: HashSet<[u8; 32]>,
+}
+impl SeenCache {
+	pub fn new(capacity: usize) -> Self {
+		Self {
+			capacity,
+			order: VecDeque::with_capacity(capacity),
+			hashes: HashSet::with_capacity(capacity),
+		}
+	}
+	/// Remembers the hash. Returns whether it had not been seen before
+	pub fn insert(&mut self, hash: [u8; 32]) -> bool {
+		if self.capacity == 0 || !self.hashes.insert(hash) {
+			return false;
+		}
+		self.order.push_back(hash);
+		if self.order.len() > self.capacity {
+			if let Some(oldest) = self.order.pop_front() {
+				self.hashes.remove(&oldest);
+			}
+		}
+		true
+	}
+	pub fn contains(&self, hash: &[u8; 32]) -> bool {
+		self.hashes.contains(hash)
+	}
+	pub fn len(&self) -> usize {
+		self.order.len()
+	}
+	pub fn is_empty(&self) -> bool {
+		self.order.is_empty()
+	}
+}
+impl Default for SeenCache {
+	fn default() -> Self {
+		Self::new(SEEN_CACHE_CAPACITY)
+	}
+}
+
+/// Chooses at most `fanout` random peers to relay an item to
+pub fn choose_fanout<'a, T>(peers: impl IntoIterator<Item = &'a T>, fanout: usize) -> Vec<T>
+	where T: Clone + 'a {
+	peers.into_iter().cloned().choose_multiple(&mut thread_rng(), fanout)
+}
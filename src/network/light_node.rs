@@ -0,0 +1,216 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use reqwest::Client;
+use rsntp::AsyncSntpClient;
+use tokio::sync::RwLock;
+
+use crate::core::genesis::GenesisSpec;
+use crate::core::light_chain::LightChain;
+use crate::core::parameters::Parameters;
+use crate::data_storage::node_config_storage::node_config::NodeConfig;
+use crate::data_storage::node_config_storage::url_serialize::PeerUrl;
+use crate::network::models::{GetHeaders, GetTxProof};
+use crate::network::protocol;
+use crate::network::protocol::{Features, MIN_PROTOCOL_VERSION, PeerProtocol, VersionInfo};
+use crate::network::sender::Sender;
+use crate::network::timing;
+
+/// A node that only follows the headers of the chain. It does not store blocks nor the UTxO set, does not forge
+/// and does not serve other nodes. Transactions are verified with merkle proofs requested from full peers
+#[derive(Clone)]
+pub struct LightNode {
+	/// The newest protocol version spoken by the node
+	pub version: u32,
+	/// The versions, features and chain sent to the peers in the handshake
+	pub version_info: VersionInfo,
+	/// The protocol negotiated with each peer
+	pub peer_protocols: Arc<RwLock<HashMap<PeerUrl, PeerProtocol>>>,
+	pub current_slot: Arc<AtomicU64>,
+	pub chain: Arc<RwLock<LightChain>>,
+	pub peers: Arc<RwLock<HashSet<PeerUrl>>>,
+	shutdown: Arc<AtomicBool>,
+	pub config: NodeConfig,
+	pub parameters: Parameters,
+	/// The unix time in seconds at which the slot 0 of the chain started
+	pub start_time: u64,
+}
+
+impl LightNode {
+	/// Creates a light node of the chain described by the given genesis specification.
+	/// If the chain has not started yet, waits until its start time
+	pub async fn new(version: u32, config_file: Option<String>, genesis: GenesisSpec) -> anyhow::Result<Self> {
+		let slot_duration = genesis.parameters.technical_parameters.slot_duration as u64;
+		let slot = timing::wait_for_current_slot(&AsyncSntpClient::new(), slot_duration, genesis.start_time).await
+			.map_err(|err| anyhow::anyhow!("Unable to sync with NTP server: {}", err))?;
+		let config = NodeConfig::load(config_file);
+		let peers = config.trusted_peers.clone();
+		let parameters = genesis.parameters;
+		let current_slot = Arc::new(AtomicU64::new(slot));
+		let mut chain = LightChain::init(&genesis)?;
+		chain.set_current_slot(current_slot.clone());
+		let version_info = VersionInfo {
+			min_version: MIN_PROTOCOL_VERSION.min(version),
+			max_version: version,
+			features: Features::none(),
+			chain_id: chain.get_chain_id(),
+		};
+		Ok(Self {
+			version,
+			version_info,
+			peer_protocols: Arc::new(RwLock::new(HashMap::new())),
+			current_slot,
+			chain: Arc::new(RwLock::new(chain)),
+			peers: Arc::new(RwLock::new(peers)),
+			shutdown: Arc::new(AtomicBool::new(false)),
+			config,
+			parameters,
+			start_time: genesis.start_time,
+		})
+	}
+	pub fn start(&mut self) {
+		log::info!("Starting the light node");
+		let mut self_clone = self.clone();
+		tokio::spawn(async move {
+			self_clone.main_loop().await;
+		});
+		log::info!("Started main loop thread");
+	}
+	pub fn get_current_slot(&self) -> u64 {
+		self.current_slot.load(Ordering::Relaxed)
+	}
+	/// Keeps the clock and the headers up to date. The clock is re-synced with the NTP server like the one of the full node
+	pub async fn main_loop(&mut self) {
+		const SYNC_CHECK_TIME: u32 = 5u32; // In seconds
+		const SECONDS_PER_RE_SYNC: u32 = 128;
+		let ntp_client = AsyncSntpClient::new();
+		let slot_duration = Duration::from_millis(self.parameters.technical_parameters.slot_duration as u64);
+		let (mut started, mut start_slot) = (Instant::now(), self.get_current_slot());
+		let mut sync_counter = SYNC_CHECK_TIME;
+		let mut re_sync_counter = 0;
+		while !self.is_shutdown() {
+			re_sync_counter += 1;
+			if re_sync_counter % SECONDS_PER_RE_SYNC == 0 {
+				timing::resync_slot(&ntp_client, &self.current_slot, slot_duration.as_millis() as u64, self.start_time).await;
+				(started, start_slot) = (Instant::now(), self.get_current_slot());
+			} else {
+				let elapsed_slots = started.elapsed().as_millis() / slot_duration.as_millis();
+				self.current_slot.store(start_slot + elapsed_slots as u64, Ordering::Relaxed);
+			}
+			if sync_counter >= SYNC_CHECK_TIME {
+				sync_counter = 0;
+				self.sync_headers().await;
+			}
+			sync_counter += 1;
+			tokio::time::sleep(Duration::from_secs(1)).await;
+		}
+	}
+	/// Requests headers from the highest peer until no more progress is made
+	pub async fn sync_headers(&self) {
+		let client = Client::new();
+		protocol::handshake_with_peers(&client, &self.version_info, &self.peers, &self.peer_protocols).await;
+		while let Some(best_peer) = self.get_best_peer_ahead(&client).await {
+			if !self.sync_round(&client, &best_peer).await {
+				break;
+			}
+		}
+	}
+	/// Returns the peer that serves the chain with the highest chain, if it is higher than ours
+	async fn get_best_peer_ahead(&self, client: &Client) -> Option<PeerUrl> {
+		const INFO_TIMEOUT: Duration = Duration::from_millis(500);
+		let peers = self.peers_with(Features::FULL_CHAIN).await;
+		let current_height = self.chain.read().await.get_height();
+
+		let mut handles = vec![];
+		for peer in peers {
+			let client = client.clone();
+			handles.push(tokio::spawn(async move {
+				match tokio::time::timeout(INFO_TIMEOUT, Sender::get_blockchain_info(&client, peer.to_url())).await {
+					Ok(Ok(info)) => Some((peer, info.height)),
+					_ => None,
+				}
+			}));
+		}
+		let mut best: Option<(PeerUrl, usize)> = None;
+		for h in handles {
+			if let Ok(Some((peer, height))) = h.await {
+				if height > current_height && best.as_ref().is_none_or(|(_, best_height)| height > *best_height) {
+					best = Some((peer, height));
+				}
+			}
+		}
+		best.map(|(peer, _)| peer)
+	}
+	/// Downloads and adds one batch of headers. Returns whether some progress was made
+	async fn sync_round(&self, client: &Client, peer: &PeerUrl) -> bool {
+		const HEADERS_TIMEOUT: Duration = Duration::from_secs(5);
+		let locator = self.chain.read().await.get_block_locator();
+		let msg = GetHeaders {
+			version: self.version_for(peer).await,
+			block_locator_object: locator,
+		};
+		let headers = match tokio::time::timeout(HEADERS_TIMEOUT, Sender::get_headers(client, peer.to_url(), msg, &self.parameters.network_parameters)).await {
+			Ok(Ok(headers)) => headers.headers,
+			_ => {
+				log::warn!("Unable to get headers from peer {}", peer.to_url());
+				return false;
+			}
+		};
+		let mut chain = self.chain.write().await;
+		match chain.add_headers(&headers) {
+			Ok(0) => false,
+			Ok(added) => {
+				log::info!("Added {} headers, synced up to height {}", added, chain.get_height());
+				true
+			}
+			Err(err) => {
+				log::warn!("Peer {} sent an invalid header chain: {}", peer.to_url(), err);
+				false
+			}
+		}
+	}
+	/// Asks the peers for the merkle proof of the transaction in the given block and verifies it against our headers.
+	/// Returns the amount of confirmations of the transaction, or None if no peer proved that it is in the block
+	pub async fn verify_transaction(&self, txid: [u8; 32], block_hash: [u8; 32]) -> Option<usize> {
+		const PROOF_TIMEOUT: Duration = Duration::from_secs(5);
+		let client = Client::new();
+		protocol::handshake_with_peers(&client, &self.version_info, &self.peers, &self.peer_protocols).await;
+		let peers = self.peers_with(Features::TX_PROOFS).await;
+		for peer in peers {
+			let msg = GetTxProof {
+				version: self.version_for(&peer).await,
+				block_hash,
+				txid,
+			};
+			let proof = match tokio::time::timeout(PROOF_TIMEOUT, Sender::get_tx_proof(&client, peer.to_url(), msg)).await {
+				Ok(Ok(proof)) => proof,
+				_ => continue,
+			};
+			if let Some(confirmations) = self.chain.read().await.verify_transaction(&txid, &block_hash, &proof.proof) {
+				return Some(confirmations);
+			}
+			log::warn!("Peer {} sent an invalid proof for transaction {}", peer.to_url(), hex::encode(txid));
+		}
+		None
+	}
+	/// Returns the peers that offer the given features according to their handshake
+	async fn peers_with(&self, features: Features) -> Vec<PeerUrl> {
+		let protocols = self.peer_protocols.read().await;
+		self.peers.read().await.iter()
+			.filter(|peer| protocols.get(peer).is_some_and(|protocol| protocol.features.contains(features)))
+			.cloned()
+			.collect()
+	}
+	/// Returns the version negotiated with the peer, or our newest version if there was no handshake with it
+	pub async fn version_for(&self, peer: &PeerUrl) -> u32 {
+		self.peer_protocols.read().await.get(peer).map_or(self.version, |protocol| protocol.version)
+	}
+	pub async fn shutdown(&mut self) {
+		self.shutdown.store(true, Ordering::Relaxed);
+	}
+	pub fn is_shutdown(&self) -> bool {
+		self.shutdown.load(Ordering::Relaxed)
+	}
+}
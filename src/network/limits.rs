@@ -0,0 +1,176 @@
+use std::collections::{BTreeMap, HashMap};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use crate::core::blockchain::MAX_HEADERS;
+use crate::core::parameters::NetworkParameters;
+use crate::data_storage::node_config_storage::url_serialize::canonical_ip;
+use crate::network::models::http_errors::ErrorType;
+use crate::network::standard::WireFormat;
+
+/// Room for the envelope, the version and the lengths of a message, on top of the size of its content
+pub const ENVELOPE_OVERHEAD: usize = 1024;
+/// The maximum size of the body of the requests to routes without a specific limit
+pub const DEFAULT_PAYLOAD_LIMIT: usize = 64 * 1024;
+/// Room for the tag and the length of each item of a list, on top of the size of the item
+const ITEM_OVERHEAD: usize = 16;
+/// How many times larger a JSON message can be than its bincode encoding. Bytes are written as lists of numbers
+const JSON_EXPANSION: usize = 4;
+/// The amount of remote IPs tracked by the rate limiter, after which the least recently used one is forgotten
+pub const MAX_TRACKED_PEERS: usize = 4096;
+/// The length of the IPv6 prefix that shares a bucket. A single host usually gets a whole /64
+const IPV6_BUCKET_PREFIX: u32 = 64;
+/// The length of the IPv4 prefix of the networks that can only have a few peers
+const IPV4_SUBNET_PREFIX: u32 = 24;
+/// The length of the IPv6 prefix of the networks that can only have a few peers. A single site usually gets a whole /48
+const IPV6_SUBNET_PREFIX: u32 = 48;
+
+/// The maximum size of the bincode encoded content of a request, set per route as app data, or of the response to a message
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct PayloadLimit(pub usize);
+impl PayloadLimit {
+	pub fn transactions(network_parameters: &NetworkParameters) -> Self {
+		Self(network_parameters.max_tx_size)
+	}
+	pub fn blocks(network_parameters: &NetworkParameters) -> Self {
+		Self(network_parameters.max_block_header_size + network_parameters.max_block_body_size)
+	}
+	/// The limit of the headers sent in answer to a block locator
+	pub fn headers(network_parameters: &NetworkParameters) -> Self {
+		Self(MAX_HEADERS * (network_parameters.max_block_header_size + ITEM_OVERHEAD))
+	}
+	/// The limit of the bodies of the given amount of blocks
+	pub fn block_bodies(network_parameters: &NetworkParameters, count: usize) -> Self {
+		Self(count * (network_parameters.max_block_body_size + ITEM_OVERHEAD))
+	}
+	/// The limit of the given amount of transactions
+	pub fn transaction_list(network_parameters: &NetworkParameters, count: usize) -> Self {
+		Self(count * (network_parameters.max_tx_size + ITEM_OVERHEAD))
+	}
+	/// Returns the maximum size of a body in the given format
+	pub fn encoded_limit(&self, format: WireFormat) -> usize {
+		match format {
+			WireFormat::Bincode => self.0 + ENVELOPE_OVERHEAD,
+			WireFormat::Json => self.0 * JSON_EXPANSION + ENVELOPE_OVERHEAD,
+		}
+	}
+}
+impl Default for PayloadLimit {
+	fn default() -> Self {
+		Self(DEFAULT_PAYLOAD_LIMIT)
+	}
+}
+
+/// What the rate limiter knows about a remote IP
+struct PeerUsage {
+	tokens: f64,
+	/// The last time that the IP made a request, when its tokens were last refilled
+	last_refill: Instant,
+	in_flight: usize,
+	/// The number of the last request of the IP, its key in `TrackedPeers::by_last_use`
+	last_use: u64,
+}
+
+/// The IPs known by the rate limiter, with an index to find the least recently used one without scanning them all
+#[derive(Default)]
+struct TrackedPeers {
+	usage: HashMap<IpAddr, PeerUsage>,
+	/// The IPs by the number of their last request
+	by_last_use: BTreeMap<u64, IpAddr>,
+	/// The amount of requests seen, which numbers them
+	requests: u64,
+}
+
+/// Limits the requests of each remote IP with a token bucket: every request takes a token, and tokens are refilled
+/// at `refill_per_second` up to `burst`. It also limits the amount of requests of each IP that are being handled at once.
+/// IPv6 addresses of the same /64 network share a bucket, and IPv4-mapped addresses share the bucket of their IPv4 address
+#[derive(Clone)]
+pub struct RateLimiter {
+	burst: u32,
+	refill_per_second: u32,
+	max_in_flight: usize,
+	peers: Arc<Mutex<TrackedPeers>>,
+}
+impl RateLimiter {
+	pub fn new(burst: u32, refill_per_second: u32, max_in_flight: usize) -> Self {
+		Self {
+			burst,
+			refill_per_second,
+			max_in_flight,
+			peers: Arc::new(Mutex::new(TrackedPeers::default())),
+		}
+	}
+	/// Takes a token for a request from the IP. The request counts as being handled until the returned guard is dropped
+	pub fn acquire(&self, ip: IpAddr) -> Result<RequestGuard, ErrorType> {
+		let ip = host_key(ip);
+		let now = Instant::now();
+		let mut guard = self.peers.lock().expect("Rate limiter lock poisoned");
+		let peers = &mut *guard;
+		if peers.usage.len() >= MAX_TRACKED_PEERS && !peers.usage.contains_key(&ip) {
+			if let Some((_, least_recently_used)) = peers.by_last_use.pop_first() {
+				peers.usage.remove(&least_recently_used);
+			}
+		}
+		peers.requests += 1;
+		let usage = peers.usage.entry(ip).or_insert(PeerUsage {
+			tokens: self.burst as f64,
+			last_refill: now,
+			in_flight: 0,
+			last_use: peers.requests,
+		});
+		peers.by_last_use.remove(&usage.last_use);
+		usage.last_use = peers.requests;
+		peers.by_last_use.insert(usage.last_use, ip);
+		self.refill(usage, now);
+		if usage.in_flight >= self.max_in_flight {
+			return Err(ErrorType::TooManyConcurrentRequests(self.max_in_flight));
+		}
+		if usage.tokens < 1.0 {
+			return Err(ErrorType::RateLimited(self.refill_per_second));
+		}
+		usage.tokens -= 1.0;
+		usage.in_flight += 1;
+		Ok(RequestGuard {
+			ip,
+			peers: self.peers.clone(),
+		})
+	}
+	/// Adds the tokens earned since the last refill. Returns the tokens of the IP
+	fn refill(&self, usage: &mut PeerUsage, now: Instant) -> f64 {
+		let earned = now.duration_since(usage.last_refill).as_secs_f64() * self.refill_per_second as f64;
+		usage.tokens = (usage.tokens + earned).min(self.burst as f64);
+		usage.last_refill = now;
+		usage.tokens
+	}
+}
+
+/// Returns the key of the host of the IP: the IPv4 address, or the /64 network of the IPv6 address
+pub fn host_key(ip: IpAddr) -> IpAddr {
+	match canonical_ip(ip) {
+		IpAddr::V6(v6) => IpAddr::V6(Ipv6Addr::from(u128::from(v6) & (u128::MAX << (128 - IPV6_BUCKET_PREFIX)))),
+		ip => ip,
+	}
+}
+/// Returns the key of the network of the IP, which is usually under the control of a single party: its /24 for IPv4, or its /48 for IPv6
+pub fn subnet_key(ip: IpAddr) -> IpAddr {
+	match canonical_ip(ip) {
+		IpAddr::V4(v4) => IpAddr::V4(Ipv4Addr::from(u32::from(v4) & (u32::MAX << (32 - IPV4_SUBNET_PREFIX)))),
+		IpAddr::V6(v6) => IpAddr::V6(Ipv6Addr::from(u128::from(v6) & (u128::MAX << (128 - IPV6_SUBNET_PREFIX)))),
+	}
+}
+
+/// A request that is being handled. Dropping it frees its slot
+pub struct RequestGuard {
+	ip: IpAddr,
+	peers: Arc<Mutex<TrackedPeers>>,
+}
+impl Drop for RequestGuard {
+	fn drop(&mut self) {
+		if let Ok(mut peers) = self.peers.lock() {
+			if let Some(usage) = peers.usage.get_mut(&self.ip) {
+				usage.in_flight = usage.in_flight.saturating_sub(1);
+			}
+		}
+	}
+}
@@ -1,6 +1,11 @@
 pub mod routes;
 pub mod models;
 pub mod node;
+pub mod light_node;
+pub mod gossip;
+pub mod limits;
+pub mod protocol;
+pub mod authentication;
 pub mod config;
 pub mod standard;
 pub mod sender;
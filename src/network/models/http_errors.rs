@@ -2,47 +2,133 @@ use std::fmt::Display;
 
 use json::object;
 
-use crate::network::routes::p2p::URL_REGEX;
+use crate::core::block::BlockError;
+use crate::core::utxo::transaction::TxError;
+use crate::data_storage::node_config_storage::url_serialize::AddressError;
 
 pub enum ErrorType {
-	WrongVersion(u32, u32), // first is the version that the other node had and second is the expected version
-	InvalidTransaction(String),
-	InvalidBlock(String),
-	InvalidUrl,
+	WrongVersion(u32, u32, u32), // the version that the other node had and the minimum and maximum supported versions
+	WrongChain([u8; 32], [u8; 32]), // the chain id of the other node and ours
+	InvalidTransaction(TxError, String), // the reason why it was rejected and the context
+	InvalidBlock(BlockError, String),
+	InvalidUrl(AddressError), // why the address of the sender can not be used
+	TooManyItems(usize, usize), // the amount of requested items and the maximum
+	Banned,
+	InvalidProof,
+	PayloadTooLarge(usize), // the maximum size of the body
+	RateLimited(u32), // the amount of requests allowed per second
+	TooManyConcurrentRequests(usize), // the maximum amount of requests handled at once
+	AddressInUse(String), // the address that a live peer already has
+	TooManyPeersInSubnet(usize), // the maximum amount of peers of the same network
 }
 impl Display for ErrorType {
 	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
 		let str = match self {
-			Self::WrongVersion(request_version, expected_version) => {
+			Self::WrongVersion(request_version, min_version, max_version) => {
 				let json = object! {
 					error: "WrongVersion",
-					message: "Distinct protocol version",
+					message: "Unsupported protocol version",
 					request_version: *request_version,
-					expected_version: *expected_version
+					min_version: *min_version,
+					max_version: *max_version
 				};
 				json.to_string()
 			},
-			Self::InvalidTransaction(context) => {
+			Self::WrongChain(request_chain_id, chain_id) => {
+				let json = object! {
+					error: "WrongChain",
+					message: "The node follows another chain",
+					request_chain_id: hex::encode(request_chain_id),
+					chain_id: hex::encode(chain_id)
+				};
+				json.to_string()
+			},
+			Self::InvalidTransaction(reason, context) => {
 				let json = object! {
 					error: "InvalidTransaction",
 					message: "The transaction is invalid in the current context",
+					reason: reason.to_string(),
 					context: context.to_string()
 				};
 				json.to_string()
 			},
-			ErrorType::InvalidBlock(context) => {
+			ErrorType::InvalidBlock(reason, context) => {
 				let json = object! {
 					error: "InvalidBlock",
 					message: "The block is invalid in the current context",
+					reason: reason.to_string(),
 					context: context.to_string()
 				};
 				json.to_string()
 			}
-			ErrorType::InvalidUrl => {
+			ErrorType::InvalidUrl(reason) => {
 				let json = object! {
 					error: "InvalidUrl",
-					message: "The given url is invalid. Must match the URL_REGEX",
-					valid_url_regex: URL_REGEX,
+					message: "The address of the node is invalid",
+					reason: reason.to_string(),
+				};
+				json.to_string()
+			}
+			ErrorType::TooManyItems(requested, maximum) => {
+				let json = object! {
+					error: "TooManyItems",
+					message: "Too many items were requested at once",
+					requested: *requested,
+					maximum: *maximum
+				};
+				json.to_string()
+			}
+			ErrorType::Banned => {
+				let json = object! {
+					error: "Banned",
+					message: "The sender is banned for misbehaving"
+				};
+				json.to_string()
+			}
+			ErrorType::InvalidProof => {
+				let json = object! {
+					error: "InvalidProof",
+					message: "The identity of the sender could not be verified. The nonce may have expired"
+				};
+				json.to_string()
+			}
+			ErrorType::PayloadTooLarge(maximum) => {
+				let json = object! {
+					error: "PayloadTooLarge",
+					message: "The body of the request is too large",
+					maximum: *maximum
+				};
+				json.to_string()
+			}
+			ErrorType::RateLimited(per_second) => {
+				let json = object! {
+					error: "RateLimited",
+					message: "Too many requests were sent, try again later",
+					per_second: *per_second
+				};
+				json.to_string()
+			}
+			ErrorType::TooManyConcurrentRequests(maximum) => {
+				let json = object! {
+					error: "TooManyConcurrentRequests",
+					message: "Too many requests are being handled at once for the sender",
+					maximum: *maximum
+				};
+				json.to_string()
+			}
+			ErrorType::AddressInUse(url) => {
+				let json = object! {
+					error: "AddressInUse",
+					message: "The address belongs to another node that is still paired up",
+					url: url.to_string()
+				};
+				json.to_string()
+			}
+			ErrorType::TooManyPeersInSubnet(maximum) => {
+				let json = object! {
+					error: "TooManyPeersInSubnet",
+					message: "Too many peers are paired up from the network of the sender",
+					maximum: *maximum
 				};
 				json.to_string()
 			}
@@ -4,6 +4,9 @@ use serde::{Deserialize, Serialize};
 
 use crate::core::block::{Block, BlockContent, BlockHeader};
 use crate::core::utxo::transaction::Transaction;
+use crate::crypto::hash::merkle::MerkleProof;
+use crate::network::authentication::NodeProof;
+use crate::network::protocol::VersionInfo;
 
 pub mod http_errors;
 
@@ -12,8 +15,10 @@ pub enum InvDataType {
 	Transaction,
 	Block,
 }
+/// Announces items by their hash. When it is sent to `/inv`, the peer answers with a `GetData` of the items it lacks
 #[derive(Clone, Deserialize, Serialize)]
 pub struct Inv {
+	pub(crate) version: u32,
 	pub(crate) data_type: InvDataType,
 	pub(crate) hashes: Vec<[u8; 32]>
 }
@@ -41,6 +46,35 @@ pub struct BlockchainInfo {
 	pub(crate) mempool_size: usize,
 }
 #[derive(Clone, Deserialize, Serialize)]
+pub struct EstimateFee {
+	pub(crate) version: u32,
+	pub(crate) input_count: usize,
+	pub(crate) output_count: usize,
+}
+#[derive(Clone, Deserialize, Serialize, Debug)]
+pub struct FeeEstimate {
+	pub(crate) version: u32,
+	pub(crate) fee_per_byte: u32,
+	/// The maximum size of a transaction with the given inputs and outputs
+	pub(crate) estimated_size: usize,
+	/// The fee that a transaction with the given inputs and outputs should pay to be accepted
+	pub(crate) estimated_fee: u64,
+}
+#[derive(Clone, Deserialize, Serialize)]
+pub struct GetTxProof {
+	pub(crate) version: u32,
+	/// The block that contains the transaction
+	pub(crate) block_hash: [u8; 32],
+	pub(crate) txid: [u8; 32],
+}
+/// The header of the block that contains the transaction and the merkle proof of the transaction against the header's merkle root
+#[derive(Clone, Deserialize, Serialize, Debug)]
+pub struct TxProof {
+	pub(crate) version: u32,
+	pub(crate) header: BlockHeader,
+	pub(crate) proof: MerkleProof,
+}
+#[derive(Clone, Deserialize, Serialize)]
 pub struct Headers {
 	pub(crate) headers: Vec<BlockHeader>,
 }
@@ -49,6 +83,12 @@ pub struct BlocksData {
 	pub(crate) version: u32,
 	pub(crate) blocks_data: Vec<Option<BlockContent>>,
 }
+/// The requested transactions, in the same order as their ids. Unknown transactions are None
+#[derive(Clone, Deserialize, Serialize)]
+pub struct TransactionsData {
+	pub(crate) version: u32,
+	pub(crate) transactions: Vec<Option<Transaction>>,
+}
 #[derive(Clone, Deserialize, Serialize)]
 pub struct SendPeers {
 	pub(crate) peers: HashSet<String>,
@@ -61,14 +101,37 @@ pub enum HttpScheme {
 #[derive(Clone, Deserialize, Serialize)]
 pub struct PairUp {
 	pub(crate) version: u32,
+	/// What the sender supports, so that the receiver can negotiate the protocol with it
+	pub(crate) info: VersionInfo,
 	pub(crate) method: HttpScheme,
 	pub(crate) port: u16,
+	/// A nonce that the receiver must sign in its answer, to prove its identity
+	pub(crate) challenge: [u8; 32],
+	/// Signs a nonce of the receiver and the rest of the message
+	pub(crate) proof: NodeProof,
+}
+/// The answer to an accepted `PairUp`
+#[derive(Clone, Deserialize, Serialize)]
+pub struct PairedUp {
+	pub(crate) version: u32,
+	/// Signs the challenge of the `PairUp`
+	pub(crate) proof: NodeProof,
 }
 #[derive(Clone, Deserialize, Serialize)]
 pub struct Unpair {
 	pub(crate) version: u32,
 	pub(crate) method: HttpScheme,
 	pub(crate) port: u16,
+	/// Signs a nonce of the receiver and the rest of the message, so that only the peer itself can unpair
+	pub(crate) proof: NodeProof,
+}
+/// A nonce that must be signed in the next `PairUp` or `Unpair` sent to the node
+#[derive(Clone, Deserialize, Serialize)]
+pub struct Challenge {
+	pub(crate) version: u32,
+	pub(crate) nonce: [u8; 32],
+	/// The public key of the identity of the node. The answer is bound to it, so that it can not be passed on to another node
+	pub(crate) public_key: Vec<u8>,
 }
 #[derive(Clone, Deserialize, Serialize)]
 pub struct NewTransaction {
@@ -1,17 +1,21 @@
-use std::collections::HashSet;
-use std::mem::size_of_val;
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::net::IpAddr;
+use std::pin::Pin;
 use std::process::{exit, ExitCode, ExitStatus};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-use actix_web::{App, HttpServer};
+use actix_web::{App, HttpRequest, HttpResponse, HttpServer};
+use anyhow::anyhow;
+use actix_web::dev::{Service, ServiceResponse};
 use actix_web::dev::ServerHandle;
 use actix_web::web::{Data, to};
 use local_ip_address::local_ip;
 use rand::prelude::IteratorRandom;
-use rand::thread_rng;
-use reqwest::{Client, Url};
+use rand::{thread_rng, Rng};
+use reqwest::Client;
 use rsntp::{AsyncSntpClient, Config, SynchronizationError};
 use serde::Serialize;
 use tokio::runtime::Handle;
@@ -19,83 +23,170 @@ use tokio::sync::RwLock;
 use tokio::task::block_in_place;
 
 use crate::consensus::lottery::Lottery;
-use crate::core::block::Block;
+use crate::core::block::{Block, BlockContent, BlockHeader, BlockValidity};
 use crate::core::blockchain::BlockChain;
+use crate::core::genesis::GenesisSpec;
+use crate::core::keys::identity::{NodeId, NodeIdentity};
 use crate::core::keys::NodeKeyChain;
 use crate::core::parameters::Parameters;
-use crate::core::utxo::transaction::Transaction;
+use crate::core::utxo::transaction::{Transaction, TxError};
 use crate::crypto::vrf::{prove, VrfPk, VrfProof, VrfSk};
+use crate::data_storage::BaseDirectory;
 use crate::data_storage::node_config_storage::node_config::NodeConfig;
-use crate::data_storage::node_config_storage::url_serialize::PeerUrl;
+use crate::data_storage::node_config_storage::url_serialize::{AddressError, canonical_ip, PeerUrl};
 use crate::network::{config, timing};
+use crate::network::authentication::{ChallengeCache, NodeProof, PAIR_UP_DOMAIN, pair_up_content, PAIRED_UP_DOMAIN, Receiver, UNPAIR_DOMAIN, unpair_content};
 use crate::network::config::config_routes;
-use crate::network::models::{HttpScheme, NewBlock, NewTransaction};
+use crate::network::limits::RateLimiter;
+use crate::network::protocol;
+use crate::network::protocol::{Features, MIN_PROTOCOL_VERSION, PeerProtocol, VersionInfo};
+use crate::network::gossip::{choose_fanout, SeenCache};
+use crate::data_storage::peer_storage::address_book::{AddressBook, Misbehaviour};
+use crate::network::models::{GetData, GetHeaders, Inv, InvDataType, NewBlock, NewTransaction, PairUp, Unpair};
 use crate::network::sender::Sender;
-use crate::network::standard::standard_serialize;
+use crate::network::standard::WireFormat;
+
+/// A node that we are paired with
+#[derive(Clone)]
+pub struct Peer {
+	pub url: PeerUrl,
+	/// The protocol negotiated with the node
+	pub protocol: PeerProtocol,
+}
+
+/// Marks a background task as running. The task is marked as finished when the guard is dropped, even if the task panics
+pub(crate) struct RunningTask<'a>(&'a AtomicBool);
+impl<'a> RunningTask<'a> {
+	/// Marks the task as running, or returns None if it already was
+	pub(crate) fn start(flag: &'a AtomicBool) -> Option<Self> {
+		(!flag.swap(true, Ordering::Relaxed)).then_some(Self(flag))
+	}
+}
+impl Drop for RunningTask<'_> {
+	fn drop(&mut self) {
+		self.0.store(false, Ordering::Relaxed);
+	}
+}
 
 #[derive(Clone)]
 pub struct Node {
 	// TODO: Keys... and stuff
+	/// The newest protocol version spoken by the node
 	pub version: u32,
+	/// The versions, features and chain sent to the peers in the handshake
+	pub version_info: VersionInfo,
 	pub current_slot: Arc<AtomicU64>,
 	pub blockchain: Arc<RwLock<BlockChain>>,
-	pub peers: Arc<RwLock<HashSet<PeerUrl>>>,
-	// TODO: Implement gossip protocol instead of broadcasting everything to everyone
+	/// The paired nodes by their id, which they proved when pairing up
+	pub peers: Arc<RwLock<HashMap<NodeId, Peer>>>,
+	/// The nonces sent to nodes that want to pair up or unpair
+	pub challenges: Arc<RwLock<ChallengeCache>>,
+	/// The transactions and blocks that were already received or relayed
+	pub seen: Arc<RwLock<SeenCache>>,
+	/// Every peer that was known, with its reliability and bans
+	pub address_book: AddressBook,
 	shutdown: Arc<AtomicBool>,
+	/// Whether the chain is currently being synchronized with the peers
+	syncing: Arc<AtomicBool>,
+	/// Whether new peers are currently being discovered
+	discovering: Arc<AtomicBool>,
+	/// The peers whose address another node wants to pair up from, while it is checked whether they are still alive
+	checked_owners: Arc<Mutex<HashSet<NodeId>>>,
 	key_chain: NodeKeyChain,
+	identity: NodeIdentity,
 	pub server_handle: Option<ServerHandle>,
 	pub config: NodeConfig,
 	pub parameters: Parameters,
+	/// The unix time in seconds at which the slot 0 of the chain started
+	pub start_time: u64,
 }
 
-pub(crate) const STARTING_SLOT_SECOND: u64 = 0;
-
-// TODO: AT THE END CHANGE THIS NUMBER FOR THE EPOCH SECOND OF THE TIME THE CRYPTO IS RELEASED
 impl Node {
-	pub async fn default(version: u32) -> Self {
-		let ntp_client = AsyncSntpClient::new();
-		let slot_time = ntp_client.synchronize("time.google.com").await
-			.expect("Unable to sync with NTP server")
-			.datetime()
-			.unix_timestamp()
-			.expect("Time went backwards") - Duration::from_secs(STARTING_SLOT_SECOND);
-		let parameters = Parameters::default();
-
-		Self {
-			version,
-			current_slot: Arc::new(AtomicU64::new(slot_time.as_millis() as u64 / parameters.technical_parameters.slot_duration as u64)),
-			blockchain: Arc::new(RwLock::new(BlockChain::init(parameters))),
-			shutdown: Arc::new(AtomicBool::new(false)),
-			key_chain: NodeKeyChain::random(),
-			server_handle: None,
-			config: NodeConfig::default(),
-			peers: Arc::new(Default::default()), // TODO: Load from default file
-			parameters,
-		}
+	pub async fn default(version: u32) -> anyhow::Result<Self> {
+		let genesis = GenesisSpec::default();
+		let slot = Self::wait_for_start(&genesis).await?;
+		Self::open(version, NodeConfig::default(), genesis, &BaseDirectory::get_base_directory(), slot)
 	}
-	pub async fn new(version: u32, config_file: Option<String>,parameters: Parameters) -> Self {
-		let ntp_client = AsyncSntpClient::new();
-		let slot_time = ntp_client.synchronize("time.google.com").await
-			.expect("Unable to sync with NTP server")
-			.datetime()
-			.unix_timestamp()
-			.expect("Time went backwards") - Duration::from_secs(STARTING_SLOT_SECOND);
-		let config = NodeConfig::load(config_file);
-		let peers = config.trusted_peers.clone();
-		Self {
+	/// Creates a node of the chain described by the given genesis specification.
+	/// If the chain has not started yet, waits until its start time
+	pub async fn new(version: u32, config_file: Option<String>, genesis: GenesisSpec) -> anyhow::Result<Self> {
+		let slot = Self::wait_for_start(&genesis).await?;
+		Self::open(version, NodeConfig::load(config_file), genesis, &BaseDirectory::get_base_directory(), slot)
+	}
+	/// Returns the current slot of the chain according to the NTP server, waiting until the chain starts
+	async fn wait_for_start(genesis: &GenesisSpec) -> anyhow::Result<u64> {
+		let slot_duration = genesis.parameters.technical_parameters.slot_duration as u64;
+		timing::wait_for_current_slot(&AsyncSntpClient::new(), slot_duration, genesis.start_time).await
+			.map_err(|err| anyhow!("Unable to sync with NTP server: {}", err))
+	}
+	/// Creates a node whose chain, keys and address book are stored in the given base directory, starting its clock at the given slot
+	pub(crate) fn open(version: u32, config: NodeConfig, genesis: GenesisSpec, base_directory: &str, slot: u64) -> anyhow::Result<Self> {
+		let address_book = AddressBook::open(base_directory, Duration::from_secs(config.ban_duration));
+		let parameters = genesis.parameters;
+		let start_time = genesis.start_time;
+		let current_slot = Arc::new(AtomicU64::new(slot));
+		let mut blockchain = BlockChain::open(base_directory, genesis)?;
+		blockchain.set_current_slot(current_slot.clone());
+		let version_info = Self::version_info(version, &blockchain);
+		Ok(Self {
 			version,
-			current_slot: Arc::new(AtomicU64::new(slot_time.as_millis() as u64 / parameters.technical_parameters.slot_duration as u64)),
-			blockchain: Arc::new(RwLock::new(BlockChain::init(parameters))),
+			version_info,
+			current_slot,
+			blockchain: Arc::new(RwLock::new(blockchain)),
 			shutdown: Arc::new(AtomicBool::new(false)),
-			key_chain: NodeKeyChain::random(),
+			syncing: Arc::new(AtomicBool::new(false)),
+			discovering: Arc::new(AtomicBool::new(false)),
+			checked_owners: Arc::new(Default::default()),
+			key_chain: NodeKeyChain::load(base_directory),
+			identity: NodeIdentity::load(base_directory),
+			challenges: Arc::new(Default::default()),
 			server_handle: None,
 			config,
-			peers: Arc::new(RwLock::new(peers)),
+			peers: Arc::new(Default::default()),
+			seen: Arc::new(Default::default()),
+			address_book,
 			parameters,
+			start_time,
+		})
+	}
+	/// The versions spoken by a full node whose newest version is `version`
+	fn version_info(version: u32, blockchain: &BlockChain) -> VersionInfo {
+		VersionInfo {
+			min_version: MIN_PROTOCOL_VERSION.min(version),
+			max_version: version,
+			features: Features::full_node(),
+			chain_id: blockchain.get_chain_id(),
 		}
 	}
+	pub fn node_id(&self) -> NodeId {
+		self.identity.id()
+	}
+	/// The public key of the identity of the node
+	pub fn public_key(&self) -> &[u8] {
+		&self.identity.public_key
+	}
+	/// Returns our address as the sender of the request reached us, which the proofs of its messages must be bound to
+	pub fn url_reached_by(&self, req: &HttpRequest) -> Result<PeerUrl, AddressError> {
+		PeerUrl::from_host(self.config.http_scheme, req.connection_info().host())
+	}
+	/// Signs the nonce of another node with our identity
+	pub fn prove(&self, domain: &str, nonce: [u8; 32], content: &[u8]) -> NodeProof {
+		NodeProof::create(&self.identity, domain, nonce, content)
+	}
+	/// Checks a proof made for a nonce that we issued, consuming the nonce. Returns the id of the node that made it
+	pub async fn verify_proof(&self, proof: &NodeProof, domain: &str, content: &[u8]) -> Option<NodeId> {
+		if !self.challenges.write().await.redeem(&proof.nonce) {
+			return None;
+		}
+		proof.verify(domain, content)
+	}
+	/// Returns the addresses of the paired nodes
+	pub async fn peer_urls(&self) -> HashSet<PeerUrl> {
+		self.peers.read().await.values().map(|peer| peer.url.clone()).collect()
+	}
 	pub fn start(&mut self) {
 		log::info!("Starting the node");
+		log::info!("Forger address: {}, VRF public key: {}", self.key_chain.wallet_key_pair.0, hex::encode(self.key_chain.vrf_key_pair.1));
 		self.start_node();
 		log::info!("Node started successfully");
 		let mut self_clone = self.clone();
@@ -110,16 +201,38 @@ impl Node {
 		});
 		log::info!("Started heart beat thread");
 	}
-	fn start_node(&mut self) {
+	pub(crate) fn start_node(&mut self) {
 		// STARTS THE NODE, THE ENTRY POINT.
 		let app_state = Data::new(self.clone());
+		let address_book = Data::new(self.address_book.clone());
+		let network_parameters = self.parameters.network_parameters;
+		let rate_limiter = RateLimiter::new(self.config.rate_limit_burst, self.config.rate_limit_per_second, self.config.max_concurrent_requests);
 
 		// Setup server
 		let server = match HttpServer::new(move || {
+			let rate_limiter = rate_limiter.clone();
 			App::new()
 				.app_data(app_state.clone())
-				.configure(config_routes)
+				.app_data(address_book.clone())
+				.wrap_fn(move |req, srv| {
+					let guard = match req.peer_addr().map(|address| rate_limiter.acquire(address.ip())) {
+						Some(Err(err)) => {
+							let response = HttpResponse::TooManyRequests().body(err.to_string());
+							return Box::pin(std::future::ready(Ok(req.into_response(response)))) as Pin<Box<dyn Future<Output = Result<ServiceResponse, actix_web::Error>>>>;
+						}
+						Some(Ok(guard)) => Some(guard),
+						None => None,
+					};
+					let response = srv.call(req);
+					Box::pin(async move {
+						let response = response.await;
+						drop(guard);
+						response
+					})
+				})
+				.configure(|config| config_routes(config, &network_parameters))
 		})
+			.max_connections(self.config.max_connections)
 			.bind(format!("{}:{}", local_ip().expect("Unable to get local IP"), self.config.listing_port)) {
 			Ok(server) => { // Just run and return server
 				server.run()
@@ -153,14 +266,17 @@ impl Node {
 			// Do something
 			let current_slot = self.current_slot.fetch_add(1, Ordering::Relaxed) + 1; // Update and get the current block
 
-			// FIXME: ADD THE HASH OF THE PREVIOUS EPOCH AS ENTRY IN THE VRF
 			let vrf_proving_key = VrfSk::from_bytes(&self.key_chain.vrf_key_pair.0).unwrap();
 			let active_slot_coeff = self.parameters.technical_parameters.active_slot_coefficient;
 
-			let node_stake = 1; // FIXME: Actually get the node stake
-			let total_staked = 2; // FIXME: Actually get the total stake
+			let epoch = self.parameters.technical_parameters.get_epoch(current_slot);
+			let (node_stake, total_staked, epoch_nonce) = {
+				let chain = self.blockchain.read().await;
+				let stake_distribution = chain.get_stake_distribution(epoch);
+				(stake_distribution.get_stake(&self.key_chain.vrf_key_pair.1), stake_distribution.get_total_stake(), chain.get_epoch_nonce(epoch))
+			};
 
-			let lottery = Lottery::run_lottery(current_slot, active_slot_coeff, &[0u8; 32], &vrf_proving_key, node_stake, total_staked); // FIXME: Replace last_epoch_hash with actual last epoch hash
+			let lottery = epoch_nonce.and_then(|epoch_nonce| Lottery::run_lottery(current_slot, active_slot_coeff, &epoch_nonce, &vrf_proving_key, node_stake, total_staked));
 
 			if let Some((random_number, proof)) = lottery {
 				// LOTERY WON!!!
@@ -178,14 +294,7 @@ impl Node {
 			}
 			if counter % SLOTS_PER_RE_SYNC == 0 {
 				timing::sync_to_slot(&ntp_client, self.parameters.technical_parameters.slot_duration as u64).await; // Re-sync sleep
-				match timing::get_accurate_slot(&ntp_client, self.parameters.technical_parameters.slot_duration as u64).await { // Re-set the slot
-					Ok(actual_current_slot) => {
-						self.current_slot.store(actual_current_slot, Ordering::Relaxed);
-					}
-					Err(err) => {
-						log::error!("Unable to synchronize with NTP server. Error: {}", err);
-					}
-				}
+				timing::resync_slot(&ntp_client, &self.current_slot, self.parameters.technical_parameters.slot_duration as u64, self.start_time).await; // Re-set the slot
 			} else {
 				spin_sleep::sleep(interval - start.elapsed()); // Regular sleep
 			}
@@ -199,40 +308,30 @@ impl Node {
 		let mut chain = self.blockchain.write().await;
 
 		let prev_hash = chain.get_last_block().header.hash;
-
-		let mut transactions = Vec::new();
-
-		for tx in chain.mempool.get_map() {
-			if size_of_val(&transactions) > self.parameters.network_parameters.max_block_body_size {
-				transactions.remove(transactions.len() - 1);
-				break;
-			} else {
-				transactions.push(tx.clone());
-			}
-		}
+		let height = chain.get_height() + 1;
+		let Some(transactions) = chain.get_block_transactions(height, self.key_chain.wallet_key_pair.0) else {
+			log::error!("The reward of the block overflows, not forging");
+			return;
+		};
 
 		let new_block = Block::new(
-			chain.get_height() + 1,
+			height,
 			transactions,
 			current_slot,
 			prev_hash,
 			self.key_chain.wallet_key_pair.0,
 			self.key_chain.vrf_key_pair.1,
 			random_number,
-			&proof);
-		if chain.add_block(&new_block) {
-			let msg = NewBlock {
-				version: self.version,
-				block: new_block,
-			};
-			let peers = self.peers.read().await.clone();
+			&proof,
+			self.version);
+		if chain.add_block(&new_block) == Ok(BlockValidity::Valid) {
+			drop(chain);
 			log::info!("Took about {:?} to add to chain", start.elapsed());
 
-			log::info!("Started broadcasting block {}", msg.block.header.height);
-			tokio::spawn(async move {
-				Self::broadcast_block(&msg, &peers).await;
-				log::info!("Finished broadcasting block {}.", msg.block.header.height);
-			}).await.ok();
+			let height = new_block.header.height;
+			log::info!("Started relaying block {}", height);
+			self.relay_block(new_block).await;
+			log::info!("Finished relaying block {}.", height);
 		} else {
 			log::error!("New block created but could not add to blockchain")
 		}
@@ -243,83 +342,397 @@ impl Node {
 
 	pub async fn main_loop(&mut self) {
 		let mut counter = 0u32; // Counter to replace peers
+		let mut sync_counter = 0u32; // Counter to check if the peers are ahead of us
 		const REPLACE_PEER_TIME: u32 = 10u32; // In seconds
+		const SYNC_CHECK_TIME: u32 = 5u32; // In seconds
+		const DISCOVER_PEER_TIME: u32 = 5u32; // In seconds
+		let mut discover_counter = DISCOVER_PEER_TIME; // Counter to look for new peers. The first peers are paired up with right away
 		while !self.is_shutdown() {
-			// // Check if pairs height is bigger
-			// let self_copy = self.clone();
-			// spawn(async move {
-			// 	let peers = self_copy.peers.read().await.clone();
-			//
-			// 	let client = Client::new();
-			// 	let current_height = self_copy.blockchain.read().await.get_height();
-			// 	for peer in peers {
-			// 		let info = tokio::time::timeout(Duration::from_millis(500), Sender::get_blockchain_info(&client, peer)).await; // Timeout because it may take a long time
-			// 		if let Ok(Ok(info)) = info {
-			// 			let height = info.height;
-			// 			if height > current_height {
-			// 				let mut self_copy_copy = self_copy.clone();
-			// 				spawn(async move {self_copy_copy.sync_chain().await});
-			// 				break;
-			// 			}
-			// 		}
-			// 	}
-			// });
+			// Check if any peer is ahead of us
+			if sync_counter >= SYNC_CHECK_TIME {
+				sync_counter = 0;
+				let mut self_copy = self.clone();
+				tokio::spawn(async move { self_copy.sync_chain().await; });
+			}
 			// Check if peer list is full
-			if self.peers.read().await.len() < self.config.max_peers {
+			if discover_counter >= DISCOVER_PEER_TIME && self.peers.read().await.len() < self.config.max_peers {
+				discover_counter = 0;
 				let mut self_copy = self.clone();
-				// spawn(async move {self_copy.discover_peers().await;}); // TODO: Enable this
+				tokio::spawn(async move { self_copy.discover_peers().await; });
 			}
 
 			if counter > REPLACE_PEER_TIME {
 				let mut self_copy = self.clone();
 				counter = 0;
-				// spawn(async move { self_copy.cycle_peers().await; }); //TODO: Enable this
+				tokio::spawn(async move { self_copy.cycle_peers().await; });
 			}
 
 			counter += 1;
+			sync_counter += 1;
+			discover_counter += 1;
 			tokio::time::sleep(Duration::from_secs(1)).await;
 		}
 	}
+	/// Pairs up with new peers until the peer list is full
 	pub async fn discover_peers(&mut self) {
-		// TODO
+		let missing = self.config.max_peers.saturating_sub(self.peers.read().await.len());
+		let new_peers = self.discover_n_peers(missing).await;
+		if !new_peers.is_empty() {
+			log::info!("Discovered {} new peers", new_peers.len());
+		}
+		let surplus = self.add_peers(&mut *self.peers.write().await, new_peers);
+		self.unpair_from_all(surplus).await;
 	}
-	async fn discover_n_peers(&self, n: u32) -> HashSet<PeerUrl> {
-		// TODO: Check that the peer discovered isn't already in peer list
-		// TODO: Check that if the peer list is empty, use seed peers
-		// TODO: Check that the peer version is valid and the peer is online
-
-		const N: u32 = 2;
-		const M: u32 = 10;
+	/// Adds the new peers while there is room for them. Returns the peers that did not fit
+	fn add_peers(&self, peers: &mut HashMap<NodeId, Peer>, new_peers: HashMap<NodeId, Peer>) -> Vec<Peer> {
+		new_peers.into_iter().filter_map(|(node_id, peer)| {
+			if peers.len() >= self.config.max_peers {
+				return Some(peer);
+			}
+			peers.insert(node_id, peer);
+			None
+		}).collect()
+	}
+	/// Finds at most `n` new peers among the trusted peers, the best peers of the address book and the peers of our peers.
+	/// A candidate is accepted if it follows our chain, shares a protocol version with us and proves its identity when pairing up
+	async fn discover_n_peers(&self, n: usize) -> HashMap<NodeId, Peer> {
+		const QUERIED_PEERS: usize = 8;
+		const CANDIDATE_TIMEOUT: Duration = Duration::from_millis(500);
+		if n == 0 {
+			return HashMap::new();
+		}
+		let Some(_discovering) = RunningTask::start(&self.discovering) else {
+			return HashMap::new(); // Already discovering
+		};
 
-		let mut current_peers = self.peers.read().await.clone();
-		let mut rng = thread_rng();
-		for _ in 0..N {
-			for p in current_peers.clone() {
-				// Sender::get_peers();
+		let client = Client::new();
+		let current_peers = self.peers.read().await.clone();
+		let current_urls: HashSet<PeerUrl> = current_peers.values().map(|peer| peer.url.clone()).collect();
+		let queried: Vec<PeerUrl> = current_urls.iter().cloned().choose_multiple(&mut thread_rng(), QUERIED_PEERS);
+
+		// Known peers are tried first
+		let mut candidates: Vec<PeerUrl> = self.config.trusted_peers.iter().cloned().chain(self.address_book.best_peers(n)).collect();
+		for peer in queried {
+			if let Ok(Ok(peers)) = tokio::time::timeout(CANDIDATE_TIMEOUT, Sender::get_peers(&client, peer.to_url())).await {
+				candidates.extend(peers.iter().filter_map(|peer| PeerUrl::parse(peer).ok()));
+			}
+		}
+		let mut unique = HashSet::new();
+		let candidates: Vec<PeerUrl> = candidates.into_iter()
+			.filter(|candidate| unique.insert(candidate.clone()))
+			.filter(|candidate| !current_urls.contains(candidate) && !self.is_own_address(candidate) && !self.address_book.is_banned(candidate))
+			.collect();
+
+		let mut new_peers = HashMap::new();
+		for candidate in candidates {
+			if new_peers.len() >= n {
+				break;
+			}
+			match self.pair_up_with(&client, &candidate).await {
+				Ok((node_id, peer)) => {
+					if node_id == self.node_id() || current_peers.contains_key(&node_id) || new_peers.contains_key(&node_id) {
+						continue; // The same node under another address
+					}
+					self.address_book.add(&candidate);
+					new_peers.insert(node_id, peer);
+				}
+				Err(err) => log::debug!("Unable to pair up with {}: {}", candidate.to_url(), err),
 			}
 		}
-		todo!()
+		new_peers
 	}
+	/// Negotiates the protocol with the candidate and asks it to pair up with us, proving our identity with its nonce.
+	/// Returns the id that the candidate proved in its answer
+	async fn pair_up_with(&self, client: &Client, candidate: &PeerUrl) -> anyhow::Result<(NodeId, Peer)> {
+		const PAIR_UP_TIMEOUT: Duration = Duration::from_millis(500);
+		let protocol = protocol::handshake(client, candidate, &self.version_info).await.map_err(|err| anyhow!("{}", err))?;
+		let candidate_challenge = tokio::time::timeout(PAIR_UP_TIMEOUT, Sender::get_challenge(client, candidate.to_url())).await??;
+		let receiver = Receiver { url: candidate, public_key: &candidate_challenge.public_key };
+		let challenge: [u8; 32] = thread_rng().gen();
+		let (method, port) = (self.config.http_scheme, self.config.listing_port);
+		let content = pair_up_content(protocol.version, &self.version_info, method, port, &challenge, receiver);
+		let msg = PairUp {
+			version: protocol.version,
+			info: self.version_info.clone(),
+			method,
+			port,
+			challenge,
+			proof: self.prove(PAIR_UP_DOMAIN, candidate_challenge.nonce, &content),
+		};
+		let answer = tokio::time::timeout(PAIR_UP_TIMEOUT, Sender::pair_up_with(client, candidate.to_url(), msg)).await??;
+		if answer.proof.nonce != challenge {
+			return Err(anyhow!("The answer signs another challenge"));
+		}
+		if answer.proof.public_key != candidate_challenge.public_key {
+			return Err(anyhow!("The answer is signed by another node than the one our proof was bound to"));
+		}
+		let node_id = answer.proof.verify(PAIRED_UP_DOMAIN, &[]).ok_or_else(|| anyhow!("Invalid proof of identity"))?;
+		Ok((node_id, Peer {
+			url: candidate.clone(),
+			protocol,
+		}))
+	}
+	/// Checks that the node at the url is still alive and is the node with the given id, by pairing up with it again
+	async fn proves_identity(&self, url: &PeerUrl, node_id: &NodeId) -> bool {
+		match self.pair_up_with(&Client::new(), url).await {
+			Ok((proven_id, _)) => proven_id == *node_id,
+			Err(err) => {
+				log::debug!("{} did not prove its identity: {}", url.to_url(), err);
+				false
+			}
+		}
+	}
+	/// Checks in the background whether the peer with the given id still answers at the given address, removing it if it does not,
+	/// so that the node that now has the address can pair up with us. It is not checked while handling the request of that node,
+	/// so that requests can not make us wait for other nodes. Each peer is only checked once at a time
+	pub(crate) fn check_address_owner(&self, url: PeerUrl, owner: NodeId) {
+		if !self.checked_owners.lock().expect("Checked owners lock poisoned").insert(owner) {
+			return;
+		}
+		let node = self.clone();
+		tokio::spawn(async move {
+			if !node.proves_identity(&url, &owner).await {
+				log::info!("Removing peer {}, which no longer answers at {}", owner, url.to_url());
+				node.peers.write().await.retain(|id, peer| *id != owner || peer.url != url);
+			}
+			node.checked_owners.lock().expect("Checked owners lock poisoned").remove(&owner);
+		});
+	}
+	/// Replaces up to `peer_cycle_count` random peers that are not trusted with newly discovered ones.
+	/// Peers are only removed if there are new peers to replace them, and they are asked to unpair from us.
+	/// New peers that do not fit in `max_peers` are also asked to unpair
 	pub async fn cycle_peers(&mut self) {
 		let peer_cycle_count = self.config.peer_cycle_count;
 		let trusted_peers = &self.config.trusted_peers;
-		let new_peers: HashSet<PeerUrl> = self.discover_n_peers(peer_cycle_count as u32).await;
+		let new_peers = self.discover_n_peers(peer_cycle_count).await;
+		if new_peers.is_empty() {
+			return;
+		}
 
 		let mut peers = self.peers.write().await;
-		let peers_to_remove: Vec<PeerUrl> = peers.iter()
-			.filter(|&url| { !trusted_peers.contains(url) }) // Check that it does not remove a trusted peer
+		let peers_to_remove: Vec<NodeId> = peers.iter()
+			.filter(|(_, peer)| !trusted_peers.contains(&peer.url)) // Check that it does not remove a trusted peer
+			.map(|(&node_id, _)| node_id)
+			.choose_multiple(&mut thread_rng(), new_peers.len());
+
+		let mut removed_peers: Vec<Peer> = peers_to_remove.iter().filter_map(|node_id| peers.remove(node_id)).collect();
+		removed_peers.extend(self.add_peers(&mut peers, new_peers));
+		drop(peers);
+		self.unpair_from_all(removed_peers).await;
+	}
+	/// Asks the peers to unpair from us, ignoring those that do not answer
+	async fn unpair_from_all(&self, peers: Vec<Peer>) {
+		let client = Client::new();
+		for peer in peers {
+			if let Err(err) = self.unpair_from(&client, &peer).await {
+				log::debug!("Unable to unpair from {}: {}", peer.url.to_url(), err);
+			}
+		}
+	}
+	/// Asks the peer to remove us from its peers, proving our identity with its nonce
+	async fn unpair_from(&self, client: &Client, peer: &Peer) -> anyhow::Result<bool> {
+		let challenge = Sender::get_challenge(client, peer.url.to_url()).await?;
+		let receiver = Receiver { url: &peer.url, public_key: &challenge.public_key };
+		let (version, method, port) = (peer.protocol.version, self.config.http_scheme, self.config.listing_port);
+		let msg = Unpair {
+			version,
+			method,
+			port,
+			proof: self.prove(UNPAIR_DOMAIN, challenge.nonce, &unpair_content(version, method, port, receiver)),
+		};
+		Sender::unpair_from(client, peer.url.to_url(), msg).await
+	}
+	/// Penalizes the peer for misbehaving, removing it from our peers if it gets banned
+	pub async fn punish(&self, peer: &PeerUrl, misbehaviour: Misbehaviour) {
+		if self.address_book.record_misbehaviour(peer, misbehaviour) {
+			log::warn!("Banned peer {} for {:?}", peer.to_url(), misbehaviour);
+			self.peers.write().await.retain(|_, paired| paired.url != *peer);
+		}
+	}
+	/// Penalizes the sender of a request for misbehaving, removing the peers of its host if it gets banned
+	pub async fn punish_sender(&self, req: &HttpRequest, misbehaviour: Misbehaviour) {
+		let Some(address) = req.peer_addr() else {
+			return;
+		};
+		let ip = canonical_ip(address.ip());
+		if self.address_book.record_host_misbehaviour(&ip.to_string(), misbehaviour) {
+			log::warn!("Banned host {} for {:?}", ip, misbehaviour);
+			self.peers.write().await.retain(|_, peer| peer.url.ip() != Some(ip));
+		}
+	}
+	/// Returns whether the sender of a request is banned
+	pub fn is_sender_banned(&self, req: &HttpRequest) -> bool {
+		req.peer_addr().is_some_and(|address| self.address_book.is_host_banned(&canonical_ip(address.ip()).to_string()))
+	}
+	/// Returns whether the address points to this node
+	fn is_own_address(&self, peer: &PeerUrl) -> bool {
+		let own_ips: Vec<IpAddr> = local_ip().into_iter().collect();
+		peer.points_to(&own_ips, self.config.listing_port)
+	}
+	/// Brings the chain up to date with the peers that are ahead of us.
+	/// The headers are requested first from the best peer and validated as a chain, then the block bodies are downloaded
+	/// in parallel from all the peers that are ahead and the blocks are added in order.
+	pub async fn sync_chain(&mut self) {
+		let Some(_syncing) = RunningTask::start(&self.syncing) else {
+			return; // Already syncing
+		};
+		let client = Client::new();
+		loop {
+			let peers_ahead = self.get_peers_ahead(&client).await;
+			if peers_ahead.is_empty() || !self.sync_round(&client, &peers_ahead).await {
+				break;
+			}
+		}
+	}
+	/// Returns the peers that serve the chain and have a higher chain than ours, sorted from the highest to the lowest
+	async fn get_peers_ahead(&self, client: &Client) -> Vec<Peer> {
+		let peers: Vec<Peer> = self.peers.read().await.values()
+			.filter(|peer| peer.protocol.features.contains(Features::FULL_CHAIN))
 			.cloned()
-			.choose_multiple(&mut thread_rng(), peer_cycle_count);
+			.collect();
+		let current_height = self.blockchain.read().await.get_height();
 
-		for peer in peers_to_remove {
-			peers.remove(&peer);
+		let mut handles = vec![];
+		for peer in peers {
+			let client = client.clone();
+			let address_book = self.address_book.clone();
+			handles.push(tokio::spawn(async move {
+				let start = Instant::now();
+				let info = tokio::time::timeout(Duration::from_millis(500), Sender::get_blockchain_info(&client, peer.url.to_url())).await; // Timeout because it may take a long time
+				match info {
+					Ok(Ok(info)) => {
+						address_book.record_success(&peer.url, start.elapsed());
+						Some((peer, info.height))
+					}
+					_ => {
+						address_book.record_failure(&peer.url);
+						None
+					}
+				}
+			}));
 		}
-		for new_peer in new_peers {
-			peers.insert(new_peer);
+		let mut peers_ahead = vec![];
+		for h in handles {
+			if let Ok(Some((peer, height))) = h.await {
+				if height > current_height {
+					peers_ahead.push((peer, height));
+				}
+			}
 		}
+		peers_ahead.sort_by(|(_, a), (_, b)| b.cmp(a));
+		peers_ahead.into_iter().map(|(peer, _)| peer).collect()
 	}
-	pub async fn sync_chain(&mut self) {
-		// TODO
+	/// Downloads and adds one batch of blocks. Returns whether some progress was made
+	async fn sync_round(&self, client: &Client, peers_ahead: &[Peer]) -> bool {
+		const HEADERS_TIMEOUT: Duration = Duration::from_secs(5);
+		let best_peer = &peers_ahead[0].url;
+
+		let locator = self.blockchain.read().await.get_block_locator();
+		let msg = GetHeaders {
+			version: peers_ahead[0].protocol.version,
+			block_locator_object: locator,
+		};
+		let headers = match tokio::time::timeout(HEADERS_TIMEOUT, Sender::get_headers(client, best_peer.to_url(), msg, &self.parameters.network_parameters)).await {
+			Ok(Ok(headers)) => headers.headers,
+			_ => {
+				log::warn!("Unable to get headers from peer {}", best_peer.to_url());
+				return false;
+			}
+		};
+
+		let new_headers: Vec<BlockHeader> = {
+			let chain = self.blockchain.read().await;
+			let new_headers: Vec<BlockHeader> = headers.into_iter()
+				.filter(|header| !chain.contains_block(&header.hash))
+				.collect();
+			if let Err(err) = chain.check_header_chain(&new_headers) {
+				log::warn!("Peer {} sent an invalid header chain: {}", best_peer.to_url(), err);
+				drop(chain);
+				// A header from a slot that has not started for us may be valid for a peer whose clock is ahead
+				if err.is_provably_invalid() {
+					self.punish(best_peer, Misbehaviour::InvalidHeaders).await;
+				}
+				return false;
+			}
+			new_headers
+		};
+		if new_headers.is_empty() {
+			return false;
+		}
+
+		let Some(blocks) = self.download_blocks(client, &new_headers, peers_ahead).await else {
+			log::warn!("Unable to download the blocks bodies from the peers");
+			return false;
+		};
+
+		let mut chain = self.blockchain.write().await;
+		for block in &blocks {
+			if let Err(err) = chain.add_block(block) {
+				log::warn!("Received invalid block {} while syncing: {}", hex::encode(block.header.hash), err);
+				drop(chain);
+				if err.is_provably_invalid() {
+					self.punish(best_peer, Misbehaviour::InvalidBlock).await;
+				}
+				return false;
+			}
+		}
+		log::info!("Synced up to height {}", chain.get_height());
+		true
+	}
+	/// Downloads the bodies of the given headers. The headers are split in chunks and each chunk is requested to a different peer,
+	/// falling back to the other peers if one fails. Returns None if some body could not be downloaded from any peer
+	async fn download_blocks(&self, client: &Client, headers: &[BlockHeader], peers: &[Peer]) -> Option<Vec<Block>> {
+		const BLOCKS_PER_REQUEST: usize = 64;
+		const GET_DATA_TIMEOUT: Duration = Duration::from_secs(10);
+
+		let mut handles = vec![];
+		for (i, chunk) in headers.chunks(BLOCKS_PER_REQUEST).enumerate() {
+			let chunk = chunk.to_vec();
+			let peers = peers.to_vec();
+			let client = client.clone();
+			let address_book = self.address_book.clone();
+			let network_parameters = self.parameters.network_parameters;
+			handles.push(tokio::spawn(async move {
+				for attempt in 0..peers.len() {
+					let peer = &peers[(i + attempt) % peers.len()]; // Each chunk starts with a different peer to spread the load
+					let msg = GetData {
+						version: peer.protocol.version,
+						data_type: InvDataType::Block,
+						hashes: chunk.iter().map(|header| header.hash).collect(),
+					};
+					if let Ok(Ok(data)) = tokio::time::timeout(GET_DATA_TIMEOUT, Sender::get_data(&client, peer.url.to_url(), msg, &network_parameters)).await {
+						if let Some(blocks) = Self::assemble_blocks(&chunk, data.blocks_data) {
+							return Some(blocks);
+						}
+					}
+					log::debug!("Unable to get block bodies from peer {}", peer.url.to_url());
+					address_book.record_failure(&peer.url);
+				}
+				None
+			}));
+		}
+		let mut blocks = Vec::with_capacity(headers.len());
+		for h in handles {
+			blocks.extend(h.await.ok()??);
+		}
+		Some(blocks)
+	}
+	/// Joins the headers with their bodies, checking that every body matches the merkle root and the witness root of its header
+	fn assemble_blocks(headers: &[BlockHeader], bodies: Vec<Option<BlockContent>>) -> Option<Vec<Block>> {
+		if headers.len() != bodies.len() {
+			return None;
+		}
+		headers.iter().zip(bodies).map(|(header, body)| {
+			let block = Block {
+				header: header.clone(),
+				transactions: body?,
+			};
+			if block.matches_header() {
+				Some(block)
+			} else {
+				None
+			}
+		}).collect()
 	}
 	pub async fn shutdown(&mut self) {
 		self.shutdown.store(true, Ordering::Relaxed);
@@ -331,56 +744,64 @@ impl Node {
 	pub fn is_shutdown(&self) -> bool {
 		block_in_place(|| self.shutdown.load(Ordering::Relaxed))
 	}
-	// Returns weather it was added or not
-	pub async fn new_transaction(&self, transaction: Transaction) -> bool {
-		if self.blockchain.write().await.add_transaction_to_mempool(&transaction) {
-			let msg = NewTransaction {
-				version: self.version,
-				transaction,
-			};
-			let peers = self.peers.read().await.clone();
-			Self::broadcast_transaction(peers, &msg).await;
-			true
-		} else {
-			false
-		}
+	// Returns why it was not added, if it was not
+	pub async fn new_transaction(&self, transaction: Transaction) -> Result<(), TxError> {
+		self.blockchain.write().await.add_transaction_to_mempool(&transaction)?;
+		self.relay_transaction(transaction).await;
+		Ok(())
 	}
-
-	pub async fn broadcast_transaction(peers: HashSet<PeerUrl>, tx: &NewTransaction) {
-		let urls: HashSet<Url> = peers.iter().map(|url| {
-			let mut new_url = url.to_url().clone();
-			new_url.set_path(config::NEW_TRANSACTION_URL);
-			new_url
-		}).collect();
-
-		Self::broadcast_bytes(urls, tx).await;
+	/// Announces the transaction to a random subset of the peers and sends it to those that lack it
+	pub async fn relay_transaction(&self, transaction: Transaction) {
+		let hash = transaction.id;
+		self.relay(InvDataType::Transaction, hash, config::NEW_TRANSACTION_URL, |version| NewTransaction {
+			version,
+			transaction: transaction.clone(),
+		}).await;
 	}
-	pub async fn broadcast_block(block: &NewBlock, peers: &HashSet<PeerUrl>) {
-		// TODO: Implement gossip protocol instead of broadcasting everything to everyone
-		let urls: HashSet<Url> = peers.iter().map(|url| {
-			let mut new_url = url.to_url().clone();
-			new_url.set_path(config::NEW_BLOCK_URL);
-			new_url
-		}).collect();
-
-		Self::broadcast_bytes(urls, block).await;
+	/// Announces the block to a random subset of the peers and sends it to those that lack it
+	pub async fn relay_block(&self, block: Block) {
+		let hash = block.header.hash;
+		self.relay(InvDataType::Block, hash, config::NEW_BLOCK_URL, |version| NewBlock {
+			version,
+			block: block.clone(),
+		}).await;
 	}
-	async fn broadcast_bytes<T>(urls: HashSet<Url>, msg: &T)
-		where T: Serialize + Send {
-		let mut handles = vec![];
-		let client = Client::new();
-
-		if let Ok(bytes) = standard_serialize(msg) {
-
-			for url in urls {
-				let bytes = bytes.clone();
+	/// Sends an `Inv` with the hash to `gossip_fanout` random peers, and the message to the path of the peers that request it.
+	/// Each peer gets the message built for the version negotiated with it. Items that were already relayed are not relayed again
+	async fn relay<T>(&self, data_type: InvDataType, hash: [u8; 32], path: &str, msg: impl Fn(u32) -> T)
+		where T: Serialize {
+		const ANNOUNCE_TIMEOUT: Duration = Duration::from_millis(500);
+		if !self.seen.write().await.insert(hash) {
+			return;
+		}
+		let format = WireFormat::default();
+		let peers = choose_fanout(self.peers.read().await.values(), self.config.gossip_fanout);
 
-				let url = url.clone();
-				let client = client.clone();
-				handles.push(tokio::spawn(async move {
-					Sender::send_bytes(&client, url, bytes.clone()).await.ok();
-				}));
-			}
+		let client = Client::new();
+		let mut handles = vec![];
+		for peer in peers {
+			let version = peer.protocol.version;
+			let Ok(bytes) = format.encode(&msg(version)) else {
+				log::error!("Unable to serialize the relayed item {} for peer {}", hex::encode(hash), peer.url);
+				continue;
+			};
+			let inv = Inv {
+				version,
+				data_type,
+				hashes: vec![hash],
+			};
+			let client = client.clone();
+			let mut url = peer.url.to_url();
+			url.set_path(path);
+			handles.push(tokio::spawn(async move {
+				let wanted = match tokio::time::timeout(ANNOUNCE_TIMEOUT, Sender::announce(&client, peer.url.to_url(), &inv)).await {
+					Ok(Ok(get_data)) => get_data.hashes.contains(&hash),
+					_ => false,
+				};
+				if wanted {
+					Sender::send_bytes(&client, url, bytes, format).await.ok();
+				}
+			}));
 		}
 		for h in handles {
 			h.await.ok();
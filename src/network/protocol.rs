@@ -0,0 +1,135 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::{Display, Formatter};
+use std::time::Duration;
+
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+
+use crate::data_storage::node_config_storage::url_serialize::PeerUrl;
+use crate::network::models::http_errors::ErrorType;
+use crate::network::sender::Sender;
+
+/// The newest protocol version spoken by this node. Version 1 introduced the handshake
+pub const PROTOCOL_VERSION: u32 = 1;
+/// The oldest protocol version still spoken by this node. Messages of older versions are rejected
+pub const MIN_PROTOCOL_VERSION: u32 = 1;
+
+/// The optional services offered by a node, as a set of bits. Unknown bits are kept, so new features can be announced before every node knows them
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Features(u64);
+impl Features {
+	/// Serves the blocks and headers of the whole chain
+	pub const FULL_CHAIN: Features = Features(1);
+	/// Serves the merkle proofs of transactions to light clients
+	pub const TX_PROOFS: Features = Features(1 << 1);
+	/// Accepts announcements of new transactions and blocks
+	pub const INV_RELAY: Features = Features(1 << 2);
+
+	pub fn none() -> Self {
+		Self(0)
+	}
+	/// The features of a full node
+	pub fn full_node() -> Self {
+		Self::FULL_CHAIN.with(Self::TX_PROOFS).with(Self::INV_RELAY)
+	}
+	pub fn with(self, other: Features) -> Self {
+		Self(self.0 | other.0)
+	}
+	pub fn contains(&self, other: Features) -> bool {
+		self.0 & other.0 == other.0
+	}
+}
+
+/// What a node supports, sent in the handshake through `/version` and `PairUp`
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct VersionInfo {
+	pub(crate) min_version: u32,
+	pub(crate) max_version: u32,
+	pub(crate) features: Features,
+	/// The hash of the genesis block of the chain of the node
+	pub(crate) chain_id: [u8; 32],
+}
+impl VersionInfo {
+	/// Returns the highest protocol version supported by both nodes.
+	/// Fails if the peer follows another chain or if their version ranges do not overlap
+	pub fn negotiate(&self, peer: &VersionInfo) -> Result<u32, ErrorType> {
+		if peer.chain_id != self.chain_id {
+			return Err(ErrorType::WrongChain(peer.chain_id, self.chain_id));
+		}
+		let version = self.max_version.min(peer.max_version);
+		if version < self.min_version.max(peer.min_version) {
+			return Err(self.unsupported(peer.max_version));
+		}
+		Ok(version)
+	}
+	pub fn supports(&self, version: u32) -> bool {
+		(self.min_version..=self.max_version).contains(&version)
+	}
+	/// The error answered to messages of a version that is not supported
+	pub fn unsupported(&self, version: u32) -> ErrorType {
+		ErrorType::WrongVersion(version, self.min_version, self.max_version)
+	}
+}
+
+/// The outcome of the handshake with a peer
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct PeerProtocol {
+	/// The version used in the messages sent to the peer
+	pub version: u32,
+	pub features: Features,
+}
+
+/// Why the handshake with a peer failed
+pub enum HandshakeError {
+	/// The peer did not answer, it may be tried again later
+	Unreachable(anyhow::Error),
+	/// The peer follows another chain or speaks no version in common with us
+	Incompatible(ErrorType),
+}
+impl Display for HandshakeError {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		match self {
+			HandshakeError::Unreachable(err) => write!(f, "Unreachable peer: {}", err),
+			HandshakeError::Incompatible(err) => write!(f, "Incompatible peer: {}", err),
+		}
+	}
+}
+
+/// Asks the peer for its versions and features and negotiates the protocol to use with it
+pub async fn handshake(client: &Client, peer: &PeerUrl, own: &VersionInfo) -> Result<PeerProtocol, HandshakeError> {
+	const HANDSHAKE_TIMEOUT: Duration = Duration::from_millis(500);
+	let info = match tokio::time::timeout(HANDSHAKE_TIMEOUT, Sender::get_version(client, peer.to_url())).await {
+		Ok(Ok(info)) => info,
+		Ok(Err(err)) => return Err(HandshakeError::Unreachable(err)),
+		Err(err) => return Err(HandshakeError::Unreachable(err.into())),
+	};
+	let version = own.negotiate(&info).map_err(HandshakeError::Incompatible)?;
+	Ok(PeerProtocol {
+		version,
+		features: info.features,
+	})
+}
+
+/// Negotiates the protocol with the peers that have not done the handshake yet, and forgets the protocols of removed peers.
+/// Incompatible peers are removed, unreachable ones are tried again on the next call
+pub async fn handshake_with_peers(client: &Client, own: &VersionInfo, peers: &RwLock<HashSet<PeerUrl>>, protocols: &RwLock<HashMap<PeerUrl, PeerProtocol>>) {
+	let new_peers: Vec<PeerUrl> = {
+		let peers = peers.read().await;
+		let mut protocols = protocols.write().await;
+		protocols.retain(|peer, _| peers.contains(peer));
+		peers.iter().filter(|peer| !protocols.contains_key(peer)).cloned().collect()
+	};
+	for peer in new_peers {
+		match handshake(client, &peer, own).await {
+			Ok(protocol) => {
+				protocols.write().await.insert(peer, protocol);
+			}
+			Err(HandshakeError::Incompatible(err)) => {
+				log::warn!("Removing peer {}: {}", peer.to_url(), err);
+				peers.write().await.remove(&peer);
+			}
+			Err(err) => log::debug!("Unable to do the handshake with peer {}: {}", peer.to_url(), err),
+		}
+	}
+}
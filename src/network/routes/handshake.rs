@@ -1,7 +1,26 @@
-use actix_web::{Responder, web};
+use actix_web::{HttpRequest, HttpResponse, Responder, web};
+use log::error;
 
+use crate::network::config::CHALLENGE_URL;
+use crate::network::models::Challenge;
 use crate::network::node::Node;
+use crate::network::standard::WireFormat;
 
-pub async fn handle_version(node: web::Data<Node>) -> impl Responder {
-	node.version.to_string()
-}
\ No newline at end of file
+/// Answers with the protocol versions, features and chain of the node
+pub async fn handle_version(node: web::Data<Node>, format: WireFormat) -> impl Responder {
+	format.respond(&node.version_info)
+}
+
+/// Answers with a new nonce that the sender must sign to pair up or unpair
+pub async fn handle_challenge(req: HttpRequest, node: web::Data<Node>, format: WireFormat) -> impl Responder {
+	let Some(address) = req.peer_addr() else {
+		error!("There was an internal server error when trying to handle request at {}", CHALLENGE_URL);
+		return HttpResponse::InternalServerError().finish();
+	};
+	let nonce = node.challenges.write().await.issue(address.ip());
+	format.respond(&Challenge {
+		version: node.version,
+		nonce,
+		public_key: node.public_key().to_vec(),
+	})
+}
@@ -1,109 +1,105 @@
 use std::collections::HashSet;
-use std::str::FromStr;
 
 use actix_web::{HttpRequest, HttpResponse, Responder, web};
 use log::error;
-use regex::Regex;
-use reqwest::Url;
 
 use crate::data_storage::node_config_storage::url_serialize::PeerUrl;
-use crate::network::config::{PAIR_UP_URL, UNPAIR_URL};
-use crate::network::models::{HttpScheme, PairUp, SendPeers};
+use crate::network::authentication::{pair_up_content, PAIR_UP_DOMAIN, PAIRED_UP_DOMAIN, Receiver, unpair_content, UNPAIR_DOMAIN};
+use crate::network::config::PAIR_UP_URL;
+use crate::network::limits::subnet_key;
+use crate::network::models::{PairedUp, PairUp, SendPeers, Unpair};
 use crate::network::models::http_errors::ErrorType;
-use crate::network::node::Node;
-use crate::network::standard;
-use crate::network::standard::StandardExtractor;
+use crate::network::node::{Node, Peer};
+use crate::network::protocol::PeerProtocol;
+use crate::network::standard::{StandardExtractor, WireFormat};
 
-pub const URL_REGEX: &str = r"(https?)://[0-9]{1,3}\.[a-zA-Z0-9]+\.[a-zA-Z0-9]+\.[a-zA-Z0-9]+:[0-9]{1,5}/[a-z, A-Z, 0-9, /]*$";
+pub async fn handle_get_peers(node: web::Data<Node>, format: WireFormat) -> impl Responder {
 
-pub async fn handle_get_peers(node: web::Data<Node>) -> impl Responder {
-
-	let peers: HashSet<String> = node.peers.read().await.iter().map(|url|url.to_url().to_string()).collect();
-	if let Ok(msg) = standard::standard_serialize(&SendPeers {
+	let peers: HashSet<String> = node.peers.read().await.values().map(|peer| peer.url.to_url().to_string()).collect();
+	format.respond(&SendPeers {
 		peers,
-	}) {
-		HttpResponse::Ok().body(msg)
-	} else {
-		HttpResponse::InternalServerError().finish()
-	}
+	})
 }
 pub async fn handle_pair_up(node: web::Data<Node>, msg: StandardExtractor<PairUp>, req: HttpRequest) -> impl Responder {
 	let request_version = msg.version;
-	let required_version = node.version;
-	if request_version != required_version { // TODO: Make version compatibility
-		return HttpResponse::BadRequest().body(ErrorType::WrongVersion(request_version, node.version).to_string());
+	if !node.version_info.supports(request_version) {
+		return HttpResponse::BadRequest().body(node.version_info.unsupported(request_version).to_string());
 	}
-
-	let request_port = msg.port;
-	let scheme = match msg.method {
-		HttpScheme::HTTP => {"http"}
-		HttpScheme::HTTPS => {"https"}
+	if node.is_sender_banned(&req) {
+		return HttpResponse::Forbidden().body(ErrorType::Banned.to_string());
+	}
+	let own_url = match node.url_reached_by(&req) {
+		Ok(url) => url,
+		Err(err) => return HttpResponse::BadRequest().body(ErrorType::InvalidUrl(err).to_string()),
 	};
-	if let Some(addr) = req.peer_addr() {
-		let url_string = format!("{}://{}:{}", scheme, addr.ip(), request_port);
-		let regex = Regex::new(URL_REGEX).expect("Unable to parse the URL_REGEX");
-		if regex.is_match(&url_string) { // TODO Do a check for size of peer list
-			if let Ok(url) = Url::from_str(&url_string) {
-				return if node.peers.read().await.len() < node.config.max_peers {
-					if node.peers.read().await.iter().any(|x| x.to_url().host_str() == Some(&addr.to_string())) { // If the address is already peer
-						HttpResponse::UnprocessableEntity().body("The given address is already a peer")
-					} else {
-						node.peers.write().await.insert(PeerUrl::new(url));
-						HttpResponse::Ok().finish()
-					}
-				} else {
-					HttpResponse::InsufficientStorage().body("Peer list full")
-				}
-			}
-			HttpResponse::BadRequest().body(ErrorType::InvalidUrl.to_string())
-		} else {
-			HttpResponse::BadRequest().body(ErrorType::InvalidUrl.to_string())
-		}
-	} else {
+	let receiver = Receiver { url: &own_url, public_key: node.public_key() };
+	let content = pair_up_content(request_version, &msg.info, msg.method, msg.port, &msg.challenge, receiver);
+	let Some(node_id) = node.verify_proof(&msg.proof, PAIR_UP_DOMAIN, &content).await else {
+		return HttpResponse::Unauthorized().body(ErrorType::InvalidProof.to_string());
+	};
+	if node_id == node.node_id() {
+		return HttpResponse::BadRequest().body("Unable to pair up with itself");
+	}
+	let protocol = match node.version_info.negotiate(&msg.info) {
+		Ok(version) => PeerProtocol {
+			version,
+			features: msg.info.features,
+		},
+		Err(err) => return HttpResponse::BadRequest().body(err.to_string()),
+	};
+
+	let Some(addr) = req.peer_addr() else {
 		error!("There was an internal server error when trying to handle request at {}", PAIR_UP_URL);
-		HttpResponse::InternalServerError().finish()
+		return HttpResponse::InternalServerError().finish();
+	};
+	let url = match PeerUrl::from_socket(msg.method, addr.ip(), msg.port) {
+		Ok(url) => url,
+		Err(err) => return HttpResponse::BadRequest().body(ErrorType::InvalidUrl(err).to_string()),
+	};
+
+	let mut peers = node.peers.write().await;
+	// The address is only given to the sender once the node that has it does not answer for it anymore, which is checked after answering
+	let previous_owner = peers.iter().find(|(id, peer)| peer.url == url && **id != node_id).map(|(id, _)| *id);
+	if let Some(previous_owner) = previous_owner {
+		node.check_address_owner(url.clone(), previous_owner);
+		return HttpResponse::Conflict().body(ErrorType::AddressInUse(url.to_url().to_string()).to_string());
+	}
+	if peers.len() >= node.config.max_peers && !peers.contains_key(&node_id) {
+		return HttpResponse::InsufficientStorage().body("Peer list full");
 	}
+	let subnet = subnet_key(addr.ip());
+	let peers_in_subnet = peers.iter()
+		.filter(|(id, peer)| **id != node_id && peer.url.ip().is_some_and(|ip| subnet_key(ip) == subnet))
+		.count();
+	if peers_in_subnet >= node.config.max_peers_per_subnet {
+		return HttpResponse::InsufficientStorage().body(ErrorType::TooManyPeersInSubnet(node.config.max_peers_per_subnet).to_string());
+	}
+	node.address_book.add(&url);
+	peers.insert(node_id, Peer { url, protocol }); // A known node may have changed its address
+	msg.response_format.respond(&PairedUp {
+		version: protocol.version,
+		proof: node.prove(PAIRED_UP_DOMAIN, msg.challenge, &[]),
+	})
 }
 
-pub async fn handle_unpair(node: web::Data<Node>, msg: StandardExtractor<PairUp>, req: HttpRequest) -> impl Responder {
+/// Removes the sender from our peers. Only the node itself can unpair, as it must sign a nonce of ours
+pub async fn handle_unpair(node: web::Data<Node>, msg: StandardExtractor<Unpair>, req: HttpRequest) -> impl Responder {
 	let request_version = msg.version;
-	let required_version = node.version;
-	if request_version != required_version { // TODO: Make version compatibility
-		return HttpResponse::BadRequest().body(ErrorType::WrongVersion(request_version, node.version).to_string());
+	if !node.version_info.supports(request_version) {
+		return HttpResponse::BadRequest().body(node.version_info.unsupported(request_version).to_string());
 	}
 
-	let request_port = msg.port;
-	let scheme = match msg.method {
-		HttpScheme::HTTP => {"http"}
-		HttpScheme::HTTPS => {"https"}
+	let own_url = match node.url_reached_by(&req) {
+		Ok(url) => url,
+		Err(err) => return HttpResponse::BadRequest().body(ErrorType::InvalidUrl(err).to_string()),
 	};
-
-	if let Some(addr) = req.peer_addr() {
-		let url_string = format!("{}://{}:{}", scheme, addr.ip(), request_port);
-		let regex = Regex::new(URL_REGEX).expect("Unable to parse the URL_REGEX");
-		if regex.is_match(&url_string) {
-			if let Ok(url) = Url::from_str(&url_string) {
-				let mut peers = node.peers.write().await;
-				if peers.remove(&PeerUrl::new(url)) {
-					return HttpResponse::Ok().finish()
-				} else {
-					for p in peers.clone() {
-						if let Some(host) = p.to_url().host_str() {
-							if host == addr.to_string() {
-								peers.remove(&p);
-								return HttpResponse::Ok().finish()
-							}
-						}
-					}
-					HttpResponse::BadRequest().body("The given address was not a peer");
-				}
-			}
-			HttpResponse::BadRequest().body(ErrorType::InvalidUrl.to_string())
-		} else {
-			HttpResponse::BadRequest().body(ErrorType::InvalidUrl.to_string())
-		}
+	let content = unpair_content(request_version, msg.method, msg.port, Receiver { url: &own_url, public_key: node.public_key() });
+	let Some(node_id) = node.verify_proof(&msg.proof, UNPAIR_DOMAIN, &content).await else {
+		return HttpResponse::Unauthorized().body(ErrorType::InvalidProof.to_string());
+	};
+	if node.peers.write().await.remove(&node_id).is_some() {
+		HttpResponse::Ok().finish()
 	} else {
-		error!("There was an internal server error when trying to handle request at {}", UNPAIR_URL);
-		HttpResponse::InternalServerError().finish()
+		HttpResponse::BadRequest().body("The given node was not a peer")
 	}
-}
\ No newline at end of file
+}
@@ -1,12 +1,14 @@
 use actix_web::{HttpResponse, Responder, web};
 
 use crate::core::block::BlockContent;
-use crate::network::models::{BlockchainInfo, BlocksData, GetBlocks, GetData, GetHeaders, Headers, Inv, InvDataType};
+use crate::core::utxo::transaction::{MAX_INPUTS_OR_OUTPUTS, Transaction};
+use crate::network::config::MAX_GET_DATA_ITEMS;
+use crate::network::models::{BlockchainInfo, BlocksData, EstimateFee, FeeEstimate, GetBlocks, GetData, GetHeaders, GetTxProof, Headers, Inv, InvDataType, TransactionsData, TxProof};
 use crate::network::models::http_errors::ErrorType;
 use crate::network::node::Node;
-use crate::network::standard::{standard_serialize, StandardExtractor};
+use crate::network::standard::{StandardExtractor, WireFormat};
 
-pub async fn handle_get_blockchain_info(node: web::Data<Node>) -> impl Responder {
+pub async fn handle_get_blockchain_info(node: web::Data<Node>, format: WireFormat) -> impl Responder {
 	let chain = node.blockchain.read().await;
 	let info = BlockchainInfo {
 		version: node.version,
@@ -14,85 +16,108 @@ pub async fn handle_get_blockchain_info(node: web::Data<Node>) -> impl Responder
 		best_block_header: chain.get_last_block().header,
 		mempool_size: chain.mempool.get_map().len()
 	};
-	if let Ok(serialized) = standard_serialize(&info) {
-		HttpResponse::Ok().body(serialized)
-	} else {
-		HttpResponse::InternalServerError().finish()
-	}
+	format.respond(&info)
 }
 
 pub async fn handle_get_blocks(node: web::Data<Node>, msg: StandardExtractor<GetBlocks>) -> impl Responder {
 	let request_version = msg.version;
-	let required_version = node.version;
-	if request_version != required_version { // TODO: Make version compatibility
-		return HttpResponse::BadRequest().body(ErrorType::WrongVersion(request_version, node.version).to_string());
+	if !node.version_info.supports(request_version) {
+		return HttpResponse::BadRequest().body(node.version_info.unsupported(request_version).to_string());
 	}
 
 	let blockchain = node.blockchain.read().await;
 	let last_known_blocks = msg.block_locator_object.clone();
 	let hashes = blockchain.get_blocks(&last_known_blocks);
 
-	if let Ok(msg) = standard_serialize(&Inv {
+	msg.response_format.respond(&Inv {
+		version: request_version,
 		data_type: InvDataType::Block,
 		hashes,
-	}) {
-		HttpResponse::Ok().body(msg)
-	} else {
-		HttpResponse::InternalServerError().finish()
-	}
+	})
 }
 
 pub async fn handle_get_data(node: web::Data<Node>, msg: StandardExtractor<GetData>) -> impl Responder {
 
 	let request_version = msg.version;
-	let required_version = node.version;
-	if request_version != required_version { // TODO: Make version compatibility
-		return HttpResponse::BadRequest().body(ErrorType::WrongVersion(request_version, node.version).to_string());
+	if !node.version_info.supports(request_version) {
+		return HttpResponse::BadRequest().body(node.version_info.unsupported(request_version).to_string());
 	}
 	
-	let blockchain = node.blockchain.read().await;
-	let data_type = msg.data_type;
 	let requested_data = &msg.hashes;
+	if requested_data.len() > MAX_GET_DATA_ITEMS {
+		return HttpResponse::BadRequest().body(ErrorType::TooManyItems(requested_data.len(), MAX_GET_DATA_ITEMS).to_string());
+	}
+	let blockchain = node.blockchain.read().await;
 	
-	match data_type {
+	match msg.data_type {
 		InvDataType::Transaction => {
-			// TODO
-			HttpResponse::Ok().finish()
+			let transactions = requested_data.iter().map(|txid| blockchain.get_transaction(txid)).collect();
+			msg.response_format.respond(&TransactionsData {
+				version: request_version,
+				transactions,
+			})
 		}
 		InvDataType::Block => {
-			let mut data = vec![];
-			for &hash in requested_data {
-				let block = blockchain.get_block_by(hash).map(|x| x.transactions.clone() as BlockContent);
-				data.push(block);
-			}
-			if let Ok(msg) = standard_serialize(&BlocksData {
-				version: node.version,
-				blocks_data: data,
-			}) {
-				HttpResponse::Ok().body(msg)
-			} else {
-				HttpResponse::InternalServerError().finish()
-			}
+			let blocks_data = requested_data.iter()
+				.map(|&hash| blockchain.get_block_by(hash).map(|block| block.transactions as BlockContent))
+				.collect();
+			msg.response_format.respond(&BlocksData {
+				version: request_version,
+				blocks_data,
+			})
 		}
 	}
 }
 pub async fn handle_get_headers(node: web::Data<Node>, msg: StandardExtractor<GetHeaders>) -> impl Responder {
 
 	let request_version = msg.version;
-	let required_version = node.version;
-	if request_version != required_version { // TODO: Make version compatibility
-		return HttpResponse::BadRequest().body(ErrorType::WrongVersion(request_version, node.version).to_string());
+	if !node.version_info.supports(request_version) {
+		return HttpResponse::BadRequest().body(node.version_info.unsupported(request_version).to_string());
 	}
 
 	let blockchain = node.blockchain.read().await;
 	let last_known_blocks = msg.block_locator_object.clone();
 	let headers = blockchain.get_headers(&last_known_blocks);
 
-	if let Ok(msg) = standard_serialize(&Headers {
+	msg.response_format.respond(&Headers {
 		headers,
-	}) {
-		HttpResponse::Ok().body(msg)
-	} else {
-		HttpResponse::InternalServerError().finish()
+	})
+}
+pub async fn handle_estimate_fee(node: web::Data<Node>, msg: StandardExtractor<EstimateFee>) -> impl Responder {
+	let request_version = msg.version;
+	if !node.version_info.supports(request_version) {
+		return HttpResponse::BadRequest().body(node.version_info.unsupported(request_version).to_string());
+	}
+	if msg.input_count >= MAX_INPUTS_OR_OUTPUTS || msg.output_count >= MAX_INPUTS_OR_OUTPUTS {
+		let requested = msg.input_count.max(msg.output_count);
+		return HttpResponse::BadRequest().body(ErrorType::TooManyItems(requested, MAX_INPUTS_OR_OUTPUTS - 1).to_string());
+	}
+
+	let blockchain = node.blockchain.read().await;
+	let estimate = FeeEstimate {
+		version: request_version,
+		fee_per_byte: blockchain.parameters.economic_parameters.fee_per_tx_byte,
+		estimated_size: Transaction::estimate_size(msg.input_count, msg.output_count),
+		estimated_fee: blockchain.estimate_fee(msg.input_count, msg.output_count),
+	};
+	msg.response_format.respond(&estimate)
+}
+pub async fn handle_get_tx_proof(node: web::Data<Node>, msg: StandardExtractor<GetTxProof>) -> impl Responder {
+	let request_version = msg.version;
+	if !node.version_info.supports(request_version) {
+		return HttpResponse::BadRequest().body(node.version_info.unsupported(request_version).to_string());
 	}
+
+	let blockchain = node.blockchain.read().await;
+	let Some(block) = blockchain.get_block_by(msg.block_hash) else {
+		return HttpResponse::NotFound().body("The block is not in the chain");
+	};
+	let Some(proof) = block.get_transaction_proof(&msg.txid) else {
+		return HttpResponse::NotFound().body("The transaction is not in the block");
+	};
+	msg.response_format.respond(&TxProof {
+		version: request_version,
+		header: block.header,
+		proof,
+	})
 }
@@ -1,50 +1,103 @@
-use actix_web::{HttpResponse, Responder, web};
+use actix_web::{HttpRequest, HttpResponse, Responder, web};
 use log::info;
 
-use crate::network::models::{NewBlock, NewTransaction};
+use crate::core::block::BlockError;
+use crate::core::utxo::transaction::TxError;
+use crate::data_storage::peer_storage::address_book::Misbehaviour;
+use crate::network::models::{GetData, Inv, InvDataType, NewBlock, NewTransaction};
 use crate::network::models::http_errors::ErrorType;
 use crate::network::node::Node;
 use crate::network::standard::StandardExtractor;
 
-pub async fn handle_tx(node: web::Data<Node>, msg: StandardExtractor<NewTransaction>) -> impl Responder {
+pub async fn handle_tx(req: HttpRequest, node: web::Data<Node>, msg: StandardExtractor<NewTransaction>) -> impl Responder {
 	let request_version = msg.version;
-	let required_version = node.version;
-	if request_version != required_version { // TODO: Make version compatibility
-		return HttpResponse::BadRequest().body(ErrorType::WrongVersion(request_version, node.version).to_string());
+	if !node.version_info.supports(request_version) {
+		return HttpResponse::BadRequest().body(node.version_info.unsupported(request_version).to_string());
 	}
-	
+	if node.is_sender_banned(&req) {
+		return HttpResponse::Forbidden().body(ErrorType::Banned.to_string());
+	}
+
 	let transaction = &msg.transaction;
 	let mut blockchain = node.blockchain.write().await;
-	if blockchain.add_transaction_to_mempool(transaction) {
-		info!("Got a new transaction. TXID: \"{:?}\"", transaction.id);
-		let peers = node.peers.read().await;
-		Node::broadcast_transaction(peers.clone(), &msg.into_inner()).await; // TODO: Actually check for duplicates
-		HttpResponse::Ok().finish()
-	} else {
-		HttpResponse::BadRequest().body(ErrorType::InvalidTransaction(blockchain.get_context()).to_string())
+	if node.seen.read().await.contains(&transaction.id) {
+		return HttpResponse::BadRequest().body(ErrorType::InvalidTransaction(TxError::AlreadyKnown, blockchain.get_context()).to_string());
+	}
+	match blockchain.add_transaction_to_mempool(transaction) {
+		Ok(()) => {
+			info!("Got a new transaction. TXID: \"{:?}\"", transaction.id);
+			let node = node.get_ref().clone();
+			let transaction = msg.into_inner().transaction;
+			tokio::spawn(async move { node.relay_transaction(transaction).await; });
+			HttpResponse::Ok().finish()
+		}
+		Err(err) => {
+			info!("Received invalid transaction: {}", err);
+			HttpResponse::BadRequest().body(ErrorType::InvalidTransaction(err, blockchain.get_context()).to_string())
+		}
 	}
 }
 
-pub async fn handle_block(node: web::Data<Node>, msg: StandardExtractor<NewBlock>) -> impl Responder {
+pub async fn handle_block(req: HttpRequest, node: web::Data<Node>, msg: StandardExtractor<NewBlock>) -> impl Responder {
 	// TODO CHECK IF THE BLOCK IS THE SAME HEIGHT AS THE CURRENT ONE AND STILL VALID
 	// TODO: CHECK IF BLOCK IS BEFORE CURRENT SLOT BUT AFTER LAST'S BLOCK SLOT
 	// TODO: IF SLOT IS SAMES AS LAST BLOCK AND HEIGHT IS SAMES AS LAST BLOCK, CHECK FOR LOTTERY
 	let request_version = msg.version;
-	let required_version = node.version;
-	if request_version != required_version { // TODO: Make version compatibility
-		return HttpResponse::BadRequest().body(ErrorType::WrongVersion(request_version, node.version).to_string());
+	if !node.version_info.supports(request_version) {
+		return HttpResponse::BadRequest().body(node.version_info.unsupported(request_version).to_string());
+	}
+	if node.is_sender_banned(&req) {
+		return HttpResponse::Forbidden().body(ErrorType::Banned.to_string());
 	}
 
 	let block = &msg.block;
 	let mut blockchain = node.blockchain.write().await;
-	if blockchain.add_block(block) {
-		info!("Received valid block");
+	if node.seen.read().await.contains(&block.header.hash) {
+		return HttpResponse::BadRequest().body(ErrorType::InvalidBlock(BlockError::AlreadyKnown, blockchain.get_context()).to_string());
+	}
+	match blockchain.add_block(block) {
+		Ok(_) => {
+			info!("Received valid block");
+			let node = node.get_ref().clone();
+			let block = msg.into_inner().block;
+			tokio::spawn(async move { node.relay_block(block).await; });
+			HttpResponse::Ok().finish()
+		}
+		Err(err) => {
+			info!("Received invalid block: {}", err);
+			let context = blockchain.get_context();
+			drop(blockchain);
+			if err.is_provably_invalid() {
+				node.punish_sender(&req, Misbehaviour::InvalidBlock).await;
+			}
+			HttpResponse::BadRequest().body(ErrorType::InvalidBlock(err, context).to_string())
+		}
+	}
+}
 
-		// TODO: Uncomment when no more testing
-		// node.broadcast_block(&msg.into_inner()).await; // TODO: Actually check for duplicates
-		HttpResponse::Ok().finish()
-	} else {
-		info!("Received invalid block");
-		HttpResponse::BadRequest().body(ErrorType::InvalidBlock(blockchain.get_context()).to_string())
+/// Answers an announcement with the request of the announced items that we lack
+pub async fn handle_inv(req: HttpRequest, node: web::Data<Node>, msg: StandardExtractor<Inv>) -> impl Responder {
+	let request_version = msg.version;
+	if !node.version_info.supports(request_version) {
+		return HttpResponse::BadRequest().body(node.version_info.unsupported(request_version).to_string());
 	}
+	if node.is_sender_banned(&req) {
+		return HttpResponse::Forbidden().body(ErrorType::Banned.to_string());
+	}
+
+	let blockchain = node.blockchain.read().await;
+	let seen = node.seen.read().await;
+	let hashes = msg.hashes.iter()
+		.filter(|hash| !seen.contains(hash))
+		.filter(|hash| match msg.data_type {
+			InvDataType::Transaction => !blockchain.mempool.contains(hash),
+			InvDataType::Block => !blockchain.contains_block(hash),
+		})
+		.copied()
+		.collect();
+	msg.response_format.respond(&GetData {
+		version: request_version,
+		data_type: msg.data_type,
+		hashes,
+	})
 }
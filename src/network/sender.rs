@@ -1,28 +1,74 @@
 use std::collections::HashSet;
 use std::error::Error;
+use std::fmt::{Display, Formatter};
 use std::io;
 use std::io::ErrorKind;
 use std::time::Duration;
 
-use reqwest::{Client, Response, StatusCode, Url};
+use reqwest::{Client, RequestBuilder, Response, StatusCode, Url};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
 
-use crate::network::{config, standard};
-use crate::network::models::{BlockchainInfo, PairUp};
-use crate::network::standard::{standard_deserialize, standard_serialize};
+use crate::core::parameters::NetworkParameters;
+use crate::network::config;
+use crate::network::limits::PayloadLimit;
+use crate::network::models::{BlockchainInfo, BlocksData, Challenge, GetData, GetHeaders, GetTxProof, Headers, Inv, PairedUp, PairUp, SendPeers, TransactionsData, TxProof, Unpair};
+use crate::network::protocol::VersionInfo;
+use crate::network::standard::WireFormat;
 
 pub struct Sender;
 
+/// The error of a response whose body is larger than the limit of its message. Holds the limit
+#[derive(Debug, Eq, PartialEq)]
+pub struct ResponseTooLarge(pub usize);
+impl Display for ResponseTooLarge {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		write!(f, "The response is larger than {} bytes", self.0)
+	}
+}
+impl Error for ResponseTooLarge {}
+
 impl Sender {
+	/// Encodes the message in the body of the request, in the default wire format, and asks for the response in the same format
+	fn with_body<T>(request: RequestBuilder, msg: &T) -> anyhow::Result<RequestBuilder>
+		where T: Serialize {
+		let format = WireFormat::default();
+		Ok(Self::accepting(request)
+			.body(format.encode(msg)?)
+			.header(reqwest::header::CONTENT_TYPE, format.content_type()))
+	}
+	/// Asks for the response in the default wire format
+	fn accepting(request: RequestBuilder) -> RequestBuilder {
+		request.header(reqwest::header::ACCEPT, WireFormat::default().content_type())
+	}
+	/// Decodes the body of the response in the format given by its Content-Type.
+	/// The body is read as it arrives and fails with `ResponseTooLarge` as soon as it exceeds the limit
+	async fn read_response<T>(mut response: Response, limit: PayloadLimit) -> anyhow::Result<T>
+		where T: DeserializeOwned {
+		let format = response.headers().get(reqwest::header::CONTENT_TYPE)
+			.and_then(|value| value.to_str().ok())
+			.and_then(WireFormat::from_media_type)
+			.unwrap_or_default();
+		let limit = limit.encoded_limit(format);
+		if response.content_length().is_some_and(|length| length > limit as u64) {
+			return Err(ResponseTooLarge(limit).into());
+		}
+		let mut data = Vec::new();
+		while let Some(chunk) = response.chunk().await? {
+			if data.len() + chunk.len() > limit {
+				return Err(ResponseTooLarge(limit).into());
+			}
+			data.extend_from_slice(&chunk);
+		}
+		format.decode(&data)
+	}
 	pub async fn get_blockchain_info(client: &Client, mut peer: Url) -> Result<BlockchainInfo, Box<impl std::error::Error>> {
 		peer.set_path(config::GET_BLOCKCHAIN_INFO_URL);
-		return match client.get(peer)
+		return match Self::accepting(client.get(peer))
 			.send().await {
 			Ok(response) => {
-				if let Ok(bytes) = response.bytes().await {
-					let data = bytes.to_vec();
-					if let Ok(info) = standard_deserialize::<BlockchainInfo>(data.as_slice()) {
-						return Ok(info);
-					}
+				if let Ok(info) = Self::read_response::<BlockchainInfo>(response, PayloadLimit::default()).await {
+					return Ok(info);
 				}
 				Err(Box::new(io::Error::new(ErrorKind::InvalidData, "Invalid data")))
 			}
@@ -31,10 +77,11 @@ impl Sender {
 			}
 		}
 	}
-	pub async fn send_bytes(client: &Client, url: Url, bytes: Vec<u8>) -> Result<String, String> {
+	/// Sends a message already encoded in the given wire format
+	pub async fn send_bytes(client: &Client, url: Url, bytes: Vec<u8>, format: WireFormat) -> Result<String, String> {
 		let send = client.post(url.clone())
 			.body(bytes)
-			.header(reqwest::header::CONTENT_TYPE, standard::DATA_TYPE) // Set the content type
+			.header(reqwest::header::CONTENT_TYPE, format.content_type()) // Set the content type
 			.send();
 
 		if let Ok(result) = tokio::time::timeout(Duration::from_millis(500), send).await {
@@ -52,35 +99,84 @@ impl Sender {
 			Err("Timeout".to_string())
 		}
 	}
-	pub async fn pair_up_with(client: &Client, peer: Url, msg: PairUp) -> reqwest::Result<bool> {
+	/// Returns the protocol versions, features and chain of the peer
+	pub async fn get_version(client: &Client, peer: Url) -> anyhow::Result<VersionInfo> {
+		let mut url = peer;
+		url.set_path(config::VERSION_URL);
+		let response = Self::accepting(client.get(url))
+			.send().await?;
+		Self::read_response::<VersionInfo>(response.error_for_status()?, PayloadLimit::default()).await
+	}
+	/// Returns a nonce that must be signed in the next `PairUp` or `Unpair` sent to the peer
+	pub async fn get_challenge(client: &Client, peer: Url) -> anyhow::Result<Challenge> {
+		let mut url = peer;
+		url.set_path(config::CHALLENGE_URL);
+		let response = Self::accepting(client.get(url))
+			.send().await?;
+		Self::read_response::<Challenge>(response.error_for_status()?, PayloadLimit::default()).await
+	}
+	/// Asks the peer to add us to its peers. Returns its answer, which proves its identity
+	pub async fn pair_up_with(client: &Client, peer: Url, msg: PairUp) -> anyhow::Result<PairedUp> {
 		let mut url = peer;
 		url.set_path(config::PAIR_UP_URL);
-		let response = client.post(url)
-			.body(standard_serialize(&msg).expect("Unable to deserialize"))
-			.header(reqwest::header::CONTENT_TYPE, standard::DATA_TYPE) // Set the content type
+		let response = Self::with_body(client.post(url), &msg)?
+			.send().await?;
+		Self::read_response::<PairedUp>(response.error_for_status()?, PayloadLimit::default()).await
+	}
+	/// Asks the peer to remove us from its peers
+	pub async fn unpair_from(client: &Client, peer: Url, msg: Unpair) -> anyhow::Result<bool> {
+		let mut url = peer;
+		url.set_path(config::UNPAIR_URL);
+		let response = Self::with_body(client.delete(url), &msg)?
 			.send().await?;
 		Ok(response.status() == StatusCode::OK)
 	}
 	pub async fn get_peers(client: &Client, peer: Url) -> anyhow::Result<HashSet<String>> {
 		let mut url = peer;
 		url.set_path(config::GET_PEERS_URL);
-		let response = client.get(url)
-			.header(reqwest::header::CONTENT_TYPE, standard::DATA_TYPE) // Set the content type
+		let response = Self::accepting(client.get(url))
 			.send().await?;
-		match response.bytes().await.map(|x|x.to_vec()) {
-			Ok(data) => {
-				match standard_deserialize::<HashSet<String>>(data.as_slice()) {
-					Ok(info) => {
-						Ok(info)
-					}
-					Err(e) => {
-						Err(e)
-					}
-				}
-			}
-			Err(e) => {
-				Err(e.into())
-			}
-		}
+		Self::read_response::<SendPeers>(response, PayloadLimit::default()).await.map(|msg| msg.peers)
+	}
+	pub async fn get_headers(client: &Client, peer: Url, msg: GetHeaders, network_parameters: &NetworkParameters) -> anyhow::Result<Headers> {
+		let mut url = peer;
+		url.set_path(config::GET_HEADERS_URL);
+		let response = Self::with_body(client.get(url), &msg)?
+			.send().await?;
+		Self::read_response::<Headers>(response.error_for_status()?, PayloadLimit::headers(network_parameters)).await
+	}
+	/// Asks for the bodies of blocks by hash
+	pub async fn get_data(client: &Client, peer: Url, msg: GetData, network_parameters: &NetworkParameters) -> anyhow::Result<BlocksData> {
+		let mut url = peer;
+		url.set_path(config::GET_DATA_URL);
+		let limit = PayloadLimit::block_bodies(network_parameters, msg.hashes.len());
+		let response = Self::with_body(client.get(url), &msg)?
+			.send().await?;
+		Self::read_response::<BlocksData>(response.error_for_status()?, limit).await
+	}
+	/// Asks for transactions by id, from the mempool or the chain of the peer
+	pub async fn get_transactions(client: &Client, peer: Url, msg: GetData, network_parameters: &NetworkParameters) -> anyhow::Result<TransactionsData> {
+		let mut url = peer;
+		url.set_path(config::GET_DATA_URL);
+		let limit = PayloadLimit::transaction_list(network_parameters, msg.hashes.len());
+		let response = Self::with_body(client.get(url), &msg)?
+			.send().await?;
+		Self::read_response::<TransactionsData>(response.error_for_status()?, limit).await
+	}
+	/// Asks for the merkle proof of a transaction. The proof must be verified against a header known to be in the chain
+	pub async fn get_tx_proof(client: &Client, peer: Url, msg: GetTxProof) -> anyhow::Result<TxProof> {
+		let mut url = peer;
+		url.set_path(config::GET_TX_PROOF_URL);
+		let response = Self::with_body(client.get(url), &msg)?
+			.send().await?;
+		Self::read_response::<TxProof>(response.error_for_status()?, PayloadLimit::default()).await
+	}
+	/// Announces items to the peer. Returns the request of the items that the peer lacks
+	pub async fn announce(client: &Client, peer: Url, msg: &Inv) -> anyhow::Result<GetData> {
+		let mut url = peer;
+		url.set_path(config::INV_URL);
+		let response = Self::with_body(client.post(url), msg)?
+			.send().await?;
+		Self::read_response::<GetData>(response.error_for_status()?, PayloadLimit::default()).await
 	}
-}
\ No newline at end of file
+}
@@ -1,34 +1,194 @@
-use actix_web::web::Json;
+use std::future::Future;
+use std::ops::Deref;
+use std::pin::Pin;
+
+use actix_web::{FromRequest, HttpRequest, HttpResponse};
+use actix_web::dev::Payload;
+use actix_web::error::{ErrorBadRequest, InternalError};
+use actix_web::http::header;
+use actix_web::web;
+use actix_web::web::Data;
+use anyhow::anyhow;
 use bincode::error::{DecodeError, EncodeError};
 use serde::de::DeserializeOwned;
-use serde::Serialize;
-
-// const DATA_TYPE: &str = "application/octet-stream";
-pub(crate) const DATA_TYPE: &str = "application/json"; // TODO CHANGE THIS AT "application/octet-stream" WHEN NOT TESTING
-
-pub type StandardExtractor<T> = Json<T>; // TODO CHANGE THIS AT BincodeSerde WHEN NOT TESTING
-// pub type StandardExtractor<T> = BincodeSerde<T>;
-
-// pub fn standard_serialize<T>(object: &T) -> anyhow::Result<Vec<u8>> // TODO CHANGE THIS AT BincodeSerde WHEN NOT TESTING
-// 	where T: Serialize{
-// 	match serialize_bincode(object) {
-// 		Ok(data) => {
-// 			Ok(data)
-// 		}
-// 		Err(err) => {
-// 			log::error!("Unable to serialize object. Error: {}", err);
-// 			Err(err.into())
-// 		}
-// 	}
-// }
-// pub fn standard_deserialize<T>(object: &[u8]) -> anyhow::Result<T>
-// 	where T: DeserializeOwned {
-// 	Ok(deserialize_bincode(object)?.0)
-// }
-pub fn standard_serialize<T>(object: &T) -> anyhow::Result<Vec<u8>> // TODO CHANGE THIS AT BincodeSerde WHEN NOT TESTING
+use serde::{Deserialize, Serialize};
+
+use crate::data_storage::node_config_storage::url_serialize::canonical_ip;
+use crate::data_storage::peer_storage::address_book::{AddressBook, Misbehaviour};
+use crate::network::limits::PayloadLimit;
+use crate::network::models::http_errors::ErrorType;
+
+pub(crate) const BINCODE_DATA_TYPE: &str = "application/octet-stream";
+pub(crate) const JSON_DATA_TYPE: &str = "application/json";
+/// The version of the envelope that wraps every message sent between nodes. Messages with a different version are rejected
+pub(crate) const WIRE_VERSION: u16 = 1;
+
+/// The encoding of the messages exchanged between nodes. Nodes use bincode by default, JSON is meant for debugging
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum WireFormat {
+	#[default]
+	Bincode,
+	Json,
+}
+
+/// Every message sent between nodes is wrapped in this envelope, so that the wire format can evolve
+#[derive(Deserialize, Serialize)]
+struct Envelope<T> {
+	wire_version: u16,
+	payload: T,
+}
+
+impl WireFormat {
+	pub fn content_type(&self) -> &'static str {
+		match self {
+			WireFormat::Bincode => BINCODE_DATA_TYPE,
+			WireFormat::Json => JSON_DATA_TYPE,
+		}
+	}
+	/// Returns the format of the given media type, ignoring its parameters (like the charset)
+	pub fn from_media_type(media_type: &str) -> Option<Self> {
+		let essence = media_type.split(';').next().unwrap_or_default().trim();
+		if essence.eq_ignore_ascii_case(BINCODE_DATA_TYPE) {
+			Some(WireFormat::Bincode)
+		} else if essence.eq_ignore_ascii_case(JSON_DATA_TYPE) {
+			Some(WireFormat::Json)
+		} else {
+			None
+		}
+	}
+	/// Returns the first known format of an Accept header, or None if it accepts anything
+	pub fn from_accept(accept: &str) -> Option<Self> {
+		accept.split(',').find_map(Self::from_media_type)
+	}
+	/// Returns the format in which the request wants the response: the one in the Accept header or,
+	/// if it accepts anything, the one in which the request was sent
+	pub fn from_request(req: &HttpRequest) -> Self {
+		let accept = req.headers().get(header::ACCEPT).and_then(|value| value.to_str().ok());
+		accept.and_then(Self::from_accept)
+			.or_else(|| Self::get_content_type(req).ok().flatten())
+			.unwrap_or_default()
+	}
+	/// Returns the format of the body of the request, None if it has no Content-Type or an error if it is not a known one
+	fn get_content_type(req: &HttpRequest) -> anyhow::Result<Option<Self>> {
+		let Some(content_type) = req.headers().get(header::CONTENT_TYPE) else {
+			return Ok(None);
+		};
+		let content_type = content_type.to_str()?;
+		Self::from_media_type(content_type).map(Some).ok_or_else(|| anyhow!("Unsupported content type {}", content_type))
+	}
+	/// Wraps the object in the envelope and encodes it
+	pub fn encode<T>(&self, object: &T) -> anyhow::Result<Vec<u8>>
+		where T: Serialize {
+		let envelope = Envelope { wire_version: WIRE_VERSION, payload: object };
+		match self {
+			WireFormat::Bincode => Ok(serialize_bincode(&envelope)?),
+			WireFormat::Json => Ok(serde_json::to_vec(&envelope)?),
+		}
+	}
+	/// Decodes an envelope and returns its content. Fails if the envelope has a different version
+	pub fn decode<T>(&self, data: &[u8]) -> anyhow::Result<T>
+		where T: DeserializeOwned {
+		let envelope: Envelope<T> = match self {
+			WireFormat::Bincode => {
+				let (envelope, read) = deserialize_bincode(data)?;
+				if read != data.len() {
+					return Err(anyhow!("Unexpected {} bytes after the message", data.len() - read));
+				}
+				envelope
+			}
+			WireFormat::Json => serde_json::from_slice(data)?,
+		};
+		if envelope.wire_version != WIRE_VERSION {
+			return Err(anyhow!("Unsupported wire version {}, expected {}", envelope.wire_version, WIRE_VERSION));
+		}
+		Ok(envelope.payload)
+	}
+	/// Creates an Ok response with the encoded object
+	pub fn respond<T>(&self, object: &T) -> HttpResponse
+		where T: Serialize {
+		match self.encode(object) {
+			Ok(data) => HttpResponse::Ok().content_type(self.content_type()).body(data),
+			Err(err) => {
+				log::error!("Unable to serialize response. Error: {}", err);
+				HttpResponse::InternalServerError().finish()
+			}
+		}
+	}
+}
+impl FromRequest for WireFormat {
+	type Error = actix_web::Error;
+	type Future = std::future::Ready<Result<Self, Self::Error>>;
+
+	fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+		std::future::ready(Ok(Self::from_request(req)))
+	}
+}
+
+/// Extracts a message from the body of a request, in the format given by its Content-Type.
+/// It also keeps the format in which the response should be sent. Bodies larger than the `PayloadLimit` of the route are rejected,
+/// and senders of messages that can not be decoded are penalized
+pub struct StandardExtractor<T> {
+	data: T,
+	pub(crate) response_format: WireFormat,
+}
+impl<T> StandardExtractor<T> {
+	pub fn into_inner(self) -> T {
+		self.data
+	}
+}
+impl<T> Deref for StandardExtractor<T> {
+	type Target = T;
+
+	fn deref(&self) -> &Self::Target {
+		&self.data
+	}
+}
+impl<T> FromRequest for StandardExtractor<T>
+	where T: DeserializeOwned + 'static {
+	type Error = actix_web::Error;
+	type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+	fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+		let request_format = match WireFormat::get_content_type(req) {
+			Ok(request_format) => request_format.unwrap_or_default(),
+			Err(err) => return Box::pin(std::future::ready(Err(actix_web::error::ErrorUnsupportedMediaType(err)))),
+		};
+		let response_format = WireFormat::from_request(req);
+		let limit = req.app_data::<PayloadLimit>().copied().unwrap_or_default().encoded_limit(request_format);
+		let content_length = req.headers().get(header::CONTENT_LENGTH)
+			.and_then(|length| length.to_str().ok())
+			.and_then(|length| length.parse::<usize>().ok());
+		if content_length.is_some_and(|length| length > limit) {
+			return Box::pin(std::future::ready(Err(payload_too_large(limit))));
+		}
+		let body = web::Payload::from_request(req, payload);
+		let address_book = req.app_data::<Data<AddressBook>>().cloned();
+		let sender = req.peer_addr().map(|address| canonical_ip(address.ip()).to_string());
+		Box::pin(async move {
+			let bytes = body.await?.to_bytes_limited(limit).await.map_err(|_| payload_too_large(limit))??;
+			let data = request_format.decode(&bytes).map_err(|err| {
+				if let (Some(address_book), Some(sender)) = (&address_book, &sender) {
+					address_book.record_host_misbehaviour(sender, Misbehaviour::MalformedMessage);
+				}
+				ErrorBadRequest(err)
+			})?;
+			Ok(StandardExtractor { data, response_format })
+		})
+	}
+}
+
+/// The error of a request whose body is larger than the limit of its route
+fn payload_too_large(limit: usize) -> actix_web::Error {
+	let error = ErrorType::PayloadTooLarge(limit).to_string();
+	InternalError::from_response(error.clone(), HttpResponse::PayloadTooLarge().body(error)).into()
+}
+
+/// Serializes objects to be stored on disk. Messages between nodes use `WireFormat` instead
+pub fn standard_serialize<T>(object: &T) -> anyhow::Result<Vec<u8>>
 	where T: Serialize{
 	Ok(serde_json::to_vec(object)?)
 }
+/// Deserializes objects stored on disk
 pub fn standard_deserialize<'a, T>(object: &'a [u8]) -> anyhow::Result<T>
 	where T: serde::Deserialize<'a> {
 	Ok(serde_json::from_slice::<T>(object)?)
@@ -42,4 +202,4 @@ pub fn deserialize_bincode<T>(object: &[u8]) -> Result<(T, usize), DecodeError>
 pub fn serialize_bincode<T>(object: &T) -> Result<Vec<u8>, EncodeError>
 	where T: Serialize {
 	bincode::serde::encode_to_vec(object, bincode::config::standard())
-}
\ No newline at end of file
+}
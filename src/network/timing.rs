@@ -1,9 +1,8 @@
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::time::Duration;
 
 use rsntp::{AsyncSntpClient, SynchronizationError};
 
-use crate::network::node::STARTING_SLOT_SECOND;
-
 /// Syncs to the next slot
 pub async fn sync_to_slot(ntp_client: &AsyncSntpClient, slot_time_in_millis: u64) {
 	const ONE_MICROSECOND: u64 = 1000000;
@@ -15,10 +14,40 @@ pub async fn sync_to_slot(ntp_client: &AsyncSntpClient, slot_time_in_millis: u64
 		.expect("Unable to sync with NTP server") * slot_time_in_millis as u32 / 1000).subsec_micros();
 	spin_sleep::sleep(Duration::from_micros(ONE_MICROSECOND - offset_in_micros as u64));
 }
-pub async fn get_accurate_slot(ntp_client: &AsyncSntpClient, slot_time_in_millis: u64) -> Result<u64, SynchronizationError> {
-	let slot_time = ntp_client.synchronize("time.google.com").await?
+/// Returns the slot at the given unix time of a chain that started at the given unix time in seconds, or None if the chain has not started yet
+pub fn slot_at(unix_time: Duration, slot_time_in_millis: u64, start_time: u64) -> Option<u64> {
+	let elapsed = unix_time.checked_sub(Duration::from_secs(start_time))?;
+	Some(elapsed.as_millis() as u64 / slot_time_in_millis)
+}
+/// Returns the unix time according to the NTP server
+async fn get_ntp_time(ntp_client: &AsyncSntpClient) -> Result<Duration, SynchronizationError> {
+	Ok(ntp_client.synchronize("time.google.com").await?
 		.datetime()
 		.unix_timestamp()
-		.expect("Time went backwards") - Duration::from_secs(STARTING_SLOT_SECOND);
-	Ok(slot_time.as_millis() as u64 / slot_time_in_millis)
-}
\ No newline at end of file
+		.expect("Time went backwards"))
+}
+/// Returns the current slot of a chain that started at the given unix time in seconds. Before the start of the chain it is the slot 0
+pub async fn get_accurate_slot(ntp_client: &AsyncSntpClient, slot_time_in_millis: u64, start_time: u64) -> Result<u64, SynchronizationError> {
+	let now = get_ntp_time(ntp_client).await?;
+	Ok(slot_at(now, slot_time_in_millis, start_time).unwrap_or(0))
+}
+/// Sets the slot to the current slot according to the NTP server. If the server can not be reached, the slot is kept
+pub async fn resync_slot(ntp_client: &AsyncSntpClient, current_slot: &AtomicU64, slot_time_in_millis: u64, start_time: u64) {
+	match get_accurate_slot(ntp_client, slot_time_in_millis, start_time).await {
+		Ok(slot) => current_slot.store(slot, Ordering::Relaxed),
+		Err(err) => log::error!("Unable to synchronize with NTP server. Error: {}", err),
+	}
+}
+/// Returns the current slot of a chain that started at the given unix time in seconds.
+/// If the chain has not started yet, waits until it starts
+pub async fn wait_for_current_slot(ntp_client: &AsyncSntpClient, slot_time_in_millis: u64, start_time: u64) -> Result<u64, SynchronizationError> {
+	loop {
+		let now = get_ntp_time(ntp_client).await?;
+		if let Some(slot) = slot_at(now, slot_time_in_millis, start_time) {
+			return Ok(slot);
+		}
+		let remaining = Duration::from_secs(start_time) - now;
+		log::info!("The chain starts in {} seconds, waiting for it", remaining.as_secs() + 1);
+		tokio::time::sleep(remaining).await;
+	}
+}
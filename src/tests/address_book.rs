@@ -0,0 +1,104 @@
+use std::time::Duration;
+
+use crate::data_storage::node_config_storage::url_serialize::PeerUrl;
+use crate::data_storage::peer_storage::address_book::{AddressBook, EVICTION_BATCH, MAX_RECORDS, Misbehaviour};
+use crate::tests::temporary_directory;
+
+fn peer(i: u8) -> PeerUrl {
+	PeerUrl::parse(&format!("http://10.0.0.{}:9812", i)).unwrap()
+}
+
+#[test]
+fn records_survive_reopening() {
+	let directory = temporary_directory("address-book");
+	let address_book = AddressBook::open(&directory, Duration::from_secs(60));
+	address_book.record_success(&peer(1), Duration::from_millis(100));
+	address_book.record_success(&peer(1), Duration::from_millis(300));
+	address_book.record_failure(&peer(1));
+	drop(address_book);
+
+	let address_book = AddressBook::open(&directory, Duration::from_secs(60));
+	let record = address_book.get(&peer(1)).expect("The record was not stored");
+	assert_eq!((record.successes, record.failures), (2, 1));
+	assert_eq!(record.latency_millis, Some(150));
+	assert!(record.last_seen.is_some());
+	assert!(address_book.get(&peer(2)).is_none());
+	let _ = std::fs::remove_dir_all(directory);
+}
+
+#[test]
+fn misbehaving_peers_are_banned() {
+	let directory = temporary_directory("address-book");
+	let address_book = AddressBook::open(&directory, Duration::from_secs(60));
+	// An invalid block is enough to be banned
+	assert!(address_book.record_misbehaviour(&peer(1), Misbehaviour::InvalidBlock));
+	assert!(address_book.is_banned(&peer(1)));
+	// Malformed messages are tolerated a few times
+	for _ in 0..4 {
+		assert!(!address_book.record_host_misbehaviour("10.0.0.2", Misbehaviour::MalformedMessage));
+	}
+	assert!(!address_book.is_banned(&peer(2)));
+	assert!(address_book.record_host_misbehaviour("10.0.0.2", Misbehaviour::MalformedMessage));
+	// A banned host bans every peer on it
+	assert!(address_book.is_host_banned("10.0.0.2"));
+	assert!(address_book.is_banned(&peer(2)));
+	assert!(!address_book.is_banned(&peer(3)));
+	assert!(address_book.record_host_misbehaviour("::1", Misbehaviour::InvalidBlock));
+	assert!(address_book.is_host_banned("::1"));
+	// Hosts are compared in their canonical form
+	assert!(address_book.is_host_banned("[::ffff:10.0.0.2]"));
+	assert!(address_book.is_banned(&PeerUrl::parse("http://[::1]:9812").unwrap()));
+	// Bans expire
+	let expiring_directory = temporary_directory("address-book");
+	let expiring = AddressBook::open(&expiring_directory, Duration::ZERO);
+	assert!(!expiring.record_misbehaviour(&peer(4), Misbehaviour::InvalidBlock));
+	assert!(!expiring.is_banned(&peer(4)));
+	let _ = std::fs::remove_dir_all(directory);
+	let _ = std::fs::remove_dir_all(expiring_directory);
+}
+
+#[test]
+fn best_peers_are_reliable_and_fast() {
+	let directory = temporary_directory("address-book");
+	let address_book = AddressBook::open(&directory, Duration::from_secs(60));
+	address_book.record_success(&peer(1), Duration::from_millis(500));
+	address_book.record_success(&peer(2), Duration::from_millis(50));
+	address_book.record_success(&peer(3), Duration::from_millis(10));
+	address_book.record_failure(&peer(3));
+	address_book.add(&peer(4));
+	address_book.record_success(&peer(5), Duration::from_millis(1));
+	address_book.record_misbehaviour(&peer(5), Misbehaviour::InvalidBlock);
+	address_book.record_host_misbehaviour("10.0.0.6", Misbehaviour::MalformedMessage);
+
+	let best_peers = |n| address_book.best_peers(n).iter().map(|peer| peer.to_url().to_string()).collect::<Vec<String>>();
+	let expected: Vec<String> = [2, 1, 4, 3].into_iter().map(|i| peer(i).to_url().to_string()).collect();
+	assert_eq!(best_peers(10), expected);
+	assert_eq!(best_peers(1), expected[..1]);
+	let _ = std::fs::remove_dir_all(directory);
+}
+
+#[test]
+fn least_valuable_records_are_evicted() {
+	let directory = temporary_directory("address-book");
+	let address_book = AddressBook::open(&directory, Duration::from_secs(60));
+	address_book.record_misbehaviour(&peer(1), Misbehaviour::InvalidBlock);
+	address_book.record_success(&peer(2), Duration::from_millis(10));
+	let mut last = None;
+	for i in 0..MAX_RECORDS + 10 {
+		let url = PeerUrl::parse(&format!("http://10.1.{}.{}:9812", i / 256, i % 256)).unwrap();
+		address_book.add(&url);
+		last = Some(url);
+	}
+	// A whole batch made room when the records of the two peers above and the new ones exceeded the maximum
+	let len = address_book.len();
+	assert_eq!(len, MAX_RECORDS + 12 - EVICTION_BATCH);
+	// Bans, reliable peers and the new peer are kept
+	assert!(address_book.is_banned(&peer(1)));
+	assert!(address_book.get(&peer(2)).is_some());
+	assert!(address_book.get(&last.unwrap()).is_some());
+	drop(address_book);
+
+	let address_book = AddressBook::open(&directory, Duration::from_secs(60));
+	assert_eq!(address_book.len(), len);
+	let _ = std::fs::remove_dir_all(directory);
+}
@@ -0,0 +1,678 @@
+use rand::{Rng, thread_rng};
+use rand::seq::SliceRandom;
+
+use crate::core::address::P2PKHAddress;
+use crate::core::block::{Block, BlockError, BlockValidity};
+use crate::core::blockchain::BlockChain;
+use crate::consensus::lottery::Lottery;
+use crate::core::genesis::{GenesisSpec, GenesisStakeholder};
+use crate::core::Hashable;
+use crate::core::parameters::Parameters;
+use crate::core::utxo::{Input, Output, UTXO};
+use crate::core::utxo::sighash::SigHashType;
+use crate::core::utxo::transaction::{Transaction, TxError};
+use crate::crypto::public_key::{PUBLIC_KEY_SIZE, SIGNATURE_SIZE};
+use crate::crypto::vrf::{keygen, VrfPk, VrfSk};
+use crate::data_storage::blockchain_storage::reorg_journal::ReorgJournal;
+use crate::tests::temporary_directory;
+
+pub(crate) struct Wallet {
+	pub(crate) address: P2PKHAddress,
+	pub(crate) private_key: Vec<u8>,
+	pub(crate) public_key: Vec<u8>,
+}
+impl Wallet {
+	pub(crate) fn random() -> Self {
+		let (address, private_key, public_key) = P2PKHAddress::random();
+		Wallet { address, private_key, public_key }
+	}
+}
+
+/// A stakeholder that can forge blocks
+pub(crate) struct Stakeholder {
+	pub(crate) wallet: Wallet,
+	pub(crate) vrf_sk: VrfSk,
+}
+impl Stakeholder {
+	pub(crate) fn random() -> Self {
+		Stakeholder { wallet: Wallet::random(), vrf_sk: keygen().0 }
+	}
+	/// The stakeholder that forges the blocks of the tests, it is the only stakeholder of the test genesis
+	pub(crate) fn default_forger() -> Self {
+		let vrf_sk = VrfSk::from_bytes(&[1u8; 32]).expect("Invalid VRF key");
+		Stakeholder { wallet: Wallet::random(), vrf_sk }
+	}
+	pub(crate) fn vrf_public_key(&self) -> [u8; 32] {
+		VrfPk::new(&self.vrf_sk).to_bytes()
+	}
+	pub(crate) fn genesis_entry(&self, stake: u64) -> GenesisStakeholder {
+		GenesisStakeholder { address: self.wallet.address, vrf_public_key: self.vrf_public_key(), stake }
+	}
+}
+
+/// Default parameters in which a stakeholder with most of the stake wins almost every slot
+pub(crate) fn test_parameters() -> Parameters {
+	let mut parameters = Parameters::default();
+	parameters.technical_parameters.active_slot_coefficient = 1.0;
+	parameters
+}
+
+/// A genesis with the test parameters where the default forger has all the stake
+pub(crate) fn test_genesis() -> GenesisSpec {
+	GenesisSpec {
+		stake_distribution: vec![Stakeholder::default_forger().genesis_entry(1)],
+		parameters: test_parameters(),
+		..Default::default()
+	}
+}
+
+/// Opens a blockchain in a new temporary directory. Returns the blockchain and the directory
+pub(crate) fn temporary_blockchain() -> (BlockChain, String) {
+	temporary_blockchain_from(test_genesis())
+}
+
+/// Opens a blockchain with the given parameters in a new temporary directory. Returns the blockchain and the directory
+pub(crate) fn temporary_blockchain_with(parameters: Parameters) -> (BlockChain, String) {
+	temporary_blockchain_from(GenesisSpec { parameters, ..test_genesis() })
+}
+
+/// Opens a blockchain created from the given genesis in a new temporary directory. Returns the blockchain and the directory
+pub(crate) fn temporary_blockchain_from(genesis: GenesisSpec) -> (BlockChain, String) {
+	let directory = temporary_directory("blockchain");
+	(BlockChain::open(&directory, genesis).expect("Unable to open the blockchain"), directory)
+}
+
+/// Creates a block on top of the given parent, forged by the default forger, with a coinbase paying to the given address
+pub(crate) fn new_block(chain: &BlockChain, parent: &Block, transactions: Vec<Transaction>, slot: u64, forger: P2PKHAddress) -> Block {
+	forge_block(chain, parent, transactions, slot, &Stakeholder::default_forger(), forger)
+}
+
+/// Creates a block on top of the given parent with the VRF proof of the given stakeholder, even if it did not win the lottery
+pub(crate) fn forge_block(chain: &BlockChain, parent: &Block, transactions: Vec<Transaction>, slot: u64, stakeholder: &Stakeholder, reward_address: P2PKHAddress) -> Block {
+	let epoch_nonce = chain.get_epoch_nonce(chain.parameters.technical_parameters.get_epoch(slot)).expect("Slot is before the last epoch");
+	forge_block_with_nonce(chain, parent, transactions, slot, stakeholder, reward_address, epoch_nonce)
+}
+
+/// Creates a block like `forge_block`, running the lottery with the given epoch nonce
+pub(crate) fn forge_block_with_nonce(chain: &BlockChain, parent: &Block, transactions: Vec<Transaction>, slot: u64, stakeholder: &Stakeholder, reward_address: P2PKHAddress, epoch_nonce: [u8; 32]) -> Block {
+	// With all the stake the lottery is always won, so the proof can be generated this way
+	let (vrf, proof) = Lottery::run_lottery(slot, 1.0, &epoch_nonce, &stakeholder.vrf_sk, 1, 1).expect("Unable to generate VRF proof");
+	let height = parent.header.height + 1;
+	let coinbase = Transaction::coinbase(height, reward_address, chain.get_block_reward(height, &transactions).expect("The block reward overflows"));
+	let transactions = [vec![coinbase], transactions].concat();
+	Block::new(height, transactions, slot, parent.header.hash, reward_address, stakeholder.vrf_public_key(), vrf, &proof, 0)
+}
+
+/// Creates a transaction that spends the given UTxO, owned by the wallet, splitting it randomly between the recipients
+/// after paying the minimum fee
+pub(crate) fn spend(chain: &BlockChain, utxo: &UTXO, owner: &Wallet, recipients: &[&Wallet]) -> Transaction {
+	let mut rng = thread_rng();
+	let fee = Parameters::default().economic_parameters.minimum_fee(Transaction::estimate_size(1, recipients.len()));
+	let input = Input {
+		prev_txid: utxo.txid,
+		output_index: utxo.output_index,
+		signature: vec![],
+		public_key: owner.public_key.clone(),
+		sighash_type: Default::default(),
+	};
+	let mut remaining = utxo.amount - fee;
+	let mut outputs = vec![];
+	for (i, recipient) in recipients.iter().enumerate() {
+		let amount = if i == recipients.len() - 1 { remaining } else { rng.gen_range(0..=remaining) };
+		remaining -= amount;
+		outputs.push(Output { amount, address: recipient.address });
+	}
+	let mut tx = Transaction::create_transaction(vec![input], outputs, rng.gen());
+	tx.sign_inputs(&owner.private_key, &[*utxo], &chain.get_chain_id()).expect("Unable to sign transaction");
+	tx
+}
+
+/// Gives each wallet some UTxOs, directly in the UTxO set
+pub(crate) fn fund_wallets(chain: &BlockChain, wallets: &[Wallet]) {
+	let mut rng = thread_rng();
+	for wallet in wallets {
+		let txid: [u8; 32] = rng.gen();
+		let utxos = (0..3).map(|output_index| UTXO {
+			txid,
+			output_index,
+			amount: rng.gen_range(1_000_000..10_000_000),
+			recipient_address: wallet.address,
+			coinbase_height: None,
+		}).collect();
+		chain.utxo_set.insert(&txid, utxos);
+	}
+}
+
+/// Returns the UTxOs owned by the wallet
+pub(crate) fn get_utxos_of(chain: &BlockChain, wallet: &Wallet) -> Vec<UTXO> {
+	chain.utxo_set.get_all().into_iter().filter(|utxo| utxo.recipient_address == wallet.address).collect()
+}
+
+/// The state that undoing a block must restore: the UTxO set, the height, the best block and the mempool
+fn state(chain: &BlockChain) -> (Vec<UTXO>, usize, [u8; 32], Vec<[u8; 32]>) {
+	let mut utxos = chain.utxo_set.get_all();
+	utxos.sort_by_key(|utxo| (utxo.txid, utxo.output_index));
+	let mut mempool: Vec<[u8; 32]> = chain.mempool.get_map().iter().map(|tx| tx.id).collect();
+	mempool.sort();
+	(utxos, chain.get_height(), chain.get_last_block().header.hash, mempool)
+}
+
+/// Creates a block with random transactions spending UTxOs of the wallets
+fn random_block(chain: &BlockChain, wallets: &[Wallet]) -> Block {
+	let mut rng = thread_rng();
+	let mut utxos = chain.utxo_set.get_all();
+	utxos.retain(|utxo| utxo.coinbase_height.is_none() && utxo.amount > 100_000); // Coinbase outputs are not mature yet and small outputs can not pay the fee
+	utxos.shuffle(&mut rng);
+	let tx_count = rng.gen_range(1..=5);
+	let transactions = utxos.iter().take(tx_count).map(|utxo| {
+		let owner = wallets.iter().find(|wallet| wallet.address == utxo.recipient_address).expect("Unknown owner");
+		let recipient_count = rng.gen_range(1..=3);
+		let recipients: Vec<&Wallet> = wallets.choose_multiple(&mut rng, recipient_count).collect();
+		spend(chain, utxo, owner, &recipients)
+	}).collect();
+	let last_block = chain.get_last_block();
+	new_block(chain, &last_block, transactions, last_block.header.slot + rng.gen_range(1..10), wallets[0].address)
+}
+
+#[test]
+fn undo_block_round_trip() {
+	const BLOCKS: usize = 10;
+	let (mut chain, directory) = temporary_blockchain();
+	let wallets: Vec<Wallet> = (0..5).map(|_| Wallet::random()).collect();
+	fund_wallets(&chain, &wallets);
+
+	let mut states = vec![];
+	let mut blocks = vec![];
+	for _ in 0..BLOCKS {
+		let block = random_block(&chain, &wallets);
+		for tx in &block.transactions[1..] {
+			assert_eq!(chain.add_transaction_to_mempool(tx), Ok(()));
+		}
+		states.push(state(&chain));
+		assert_eq!(chain.add_block(&block), Ok(BlockValidity::Valid));
+		blocks.push(block);
+	}
+	for block in blocks.iter().rev() {
+		assert!(chain.undo_block(block));
+		assert_eq!(state(&chain), states.pop().unwrap());
+		// The transactions were not in the mempool when the previous state was taken
+		for tx in &block.transactions {
+			chain.mempool.remove(tx);
+		}
+	}
+	assert!(!chain.undo_block(&chain.get_last_block()), "The genesis block must not be undone");
+
+	drop(chain);
+	std::fs::remove_dir_all(directory).ok();
+}
+
+#[test]
+fn undo_block_only_last() {
+	let (mut chain, directory) = temporary_blockchain();
+	let genesis = chain.get_last_block();
+	let first = new_block(&chain, &genesis, vec![], 1, P2PKHAddress::null());
+	let second = new_block(&chain, &first, vec![], 2, P2PKHAddress::null());
+	assert_eq!(chain.add_block(&first), Ok(BlockValidity::Valid));
+	assert_eq!(chain.add_block(&second), Ok(BlockValidity::Valid));
+
+	assert!(!chain.undo_block(&first));
+	assert!(chain.undo_block(&second));
+	assert!(chain.undo_block(&first));
+	assert_eq!(chain.get_height(), 0);
+	assert_eq!(chain.get_last_block(), genesis);
+
+	drop(chain);
+	std::fs::remove_dir_all(directory).ok();
+}
+
+#[test]
+fn longer_fork_replaces_chain() {
+	let (mut chain, directory) = temporary_blockchain();
+	let genesis = chain.get_last_block();
+	let a1 = new_block(&chain, &genesis, vec![], 1, P2PKHAddress::null());
+	let b1 = new_block(&chain, &genesis, vec![], 2, P2PKHAddress::null());
+	let b2 = new_block(&chain, &b1, vec![], 3, P2PKHAddress::null());
+	assert_eq!(chain.add_block(&a1), Ok(BlockValidity::Valid));
+
+	let b1_validity = chain.add_block(&b1);
+	let expected = if b1.header.vrf < a1.header.vrf { BlockValidity::BetterThanLastBlock } else { BlockValidity::Fork };
+	assert_eq!(b1_validity, Ok(expected));
+
+	assert_eq!(chain.add_block(&b2), Ok(if expected == BlockValidity::Fork { BlockValidity::BetterThanLastBlock } else { BlockValidity::Valid }));
+	// Known blocks are not added twice
+	assert_eq!(chain.add_block(&b1), Err(BlockError::AlreadyKnown));
+	assert_eq!(chain.get_height(), 2);
+	assert_eq!(chain.get_last_block(), b2);
+	assert_eq!(chain.get_block_at(1), Some(b1));
+
+	drop(chain);
+	std::fs::remove_dir_all(directory).ok();
+}
+
+#[test]
+fn candidates_are_checked_before_being_kept() {
+	const MAX_CANDIDATE_BLOCKS: usize = 8;
+	let mut parameters = test_parameters();
+	parameters.network_parameters.max_candidate_blocks = MAX_CANDIDATE_BLOCKS;
+	let (mut chain, directory) = temporary_blockchain_with(parameters);
+	let genesis = chain.get_last_block();
+	let a1 = new_block(&chain, &genesis, vec![], 1, P2PKHAddress::null());
+	let a2 = new_block(&chain, &a1, vec![], 2, P2PKHAddress::null());
+	let a3 = new_block(&chain, &a2, vec![], 3, P2PKHAddress::null());
+	for block in [&a1, &a2, &a3] {
+		assert_eq!(chain.add_block(block), Ok(BlockValidity::Valid));
+	}
+
+	// Forks must be forged by a leader of their slot with the epoch nonce of their branch
+	let outsider = forge_block(&chain, &genesis, vec![], 3, &Stakeholder::random(), P2PKHAddress::null());
+	assert_eq!(chain.add_block(&outsider), Err(BlockError::NotLeader));
+	let wrong_nonce = forge_block_with_nonce(&chain, &genesis, vec![], 3, &Stakeholder::default_forger(), P2PKHAddress::null(), [7u8; 32]);
+	assert_eq!(chain.add_block(&wrong_nonce), Err(BlockError::InvalidVRF));
+	assert_eq!(chain.get_candidate_count(), 0);
+
+	// When the candidates are full, only higher candidates replace the lowest ones
+	for slot in 0..MAX_CANDIDATE_BLOCKS as u64 {
+		let fork = new_block(&chain, &genesis, vec![], 10 + slot, P2PKHAddress::null());
+		assert_eq!(chain.add_block(&fork), Ok(BlockValidity::Fork));
+	}
+	let low = new_block(&chain, &genesis, vec![], 5, P2PKHAddress::null());
+	assert_eq!(chain.add_block(&low), Err(BlockError::TooManyCandidates));
+	let high = new_block(&chain, &a1, vec![], 5, P2PKHAddress::null());
+	assert_eq!(chain.add_block(&high), Ok(BlockValidity::Fork));
+	assert_eq!(chain.get_candidate_count(), MAX_CANDIDATE_BLOCKS);
+
+	drop(chain);
+	std::fs::remove_dir_all(directory).ok();
+}
+
+#[test]
+fn pruning_removes_whole_forks() {
+	let mut parameters = test_parameters();
+	parameters.technical_parameters.security_parameter = 3;
+	let (mut chain, directory) = temporary_blockchain_with(parameters);
+	for slot in 1..=3 {
+		let block = new_block(&chain, &chain.get_last_block(), vec![], slot, P2PKHAddress::null());
+		assert_eq!(chain.add_block(&block), Ok(BlockValidity::Valid));
+	}
+	let genesis = chain.get_block_at(0).unwrap();
+	let b1 = new_block(&chain, &genesis, vec![], 10, P2PKHAddress::null());
+	let b2 = new_block(&chain, &b1, vec![], 11, P2PKHAddress::null());
+	assert_eq!(chain.add_block(&b1), Ok(BlockValidity::Fork));
+	assert_eq!(chain.add_block(&b2), Ok(BlockValidity::Fork));
+	assert_eq!(chain.get_candidate_count(), 2);
+
+	// The first block of the fork becomes too deep before the second one, which goes with it
+	for slot in 4..=5 {
+		let block = new_block(&chain, &chain.get_last_block(), vec![], slot, P2PKHAddress::null());
+		assert_eq!(chain.add_block(&block), Ok(BlockValidity::Valid));
+	}
+	assert_eq!(chain.get_candidate_count(), 0);
+
+	drop(chain);
+	std::fs::remove_dir_all(directory).ok();
+}
+
+#[test]
+fn interrupted_reorganization_is_recovered() {
+	let (mut chain, directory) = temporary_blockchain();
+	let wallets: Vec<Wallet> = (0..3).map(|_| Wallet::random()).collect();
+	fund_wallets(&chain, &wallets);
+	let genesis = chain.get_last_block();
+	let first = random_block(&chain, &wallets);
+	assert_eq!(chain.add_block(&first), Ok(BlockValidity::Valid));
+	let second = random_block(&chain, &wallets);
+	assert_eq!(chain.add_block(&second), Ok(BlockValidity::Valid));
+	let expected = state(&chain);
+	let genesis_spec = chain.genesis.clone();
+
+	// The node stops after undoing the last block of a reorganization to the genesis block
+	ReorgJournal { fork_point: genesis.header.hash, original_blocks: vec![first, second.clone()] }.save(&directory).unwrap();
+	assert!(chain.undo_block(&second));
+	drop(chain);
+
+	let chain = BlockChain::open(&directory, genesis_spec).unwrap();
+	assert_eq!(state(&chain), expected);
+	assert!(ReorgJournal::load(&directory).unwrap().is_none());
+
+	drop(chain);
+	std::fs::remove_dir_all(directory).ok();
+}
+
+#[test]
+fn spend_received_coins() {
+	let (mut chain, directory) = temporary_blockchain();
+	let (alice, bob, carol) = (Wallet::random(), Wallet::random(), Wallet::random());
+	fund_wallets(&chain, std::slice::from_ref(&alice));
+	let funding = get_utxos_of(&chain, &alice)[0];
+
+	// Bob receives coins in the first block
+	let to_bob = spend(&chain, &funding, &alice, &[&bob]);
+	let first = new_block(&chain, &chain.get_last_block(), vec![to_bob.clone()], 1, carol.address);
+	assert_eq!(chain.add_block(&first), Ok(BlockValidity::Valid));
+	let received = chain.utxo_set.get_utxo(&to_bob.id, 0).expect("Output was not added to the UTxO set");
+	assert_eq!(received.recipient_address, bob.address);
+	assert_eq!(received.amount, to_bob.output_list[0].amount);
+	assert!(chain.utxo_set.get_utxo(&funding.txid, funding.output_index).is_none());
+
+	// And spends them in the next one
+	let to_carol = spend(&chain, &received, &bob, &[&carol]);
+	let second = new_block(&chain, &first, vec![to_carol.clone()], 2, carol.address);
+	assert_eq!(chain.add_block(&second), Ok(BlockValidity::Valid));
+	assert!(chain.utxo_set.get_utxo(&to_bob.id, 0).is_none());
+	assert_eq!(chain.utxo_set.get_utxo(&to_carol.id, 0).map(|utxo| utxo.recipient_address), Some(carol.address));
+
+	// The same output can not be spent twice
+	let double_spend = spend(&chain, &received, &bob, &[&alice]);
+	assert_eq!(chain.add_transaction_to_mempool(&double_spend), Err(TxError::MissingInput(received.txid, received.output_index)));
+
+	assert!(chain.undo_block(&second));
+	assert!(chain.utxo_set.get_utxo(&to_carol.id, 0).is_none());
+	assert_eq!(chain.utxo_set.get_utxo(&to_bob.id, 0), Some(received));
+	assert!(chain.undo_block(&first));
+	assert_eq!(chain.utxo_set.get_utxo(&funding.txid, funding.output_index), Some(funding));
+	assert!(chain.utxo_set.get(&to_bob.id).is_none());
+
+	drop(chain);
+	std::fs::remove_dir_all(directory).ok();
+}
+
+#[test]
+fn transactions_spend_outputs_of_the_same_block() {
+	let (mut chain, directory) = temporary_blockchain();
+	let (alice, bob, carol) = (Wallet::random(), Wallet::random(), Wallet::random());
+	fund_wallets(&chain, std::slice::from_ref(&alice));
+	let funding = get_utxos_of(&chain, &alice)[0];
+	let to_bob = spend(&chain, &funding, &alice, &[&bob]);
+	let received = to_bob.get_created_utxos(None)[0];
+	let to_carol = spend(&chain, &received, &bob, &[&carol]);
+	let genesis = chain.get_last_block();
+
+	// The output must be created before it is spent
+	let reversed = new_block(&chain, &genesis, vec![to_carol.clone(), to_bob.clone()], 1, bob.address);
+	assert_eq!(chain.add_block(&reversed), Err(BlockError::InvalidTransaction(1, TxError::MissingInput(to_bob.id, 0))));
+
+	let block = new_block(&chain, &genesis, vec![to_bob.clone(), to_carol.clone()], 1, bob.address);
+	assert_eq!(block.transactions[0].output_list[0].amount, chain.parameters.economic_parameters.block_subsidy(1) + to_bob.get_fee(&chain) + received.amount - to_carol.get_output_amount().unwrap());
+	assert_eq!(chain.add_block(&block), Ok(BlockValidity::Valid));
+	assert!(chain.utxo_set.get_utxo(&to_bob.id, 0).is_none());
+	assert_eq!(chain.utxo_set.get_utxo(&to_carol.id, 0).map(|utxo| utxo.recipient_address), Some(carol.address));
+
+	assert!(chain.undo_block(&block));
+	assert_eq!(chain.utxo_set.get_utxo(&funding.txid, funding.output_index), Some(funding));
+	assert!(chain.utxo_set.get(&to_bob.id).is_none());
+	assert!(chain.utxo_set.get(&to_carol.id).is_none());
+
+	drop(chain);
+	std::fs::remove_dir_all(directory).ok();
+}
+
+#[test]
+fn coinbase_reward() {
+	let mut parameters = test_parameters();
+	parameters.economic_parameters.coinbase_maturity = 3;
+	let (mut chain, directory) = temporary_blockchain_with(parameters);
+	let (forger, other) = (Wallet::random(), Wallet::random());
+	let subsidy = parameters.economic_parameters.block_subsidy(1);
+
+	// A coinbase paying more than the reward is not valid
+	let genesis = chain.get_last_block();
+	let mut greedy = new_block(&chain, &genesis, vec![], 1, forger.address);
+	greedy.transactions[0] = Transaction::coinbase(1, forger.address, subsidy + 1);
+	greedy.update_hash();
+	assert_eq!(chain.add_block(&greedy), Err(BlockError::InvalidCoinbase));
+	// A block without coinbase is not valid either
+	greedy.transactions.clear();
+	greedy.update_hash();
+	assert_eq!(chain.add_block(&greedy), Err(BlockError::InvalidCoinbase));
+	// Only the first transaction can have an input that spends nothing
+	let mut second_coinbase = new_block(&chain, &genesis, vec![], 1, forger.address);
+	second_coinbase.transactions.push(Transaction::coinbase(2, other.address, 1));
+	second_coinbase.update_hash();
+	assert_eq!(chain.add_block(&second_coinbase), Err(BlockError::InvalidCoinbase));
+	fund_wallets(&chain, std::slice::from_ref(&other));
+	let mut disguised = spend(&chain, &get_utxos_of(&chain, &other)[0], &other, &[&other]);
+	disguised.input_list.push(Transaction::coinbase(2, other.address, 1).input_list[0].clone());
+	disguised.update_hash();
+	let mut disguised_block = new_block(&chain, &genesis, vec![], 1, forger.address);
+	disguised_block.transactions.push(disguised);
+	disguised_block.update_hash();
+	assert_eq!(chain.add_block(&disguised_block), Err(BlockError::InvalidCoinbase));
+	let mut misplaced = new_block(&chain, &genesis, vec![], 1, forger.address);
+	misplaced.transactions.reverse();
+	misplaced.transactions.insert(0, spend(&chain, &get_utxos_of(&chain, &other)[1], &other, &[&other]));
+	misplaced.update_hash();
+	assert_eq!(chain.add_block(&misplaced), Err(BlockError::InvalidCoinbase));
+
+	let first = new_block(&chain, &genesis, vec![], 1, forger.address);
+	assert_eq!(chain.add_block(&first), Ok(BlockValidity::Valid));
+	let coinbase = &first.transactions[0];
+	let reward = chain.utxo_set.get_utxo(&coinbase.id, 0).expect("The coinbase output was not added");
+	assert_eq!(reward.amount, subsidy);
+	assert_eq!(reward.recipient_address, forger.address);
+	assert_eq!(reward.coinbase_height, Some(1));
+
+	// The reward can not be spent until it is mature
+	let spend_reward = spend(&chain, &reward, &forger, &[&other]);
+	for slot in 2..4 {
+		assert_eq!(chain.add_transaction_to_mempool(&spend_reward), Err(TxError::ImmatureCoinbase(reward.txid, reward.output_index)));
+		let block = new_block(&chain, &chain.get_last_block(), vec![], slot, other.address);
+		assert_eq!(chain.add_block(&block), Ok(BlockValidity::Valid));
+	}
+	assert_eq!(chain.add_transaction_to_mempool(&spend_reward), Ok(()));
+
+	// Undoing the block removes the reward
+	for _ in 0..3 {
+		assert!(chain.undo_block(&chain.get_last_block()));
+	}
+	assert!(chain.utxo_set.get(&coinbase.id).is_none());
+	assert!(chain.mempool.get_map().iter().all(|tx| !tx.is_coinbase()));
+
+	drop(chain);
+	std::fs::remove_dir_all(directory).ok();
+}
+
+#[test]
+fn transaction_fees() {
+	let (mut chain, directory) = temporary_blockchain();
+	let (alice, bob, forger) = (Wallet::random(), Wallet::random(), Wallet::random());
+	fund_wallets(&chain, std::slice::from_ref(&alice));
+	let mut utxos = get_utxos_of(&chain, &alice);
+	let (first, second) = (utxos.remove(0), utxos.remove(0));
+
+	// Inputs must be able to pay for the outputs
+	let mut overspend = spend(&chain, &first, &alice, &[&bob]);
+	overspend.output_list[0].amount = first.amount + 1;
+	overspend.update_hash();
+	overspend.sign_inputs(&alice.private_key, &[first], &chain.get_chain_id()).unwrap();
+	assert_eq!(chain.add_transaction_to_mempool(&overspend), Err(TxError::ValueMismatch { input_amount: first.amount, output_amount: first.amount + 1 }));
+
+	// Amounts that do not fit in 64 bits are rejected instead of wrapping around
+	let mut overflowing = spend(&chain, &first, &alice, &[&bob, &bob]);
+	for output in &mut overflowing.output_list {
+		output.amount = u64::MAX / 2 + 1;
+	}
+	overflowing.update_hash();
+	overflowing.sign_inputs(&alice.private_key, &[first], &chain.get_chain_id()).unwrap();
+	assert_eq!(overflowing.get_output_amount(), Err(TxError::AmountOverflow));
+	assert_eq!(chain.add_transaction_to_mempool(&overflowing), Err(TxError::AmountOverflow));
+
+	// Paying no fee is not enough
+	let mut no_fee = spend(&chain, &first, &alice, &[&bob]);
+	no_fee.output_list[0].amount = first.amount;
+	no_fee.update_hash();
+	no_fee.sign_inputs(&alice.private_key, &[first], &chain.get_chain_id()).unwrap();
+	assert_eq!(no_fee.get_fee(&chain), 0);
+	assert!(matches!(chain.add_transaction_to_mempool(&no_fee), Err(TxError::InsufficientFee { fee: 0, .. })));
+
+	let paying = spend(&chain, &first, &alice, &[&bob]);
+	assert!(paying.size() <= Transaction::estimate_size(1, 1));
+	// The estimate is the size of the biggest transaction with those inputs and outputs
+	for (input_count, output_count) in [(0, 0), (1, 1), (3, 2), (127, 127), (300, 1)] {
+		let input = Input {
+			prev_txid: [u8::MAX; 32],
+			output_index: usize::MAX,
+			signature: vec![0u8; SIGNATURE_SIZE],
+			public_key: vec![0u8; PUBLIC_KEY_SIZE],
+			sighash_type: SigHashType::SingleAnyoneCanPay,
+		};
+		let output = Output { amount: u64::MAX, address: P2PKHAddress::null() };
+		let biggest = Transaction { id: [0u8; 32], extra_entropy: u16::MAX, input_list: vec![input; input_count], output_list: vec![output; output_count] };
+		assert_eq!(Transaction::estimate_size(input_count, output_count), biggest.size());
+	}
+	assert_eq!(paying.get_fee(&chain), chain.estimate_fee(1, 1));
+	assert_eq!(chain.add_transaction_to_mempool(&paying), Ok(()));
+	assert_eq!(chain.add_transaction_to_mempool(&paying), Err(TxError::AlreadyKnown));
+
+	let generous = spend(&chain, &second, &alice, &[&bob, &alice]);
+	let fees = paying.get_fee(&chain) + generous.get_fee(&chain);
+
+	// The forger gets the fees
+	let block = new_block(&chain, &chain.get_last_block(), vec![paying, generous], 1, forger.address);
+	let coinbase = block.transactions[0].clone();
+	assert_eq!(coinbase.output_list[0].amount, chain.parameters.economic_parameters.block_subsidy(1) + fees);
+	assert_eq!(chain.add_block(&block), Ok(BlockValidity::Valid));
+
+	drop(chain);
+	std::fs::remove_dir_all(directory).ok();
+}
+
+#[test]
+fn forged_blocks_fit_the_coinbase_in_the_body_size() {
+	let (mut chain, directory) = temporary_blockchain();
+	let wallets: Vec<Wallet> = (0..4).map(|_| Wallet::random()).collect();
+	fund_wallets(&chain, &wallets);
+	let mut mempool_size = 0;
+	for wallet in &wallets {
+		for utxo in get_utxos_of(&chain, wallet) {
+			let tx = spend(&chain, &utxo, wallet, &[wallet]);
+			mempool_size += tx.size();
+			assert_eq!(chain.add_transaction_to_mempool(&tx), Ok(()));
+		}
+	}
+	// All the transactions fit in the body, but not together with the coinbase
+	let max_body_size = mempool_size + 1;
+	chain.parameters.network_parameters.max_block_body_size = max_body_size;
+
+	let forger = Wallet::random();
+	let transactions = chain.get_block_transactions(1, forger.address).unwrap();
+	assert!(transactions.len() > 1);
+	assert!(transactions.len() < wallets.len() * 3 + 1);
+	let block = new_block(&chain, &chain.get_last_block(), transactions[1..].to_vec(), 1, forger.address);
+	assert_eq!(block.transactions, transactions);
+	assert!(block.header.body_size <= max_body_size as u64);
+	assert_eq!(chain.add_block(&block), Ok(BlockValidity::Valid));
+
+	drop(chain);
+	std::fs::remove_dir_all(directory).ok();
+}
+
+#[test]
+fn rejection_reasons() {
+	let (mut chain, directory) = temporary_blockchain();
+	let (alice, bob) = (Wallet::random(), Wallet::random());
+	fund_wallets(&chain, std::slice::from_ref(&alice));
+	let utxo = get_utxos_of(&chain, &alice)[0];
+
+	// Only the owner can spend an output
+	let stolen = spend(&chain, &utxo, &bob, &[&bob]);
+	assert_eq!(chain.add_transaction_to_mempool(&stolen), Err(TxError::WrongOwner(utxo.txid, utxo.output_index)));
+
+	// Signatures from other keys are not valid
+	let mut forged = spend(&chain, &utxo, &alice, &[&bob]);
+	forged.sign_inputs(&bob.private_key, &[utxo], &chain.get_chain_id()).unwrap();
+	assert_eq!(chain.add_transaction_to_mempool(&forged), Err(TxError::InvalidSignature(0)));
+
+	// The same output can not be spent twice by the same transaction
+	let mut duplicated = spend(&chain, &utxo, &alice, &[&bob]);
+	duplicated.input_list.push(duplicated.input_list[0].clone());
+	duplicated.update_hash();
+	assert_eq!(chain.add_transaction_to_mempool(&duplicated), Err(TxError::DuplicateInput));
+
+	// Coinbase transactions are not relayed
+	let coinbase = Transaction::coinbase(1, bob.address, 1);
+	assert_eq!(chain.add_transaction_to_mempool(&coinbase), Err(TxError::UnexpectedCoinbase));
+
+	// Blocks report which transaction is not valid
+	let valid = spend(&chain, &utxo, &alice, &[&bob]);
+	let other_utxo = get_utxos_of(&chain, &alice)[1];
+	let other_stolen = spend(&chain, &other_utxo, &bob, &[&bob]);
+	let block = new_block(&chain, &chain.get_last_block(), vec![valid, other_stolen], 1, bob.address);
+	assert_eq!(chain.add_block(&block), Err(BlockError::InvalidTransaction(2, TxError::WrongOwner(other_utxo.txid, other_utxo.output_index))));
+
+	// Blocks whose parent is unknown are orphans
+	let orphan = new_block(&chain, &block, vec![], 2, bob.address);
+	assert_eq!(chain.add_block(&orphan), Err(BlockError::Orphan));
+
+	drop(chain);
+	std::fs::remove_dir_all(directory).ok();
+}
+
+#[test]
+fn headers_commit_to_the_body() {
+	let (mut chain, directory) = temporary_blockchain();
+	let (alice, bob) = (Wallet::random(), Wallet::random());
+	fund_wallets(&chain, std::slice::from_ref(&alice));
+	let utxo = get_utxos_of(&chain, &alice)[0];
+	let block = new_block(&chain, &chain.get_last_block(), vec![spend(&chain, &utxo, &alice, &[&bob])], 1, bob.address);
+
+	// Headers can be verified without the body
+	assert_eq!(block.header.verify(), Ok(()));
+	assert!(chain.is_header_chain_valid(std::slice::from_ref(&block.header)));
+	let mut tampered = block.header.clone();
+	tampered.slot += 1;
+	assert_eq!(tampered.verify(), Err(BlockError::InvalidHash));
+	assert_eq!(chain.check_header_chain(&[tampered]), Err(BlockError::InvalidHash));
+	let mut future_version = block.header.clone();
+	future_version.version += 1;
+	future_version.hash = future_version.calculate_hash();
+	assert_eq!(future_version.verify(), Err(BlockError::UnsupportedVersion(future_version.version)));
+
+	// But the body must match the header
+	let mut other_body = block.clone();
+	other_body.transactions.truncate(1);
+	assert_eq!(chain.add_block(&other_body), Err(BlockError::InvalidMerkleRoot));
+	let mut oversized = block.clone();
+	oversized.header.body_size = chain.parameters.network_parameters.max_block_body_size as u64 + 1;
+	oversized.header.hash = oversized.header.calculate_hash();
+	assert_eq!(chain.check_header_chain(std::slice::from_ref(&oversized.header)), Err(BlockError::TooLarge(oversized.header.body_size as usize)));
+	assert_eq!(chain.add_block(&oversized), Err(BlockError::InvalidBodySize));
+	assert_eq!(chain.add_block(&block), Ok(BlockValidity::Valid));
+
+	drop(chain);
+	std::fs::remove_dir_all(directory).ok();
+}
+
+#[test]
+fn transactions_are_found_by_id() {
+	let (mut chain, directory) = temporary_blockchain();
+	let (alice, bob) = (Wallet::random(), Wallet::random());
+	fund_wallets(&chain, std::slice::from_ref(&alice));
+	let utxo = get_utxos_of(&chain, &alice)[0];
+	let tx = spend(&chain, &utxo, &alice, &[&bob]);
+	assert_eq!(chain.get_transaction(&tx.id), None);
+
+	assert_eq!(chain.add_transaction_to_mempool(&tx), Ok(()));
+	assert_eq!(chain.get_transaction(&tx.id), Some(tx.clone()));
+	assert_eq!(chain.get_transaction_block(&tx.id), None);
+
+	let block = new_block(&chain, &chain.get_last_block(), vec![tx.clone()], 1, bob.address);
+	assert_eq!(chain.add_block(&block), Ok(BlockValidity::Valid));
+	assert_eq!(chain.get_transaction_block(&tx.id), Some(block.header.hash));
+	assert_eq!(chain.get_transaction(&tx.id), Some(tx.clone()));
+	assert_eq!(chain.get_transaction_block(&block.transactions[0].id), Some(block.header.hash));
+
+	// Chains without index are indexed when opened
+	let genesis = chain.genesis.clone();
+	drop(chain);
+	std::fs::remove_dir_all(format!("{}/blockchain/tx-index-db", directory)).unwrap();
+	let mut chain = BlockChain::open(&directory, genesis).unwrap();
+	assert_eq!(chain.get_transaction_block(&tx.id), Some(block.header.hash));
+
+	// Undone blocks are not indexed anymore, their transactions are back in the mempool
+	assert!(chain.undo_block(&block));
+	assert_eq!(chain.get_transaction_block(&tx.id), None);
+	assert_eq!(chain.get_transaction(&tx.id), Some(tx));
+
+	drop(chain);
+	std::fs::remove_dir_all(directory).ok();
+}
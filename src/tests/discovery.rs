@@ -0,0 +1,156 @@
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use crate::data_storage::node_config_storage::node_config::NodeConfig;
+use crate::data_storage::node_config_storage::url_serialize::PeerUrl;
+use crate::network::node::{Node, RunningTask};
+use crate::tests::blockchain::test_genesis;
+use crate::tests::gossip::{temporary_node, url_of};
+
+/// Starts the given amount of nodes of the genesis of the given node. Returns the nodes and their directories
+fn start_peers_of(node: &Node, count: usize) -> Vec<(Node, String)> {
+	let genesis = node.blockchain.try_read().unwrap().genesis.clone();
+	(0..count).map(|_| {
+		let (mut peer, directory) = temporary_node(genesis.clone(), NodeConfig::default());
+		peer.start_node();
+		(peer, directory)
+	}).collect()
+}
+
+async fn stop(nodes: Vec<(Node, String)>) {
+	for (mut node, directory) in nodes {
+		node.shutdown().await;
+		drop(node);
+		std::fs::remove_dir_all(directory).ok();
+	}
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn discovery_fills_the_peer_list_up_to_the_limit() {
+	let (mut node, directory) = temporary_node(test_genesis(), NodeConfig { max_peers: 2, ..NodeConfig::default() });
+	let others = start_peers_of(&node, 3);
+	node.config.trusted_peers = others.iter().map(|(peer, _)| url_of(peer)).collect();
+
+	node.discover_peers().await;
+	let peers = node.peer_urls().await;
+	assert_eq!(peers.len(), 2);
+	// The discovered nodes paired up with us too
+	for (other, _) in &others {
+		assert_eq!(peers.contains(&url_of(other)), other.peers.read().await.contains_key(&node.node_id()));
+	}
+	// A full peer list is left as it is
+	node.discover_peers().await;
+	assert_eq!(node.peer_urls().await, peers);
+
+	stop(others).await;
+	drop(node);
+	std::fs::remove_dir_all(directory).ok();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn cycling_keeps_trusted_peers_and_the_peer_limit() {
+	let (mut node, directory) = temporary_node(test_genesis(), NodeConfig { max_peers: 2, peer_cycle_count: 4, ..NodeConfig::default() });
+	let trusted = start_peers_of(&node, 2);
+	let others = start_peers_of(&node, 2);
+	let trusted_urls: HashSet<PeerUrl> = trusted.iter().map(|(peer, _)| url_of(peer)).collect();
+	node.config.trusted_peers = trusted_urls.clone();
+	node.discover_peers().await;
+	assert_eq!(node.peer_urls().await, trusted_urls);
+
+	for (other, _) in &others {
+		node.address_book.add(&url_of(other));
+	}
+	node.cycle_peers().await;
+	// Trusted peers are never replaced, so the new peers do not fit and are unpaired
+	assert_eq!(node.peer_urls().await, trusted_urls);
+	for (other, _) in &others {
+		assert!(!other.peers.read().await.contains_key(&node.node_id()));
+	}
+
+	stop(trusted).await;
+	stop(others).await;
+	drop(node);
+	std::fs::remove_dir_all(directory).ok();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn addresses_of_live_peers_can_not_be_taken_over() {
+	let (mut node, directory) = temporary_node(test_genesis(), NodeConfig::default());
+	node.start_node();
+	let mut owner = start_peers_of(&node, 1);
+	let (mut impostor, impostor_directory) = temporary_node(node.blockchain.read().await.genesis.clone(), NodeConfig::default());
+	owner[0].0.config.trusted_peers = HashSet::from([url_of(&node)]);
+	impostor.config.trusted_peers = HashSet::from([url_of(&node)]);
+	impostor.config.listing_port = owner[0].0.config.listing_port;
+	let owner_id = owner[0].0.node_id();
+	owner[0].0.discover_peers().await;
+	assert!(node.peers.read().await.contains_key(&owner_id));
+
+	// The node that has the address is still alive, so it keeps it
+	impostor.discover_peers().await;
+	assert!(impostor.peers.read().await.is_empty());
+	tokio::time::sleep(Duration::from_secs(1)).await; // The owner is checked after answering
+	assert!(node.peers.read().await.contains_key(&owner_id));
+	assert!(!node.peers.read().await.contains_key(&impostor.node_id()));
+
+	// Once it stops answering, it is removed and the address can be used by another node
+	stop(owner).await;
+	impostor.discover_peers().await;
+	assert!(impostor.peers.read().await.is_empty());
+	for _ in 0..20 {
+		if !node.peers.read().await.contains_key(&owner_id) {
+			break;
+		}
+		tokio::time::sleep(Duration::from_millis(100)).await;
+	}
+	impostor.discover_peers().await;
+	assert!(impostor.peers.read().await.contains_key(&node.node_id()));
+	let peers = node.peers.read().await;
+	assert!(!peers.contains_key(&owner_id));
+	assert_eq!(peers.get(&impostor.node_id()).map(|peer| &peer.url), Some(&url_of(&impostor)));
+	drop(peers);
+
+	node.shutdown().await;
+	drop(node);
+	drop(impostor);
+	std::fs::remove_dir_all(directory).ok();
+	std::fs::remove_dir_all(impostor_directory).ok();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn peers_of_the_same_network_are_limited() {
+	let (mut node, directory) = temporary_node(test_genesis(), NodeConfig { max_peers_per_subnet: 2, ..NodeConfig::default() });
+	node.start_node();
+	let mut others = start_peers_of(&node, 3);
+	for (other, _) in others.iter_mut() {
+		other.config.trusted_peers = HashSet::from([url_of(&node)]);
+		other.discover_peers().await;
+	}
+	// All the nodes listen on the same IP, so only the first ones can pair up with us
+	let paired: Vec<bool> = others.iter().map(|(other, _)| other.peers.try_read().unwrap().contains_key(&node.node_id())).collect();
+	assert_eq!(paired, [true, true, false]);
+	assert_eq!(node.peers.read().await.len(), 2);
+
+	stop(others).await;
+	node.shutdown().await;
+	drop(node);
+	std::fs::remove_dir_all(directory).ok();
+}
+
+#[test]
+fn running_tasks_are_finished_even_if_they_panic() {
+	let flag = AtomicBool::new(false);
+	let task = RunningTask::start(&flag);
+	assert!(task.is_some());
+	assert!(RunningTask::start(&flag).is_none());
+	drop(task);
+	assert!(!flag.load(Ordering::Relaxed));
+
+	let panicked = std::panic::catch_unwind(|| {
+		let _task = RunningTask::start(&flag);
+		panic!("The task failed");
+	});
+	assert!(panicked.is_err());
+	assert!(!flag.load(Ordering::Relaxed));
+}
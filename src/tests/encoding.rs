@@ -0,0 +1,106 @@
+use crate::core::address::P2PKHAddress;
+use crate::core::block::BlockHeader;
+use crate::core::encoding::{BLOCK_HASH_DOMAIN, CanonicalEncode, hash_with_domain, Witness, WITNESS_DOMAIN};
+use crate::core::Hashable;
+use crate::core::utxo::{Input, Output, UTXO};
+use crate::core::utxo::sighash::{signature_hash, SigHashType};
+use crate::core::utxo::transaction::Transaction;
+
+/// A transaction with one input and two outputs, with every field set to a recognizable value
+fn golden_transaction() -> Transaction {
+	let input = Input {
+		prev_txid: [0x11; 32],
+		output_index: 2,
+		signature: vec![0x99; 64],
+		public_key: vec![0x22; 33],
+		sighash_type: SigHashType::All,
+	};
+	let outputs = vec![
+		Output { amount: 1_000, address: P2PKHAddress { address: [0x33; 16] } },
+		Output { amount: 250_000, address: P2PKHAddress { address: [0x44; 16] } },
+	];
+	Transaction::create_transaction(vec![input], outputs, 7)
+}
+
+fn golden_header() -> BlockHeader {
+	BlockHeader {
+		hash: [0u8; 32],
+		version: 1,
+		protocol_version: 3,
+		height: 5,
+		previous_hash: [0x55; 32],
+		slot: 1_234,
+		merkle_root: [0x66; 32],
+		witness_root: [0x69; 32],
+		body_size: 4_096,
+		vrf: [0x77; 32],
+		vrf_proof: [0x88; 96],
+		forger_vrf_public_key: [0xaa; 32],
+		forger_address: P2PKHAddress { address: [0xbb; 16] },
+	}
+}
+
+#[test]
+fn golden_transaction_id() {
+	let mut tx = golden_transaction();
+	assert_eq!(hex::encode(tx.to_canonical_bytes()), concat!(
+		"0007", // extra entropy
+		"0000000000000001", // 1 input
+		"1111111111111111111111111111111111111111111111111111111111111111", "0000000000000002", // previous output
+		"0000000000000021", "222222222222222222222222222222222222222222222222222222222222222222", // public key
+		"0000000000000002", // 2 outputs
+		"00000000000003e8", "33333333333333333333333333333333",
+		"000000000003d090", "44444444444444444444444444444444",
+	));
+	assert_eq!(hex::encode(tx.id), "4c1b266293f779a252c3e5749a453e316139c17a855fc96c4047b3297892003f");
+
+	assert_eq!(hex::encode(Witness(&tx).to_canonical_bytes()), hex::encode([
+		tx.to_canonical_bytes(),
+		hex::decode(concat!("0000000000000040", "99999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999")).unwrap(),
+		vec![0x01], // signature hash type
+	].concat()));
+	let witness_hash = tx.calculate_witness_hash();
+	assert_eq!(witness_hash, hash_with_domain(WITNESS_DOMAIN, &Witness(&tx)));
+
+	// Signatures are not part of the id, only of the witness hash
+	tx.input_list[0].signature = vec![];
+	tx.input_list[0].sighash_type = SigHashType::SingleAnyoneCanPay;
+	assert_eq!(tx.calculate_hash(), tx.id);
+	assert_ne!(tx.calculate_witness_hash(), witness_hash);
+}
+
+#[test]
+fn golden_block_hash() {
+	let header = golden_header();
+	assert_eq!(hex::encode(header.to_canonical_bytes()), concat!(
+		"0001", // header version
+		"00000003", // protocol version
+		"5555555555555555555555555555555555555555555555555555555555555555", // previous hash
+		"6666666666666666666666666666666666666666666666666666666666666666", // merkle root
+		"6969696969696969696969696969696969696969696969696969696969696969", // witness root
+		"0000000000001000", // body size
+		"00000000000004d2", // slot
+		"0000000000000005", // height
+		"7777777777777777777777777777777777777777777777777777777777777777", // VRF output
+		"888888888888888888888888888888888888888888888888888888888888888888888888888888888888888888888888",
+		"888888888888888888888888888888888888888888888888888888888888888888888888888888888888888888888888", // VRF proof
+		"bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb", // forger address
+		"aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa", // forger VRF public key
+	));
+	assert_eq!(hex::encode(hash_with_domain(BLOCK_HASH_DOMAIN, &header)), "a8b726df989f7b994aa1f69bac690cba7aafa4ba64a6b34e7d2017464f60fdb7");
+	assert_eq!(header.calculate_hash(), hash_with_domain(BLOCK_HASH_DOMAIN, &header));
+}
+
+#[test]
+fn golden_signature_hash() {
+	let tx = golden_transaction();
+	let spent_utxo = UTXO {
+		txid: [0x11; 32],
+		output_index: 2,
+		amount: 300_000,
+		recipient_address: P2PKHAddress { address: [0xcc; 16] },
+		coinbase_height: None,
+	};
+	let sighash = signature_hash(&tx, 0, &[spent_utxo], SigHashType::All, &[0xdd; 32]).unwrap();
+	assert_eq!(hex::encode(sighash), "00c345d66ddac923351a95f44d4c49223f0b00f7f6abbd3666db4129b1cdc5a7");
+}
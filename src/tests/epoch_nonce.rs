@@ -0,0 +1,63 @@
+use crate::core::block::{BlockError, BlockValidity};
+use crate::core::genesis::GenesisSpec;
+use crate::tests::blockchain::{forge_block_with_nonce, new_block, Stakeholder, temporary_blockchain_from, temporary_blockchain_with, test_parameters, Wallet};
+
+const EPOCH_DURATION: u32 = 10;
+
+#[test]
+fn epoch_nonce_evolves_with_blocks() {
+	let mut parameters = test_parameters();
+	parameters.technical_parameters.epoch_duration = EPOCH_DURATION;
+	let (mut chain, directory) = temporary_blockchain_with(parameters);
+	let forger = Wallet::random().address;
+	let genesis_nonce = chain.get_epoch_nonce(0).unwrap();
+	assert_ne!(chain.get_epoch_nonce(1), chain.get_epoch_nonce(2)); // Epochs without blocks have different nonces too
+
+	let first = new_block(&chain, &chain.get_last_block(), vec![], 1, forger);
+	assert_eq!(chain.add_block(&first), Ok(BlockValidity::Valid));
+	let early_nonce = chain.get_epoch_nonce(1).unwrap();
+	let second = new_block(&chain, &first, vec![], 2, forger);
+	assert_eq!(chain.add_block(&second), Ok(BlockValidity::Valid));
+
+	// The nonce of an epoch does not change during the epoch, but every block changes the nonce of the next one
+	assert_eq!(chain.get_epoch_nonce(0), Some(genesis_nonce));
+	let next_nonce = chain.get_epoch_nonce(1).unwrap();
+	assert_ne!(next_nonce, early_nonce);
+	assert_ne!(next_nonce, genesis_nonce);
+
+	// Blocks must use the nonce of their epoch
+	let next_epoch = EPOCH_DURATION as u64;
+	let stale = forge_block_with_nonce(&chain, &second, vec![], next_epoch, &Stakeholder::default_forger(), forger, early_nonce);
+	assert_eq!(chain.add_block(&stale), Err(BlockError::InvalidVRF));
+	let third = forge_block_with_nonce(&chain, &second, vec![], next_epoch, &Stakeholder::default_forger(), forger, next_nonce);
+	assert_eq!(chain.add_block(&third), Ok(BlockValidity::Valid));
+	assert_eq!(chain.get_epoch_nonce(1), Some(next_nonce));
+	assert_eq!(chain.get_epoch_nonce(0), None);
+	assert!(chain.is_header_chain_valid(&[new_block(&chain, &third, vec![], next_epoch + 1, forger).header]));
+	assert!(!chain.is_header_chain_valid(&[forge_block_with_nonce(&chain, &third, vec![], next_epoch + 1, &Stakeholder::default_forger(), forger, early_nonce).header]));
+	// Headers of a fork use the nonces of their own branch
+	assert!(chain.is_header_chain_valid(&[forge_block_with_nonce(&chain, &first, vec![], next_epoch + 1, &Stakeholder::default_forger(), forger, early_nonce).header]));
+	assert!(!chain.is_header_chain_valid(&[forge_block_with_nonce(&chain, &first, vec![], next_epoch + 1, &Stakeholder::default_forger(), forger, next_nonce).header]));
+
+	// Rolling back restores the nonces
+	assert!(chain.undo_block(&third));
+	assert_eq!(chain.get_epoch_nonce(1), Some(next_nonce));
+	assert_eq!(chain.get_epoch_nonce(0), Some(genesis_nonce));
+	assert!(chain.undo_block(&second));
+	assert_eq!(chain.get_epoch_nonce(1), Some(early_nonce));
+
+	drop(chain);
+	std::fs::remove_dir_all(directory).ok();
+}
+
+#[test]
+fn genesis_seed_changes_nonce() {
+	let other = GenesisSpec { epoch_nonce_seed: [1u8; 32], ..GenesisSpec::default() };
+	let (chain, directory) = temporary_blockchain_with(test_parameters());
+	let (other_chain, other_directory) = temporary_blockchain_from(other);
+	assert_ne!(chain.get_epoch_nonce(0), other_chain.get_epoch_nonce(0));
+
+	drop((chain, other_chain));
+	std::fs::remove_dir_all(directory).ok();
+	std::fs::remove_dir_all(other_directory).ok();
+}
@@ -0,0 +1,111 @@
+use std::time::Duration;
+
+use crate::core::block::BlockValidity;
+use crate::core::blockchain::BlockChain;
+use crate::core::genesis::{GenesisAllocation, GenesisSpec};
+use crate::crypto::vrf::keygen;
+use crate::network::timing;
+use crate::tests::blockchain::{new_block, spend, Stakeholder, temporary_blockchain, temporary_blockchain_from, test_genesis, test_parameters, Wallet};
+
+#[test]
+fn genesis_allocations_are_spendable() {
+	let (alice, bob, forger) = (Wallet::random(), Wallet::random(), Stakeholder::default_forger());
+	let genesis = GenesisSpec {
+		allocations: vec![GenesisAllocation { address: alice.address, amount: 5_000_000 }],
+		stake_distribution: vec![forger.genesis_entry(1_000_000)],
+		parameters: test_parameters(),
+		..Default::default()
+	};
+	let (mut chain, directory) = temporary_blockchain_from(genesis.clone());
+	assert_eq!(chain.get_chain_id(), genesis.get_block().header.hash);
+
+	let utxos = genesis.get_utxos();
+	assert_eq!(utxos.len(), 2);
+	assert_eq!(chain.utxo_set.get_utxo(&utxos[0].txid, 0), Some(utxos[0]));
+	assert_eq!(chain.utxo_set.get_utxo(&utxos[1].txid, 1).map(|utxo| (utxo.recipient_address, utxo.amount)), Some((forger.wallet.address, 1_000_000)));
+
+	let to_bob = spend(&chain, &utxos[0], &alice, &[&bob]);
+	let block = new_block(&chain, &chain.get_last_block(), vec![to_bob.clone()], 1, forger.wallet.address);
+	assert_eq!(chain.add_block(&block), Ok(BlockValidity::Valid));
+	assert!(chain.utxo_set.get_utxo(&utxos[0].txid, 0).is_none());
+	assert!(chain.utxo_set.get_utxo(&to_bob.id, 0).is_some());
+
+	// Undoing every block goes back to the genesis distribution
+	assert!(chain.undo_block(&block));
+	assert_eq!(chain.utxo_set.get_all().len(), 2);
+
+	drop(chain);
+	std::fs::remove_dir_all(directory).ok();
+}
+
+#[test]
+fn different_genesis_is_different_chain() {
+	let (chain, directory) = temporary_blockchain();
+	let funded = GenesisSpec {
+		allocations: vec![GenesisAllocation { address: Wallet::random().address, amount: 1 }],
+		..test_genesis()
+	};
+	let reseeded = GenesisSpec { epoch_nonce_seed: [1u8; 32], ..test_genesis() };
+	assert_ne!(chain.get_chain_id(), GenesisSpec::default().get_block().header.hash);
+	assert_ne!(chain.get_chain_id(), funded.get_block().header.hash);
+	assert_ne!(chain.get_chain_id(), reseeded.get_block().header.hash);
+
+	drop(chain);
+	std::fs::remove_dir_all(directory).ok();
+}
+
+#[test]
+fn stored_chain_must_match_genesis() {
+	let (mut chain, directory) = temporary_blockchain();
+	let genesis = chain.genesis.clone();
+	let reseeded = GenesisSpec { epoch_nonce_seed: [1u8; 32], ..genesis.clone() };
+	let block = new_block(&chain, &chain.get_last_block(), vec![], 1, Wallet::random().address);
+	assert_eq!(chain.add_block(&block), Ok(BlockValidity::Valid));
+	// Every output is spent after the genesis block
+	for utxo in chain.utxo_set.get_all() {
+		chain.utxo_set.remove(&utxo.txid);
+	}
+	drop(chain);
+
+	// Opening the chain with another genesis is an error instead of a panic
+	assert!(BlockChain::open(&directory, reseeded).is_err());
+	// An empty UTxO set is not seeded again once the chain has blocks
+	let chain = BlockChain::open(&directory, genesis).unwrap();
+	assert_eq!(chain.get_height(), 1);
+	assert!(chain.utxo_set.get_all().is_empty());
+
+	drop(chain);
+	std::fs::remove_dir_all(directory).ok();
+}
+
+#[test]
+fn slots_start_at_the_start_time() {
+	let start_time = 1_700_000_000;
+	assert_eq!(timing::slot_at(Duration::from_secs(start_time - 1), 1000, start_time), None);
+	assert_eq!(timing::slot_at(Duration::from_secs(start_time), 1000, start_time), Some(0));
+	assert_eq!(timing::slot_at(Duration::from_millis(start_time * 1000 + 2500), 1000, start_time), Some(2));
+}
+
+#[test]
+fn genesis_spec_from_json() {
+	let alice = Wallet::random();
+	let (_, vrf_pk) = keygen();
+	let json = format!(r#"{{
+		"start_time": 1700000000,
+		"epoch_nonce_seed": "{}",
+		"allocations": [{{ "address": "{}", "amount": 1000 }}],
+		"stake_distribution": [{{ "address": "{}", "vrf_public_key": "{}", "stake": 500 }}],
+		"parameters": {{ "economic_parameters": {{ "coinbase_maturity": 10 }} }}
+	}}"#, hex::encode([7u8; 32]), alice.address, alice.address, hex::encode(vrf_pk.to_bytes()));
+	let genesis: GenesisSpec = serde_json::from_str(&json).expect("Unable to parse genesis");
+	assert_eq!(genesis.start_time, 1700000000);
+	assert_eq!(genesis.epoch_nonce_seed, [7u8; 32]);
+	assert_eq!(genesis.allocations[0].address, alice.address);
+	assert_eq!(genesis.stake_distribution[0].vrf_public_key, vrf_pk.to_bytes());
+	assert_eq!(genesis.parameters.economic_parameters.coinbase_maturity, 10);
+	// Parameters that are not given keep their default value
+	assert_eq!(genesis.parameters.economic_parameters.fee_per_tx_byte, GenesisSpec::default().parameters.economic_parameters.fee_per_tx_byte);
+
+	let round_trip: GenesisSpec = serde_json::from_str(&serde_json::to_string(&genesis).unwrap()).unwrap();
+	assert_eq!(round_trip.get_block(), genesis.get_block());
+}
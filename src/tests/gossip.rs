@@ -0,0 +1,98 @@
+use std::collections::HashSet;
+
+use local_ip_address::local_ip;
+use rand::{Rng, thread_rng};
+use reqwest::Client;
+
+use crate::core::genesis::GenesisSpec;
+use crate::data_storage::node_config_storage::node_config::NodeConfig;
+use crate::data_storage::node_config_storage::url_serialize::PeerUrl;
+use crate::network::gossip::{choose_fanout, SeenCache};
+use crate::network::models::{Inv, InvDataType};
+use crate::network::node::{Node, Peer};
+use crate::network::protocol::{Features, PeerProtocol, PROTOCOL_VERSION};
+use crate::network::sender::Sender;
+use crate::tests::blockchain::{new_block, test_genesis, Wallet};
+use crate::tests::temporary_directory;
+
+#[test]
+fn seen_cache_forgets_the_oldest_hashes() {
+	let mut seen = SeenCache::new(3);
+	for i in 0..3u8 {
+		assert!(seen.insert([i; 32]));
+	}
+	// Duplicates are detected and not stored twice
+	assert!(!seen.insert([1; 32]));
+	assert_eq!(seen.len(), 3);
+
+	assert!(seen.insert([3; 32]));
+	assert_eq!(seen.len(), 3);
+	assert!(!seen.contains(&[0; 32]));
+	assert!(seen.contains(&[1; 32]) && seen.contains(&[3; 32]));
+	// A forgotten hash is new again
+	assert!(seen.insert([0; 32]));
+	assert!(!seen.contains(&[1; 32]));
+}
+
+#[test]
+fn fanout_is_a_subset_of_the_peers() {
+	let peers: HashSet<PeerUrl> = (0..20)
+		.map(|i| PeerUrl::parse(&format!("http://10.0.0.{}:9812", i)).unwrap())
+		.collect();
+	let chosen = choose_fanout(&peers, 8);
+	assert_eq!(chosen.len(), 8);
+	assert_eq!(chosen.iter().collect::<HashSet<_>>().len(), 8);
+	assert!(chosen.iter().all(|peer| peers.contains(peer)));
+	// With fewer peers than the fanout every peer is chosen
+	assert_eq!(choose_fanout(&peers, 50).len(), 20);
+	assert!(choose_fanout(&HashSet::<PeerUrl>::new(), 8).is_empty());
+}
+
+/// Opens a node of the given genesis in a new temporary directory, listening on a random port. Returns the node and the directory
+pub(crate) fn temporary_node(genesis: GenesisSpec, config: NodeConfig) -> (Node, String) {
+	let directory = temporary_directory("node");
+	let config = NodeConfig {
+		listing_port: thread_rng().gen_range(20000..60000),
+		..config
+	};
+	(Node::open(PROTOCOL_VERSION, config, genesis, &directory, 100).expect("Unable to open the node"), directory)
+}
+
+/// The address at which the node listens
+pub(crate) fn url_of(node: &Node) -> PeerUrl {
+	PeerUrl::parse(&format!("http://{}:{}", local_ip().unwrap(), node.config.listing_port)).unwrap()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn relayed_blocks_are_announced_and_sent_when_requested() {
+	let genesis = test_genesis();
+	let (relayer, relayer_directory) = temporary_node(genesis.clone(), NodeConfig::default());
+	let (mut receiver, receiver_directory) = temporary_node(genesis, NodeConfig::default());
+	receiver.start_node();
+	let receiver_url = url_of(&receiver);
+	relayer.peers.write().await.insert(receiver.node_id(), Peer {
+		url: receiver_url.clone(),
+		protocol: PeerProtocol { version: PROTOCOL_VERSION, features: Features::full_node() },
+	});
+
+	let block = {
+		let chain = relayer.blockchain.read().await;
+		new_block(&chain, &chain.get_last_block(), vec![], 1, Wallet::random().address)
+	};
+	relayer.relay_block(block.clone()).await;
+	assert_eq!(receiver.blockchain.read().await.get_last_block(), block);
+
+	// Known items are not requested again
+	let inv = Inv {
+		version: PROTOCOL_VERSION,
+		data_type: InvDataType::Block,
+		hashes: vec![block.header.hash, [7u8; 32]],
+	};
+	let get_data = Sender::announce(&Client::new(), receiver_url.to_url(), &inv).await.unwrap();
+	assert_eq!(get_data.hashes, vec![[7u8; 32]]);
+
+	receiver.shutdown().await;
+	drop((relayer, receiver));
+	std::fs::remove_dir_all(relayer_directory).ok();
+	std::fs::remove_dir_all(receiver_directory).ok();
+}
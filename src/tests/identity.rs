@@ -0,0 +1,134 @@
+use std::fs::remove_dir_all;
+use std::net::{IpAddr, Ipv4Addr};
+
+use reqwest::Client;
+
+use crate::core::keys::identity::NodeIdentity;
+use crate::data_storage::node_config_storage::node_config::NodeConfig;
+use crate::data_storage::node_config_storage::url_serialize::PeerUrl;
+use crate::network::authentication::{ChallengeCache, MAX_CHALLENGES_PER_HOST, MAX_PENDING_CHALLENGES, NodeProof, pair_up_content, PAIR_UP_DOMAIN, Receiver, UNPAIR_DOMAIN};
+use crate::network::models::PairUp;
+use crate::network::node::Node;
+use crate::network::sender::Sender;
+use crate::tests::blockchain::test_genesis;
+use crate::tests::gossip::{temporary_node, url_of};
+use crate::tests::temporary_directory;
+
+const LOCALHOST: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+#[test]
+fn proofs_bind_the_identity_to_the_nonce_and_content() {
+	let identity = NodeIdentity::random();
+	let nonce = [5u8; 32];
+	let proof = NodeProof::create(&identity, PAIR_UP_DOMAIN, nonce, b"content");
+	assert_eq!(proof.verify(PAIR_UP_DOMAIN, b"content"), Some(identity.id()));
+
+	// The proof is not valid for another message, kind of message or nonce
+	assert_eq!(proof.verify(PAIR_UP_DOMAIN, b"other content"), None);
+	assert_eq!(proof.verify(UNPAIR_DOMAIN, b"content"), None);
+	let replayed = NodeProof {
+		nonce: [6u8; 32],
+		..proof.clone()
+	};
+	assert_eq!(replayed.verify(PAIR_UP_DOMAIN, b"content"), None);
+	// Another node can not claim the proof as its own
+	let impersonated = NodeProof {
+		public_key: NodeIdentity::random().public_key,
+		..proof
+	};
+	assert_eq!(impersonated.verify(PAIR_UP_DOMAIN, b"content"), None);
+}
+
+#[test]
+fn challenges_can_only_be_redeemed_once() {
+	let mut challenges = ChallengeCache::default();
+	let nonce = challenges.issue(LOCALHOST);
+	assert_ne!(challenges.issue(LOCALHOST), nonce);
+	assert_eq!(challenges.len(), 2);
+
+	assert!(challenges.redeem(&nonce));
+	assert!(!challenges.redeem(&nonce));
+	assert!(!challenges.redeem(&[0u8; 32]));
+	assert_eq!(challenges.len(), 1);
+}
+
+#[test]
+fn hosts_with_too_many_challenges_lose_their_oldest_one() {
+	let mut challenges = ChallengeCache::default();
+	let other_host = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+	let other_nonce = challenges.issue(other_host);
+	let oldest = challenges.issue(LOCALHOST);
+	for _ in 1..MAX_CHALLENGES_PER_HOST {
+		challenges.issue(LOCALHOST);
+	}
+	assert_eq!(challenges.len(), MAX_CHALLENGES_PER_HOST + 1);
+
+	let newest = challenges.issue(LOCALHOST);
+	assert_eq!(challenges.len(), MAX_CHALLENGES_PER_HOST + 1);
+	assert!(!challenges.redeem(&oldest));
+	assert!(challenges.redeem(&newest));
+	assert!(challenges.redeem(&other_nonce));
+}
+
+#[test]
+fn the_oldest_challenge_is_forgotten_when_the_cache_is_full() {
+	let mut challenges = ChallengeCache::default();
+	let nonces: Vec<_> = (0..MAX_PENDING_CHALLENGES as u32)
+		.map(|i| challenges.issue(IpAddr::V4(Ipv4Addr::from(i))))
+		.collect();
+	let newest = challenges.issue(LOCALHOST);
+	assert_eq!(challenges.len(), MAX_PENDING_CHALLENGES);
+	assert!(!challenges.redeem(&nonces[0]));
+	assert!(challenges.redeem(&nonces[1]));
+	assert!(challenges.redeem(&newest));
+}
+
+#[test]
+fn identity_is_kept_between_runs() {
+	let base_directory = &temporary_directory("identity");
+	let identity = NodeIdentity::load(base_directory);
+	assert_eq!(NodeIdentity::load(base_directory).id(), identity.id());
+	remove_dir_all(base_directory).unwrap();
+	assert_ne!(NodeIdentity::load(base_directory).id(), identity.id());
+	remove_dir_all(base_directory).unwrap();
+}
+
+/// Signs a `PairUp` from the node for a nonce of the receiver, bound to the given receiver address
+fn pair_up_message(node: &Node, nonce: [u8; 32], receiver: Receiver) -> PairUp {
+	let (version, info, method, port) = (node.version, node.version_info.clone(), node.config.http_scheme, node.config.listing_port);
+	let challenge = [7u8; 32];
+	let content = pair_up_content(version, &info, method, port, &challenge, receiver);
+	PairUp { version, info, method, port, challenge, proof: node.prove(PAIR_UP_DOMAIN, nonce, &content) }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn proofs_passed_on_to_another_node_are_rejected() {
+	let genesis = test_genesis();
+	let (sender, sender_directory) = temporary_node(genesis.clone(), NodeConfig::default());
+	let (mut receiver, receiver_directory) = temporary_node(genesis, NodeConfig::default());
+	receiver.start_node();
+	let (client, receiver_url) = (Client::new(), url_of(&receiver));
+
+	// A node in the middle passes the nonce of the receiver on, but the sender binds its proof to the address of that node
+	let relay_url = PeerUrl::parse("http://10.9.9.9:1234").unwrap();
+	let challenge = Sender::get_challenge(&client, receiver_url.to_url()).await.unwrap();
+	let relayed = pair_up_message(&sender, challenge.nonce, Receiver { url: &relay_url, public_key: &challenge.public_key });
+	assert!(Sender::pair_up_with(&client, receiver_url.to_url(), relayed).await.is_err());
+	// Proofs bound to another identity are rejected too
+	let challenge = Sender::get_challenge(&client, receiver_url.to_url()).await.unwrap();
+	let other_key = NodeIdentity::random().public_key;
+	let relayed = pair_up_message(&sender, challenge.nonce, Receiver { url: &receiver_url, public_key: &other_key });
+	assert!(Sender::pair_up_with(&client, receiver_url.to_url(), relayed).await.is_err());
+	assert!(receiver.peers.read().await.is_empty());
+
+	let challenge = Sender::get_challenge(&client, receiver_url.to_url()).await.unwrap();
+	let direct = pair_up_message(&sender, challenge.nonce, Receiver { url: &receiver_url, public_key: &challenge.public_key });
+	let answer = Sender::pair_up_with(&client, receiver_url.to_url(), direct).await.unwrap();
+	assert_eq!(answer.proof.public_key, challenge.public_key);
+	assert!(receiver.peers.read().await.contains_key(&sender.node_id()));
+
+	receiver.shutdown().await;
+	drop((sender, receiver));
+	remove_dir_all(sender_directory).ok();
+	remove_dir_all(receiver_directory).ok();
+}
@@ -0,0 +1,24 @@
+use std::fs::remove_dir_all;
+#[cfg(unix)]
+use std::os::unix::fs::PermissionsExt;
+
+use crate::core::keys::NodeKeyChain;
+use crate::tests::temporary_directory;
+
+#[test]
+fn keys_are_kept_between_runs_and_only_readable_by_the_owner() {
+	let base_directory = &temporary_directory("keys");
+	let path = format!("{}/node/keys.json", base_directory);
+	let keys = NodeKeyChain::load(base_directory);
+	assert_eq!(NodeKeyChain::load(base_directory).vrf_key_pair, keys.vrf_key_pair);
+	#[cfg(unix)]
+	{
+		let mode = |path: &str| std::fs::metadata(path).unwrap().permissions().mode() & 0o777;
+		assert_eq!(mode(&path), 0o600);
+		// Files written with the default permissions are restricted when they are loaded
+		std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644)).unwrap();
+		assert_eq!(NodeKeyChain::load(base_directory).vrf_key_pair, keys.vrf_key_pair);
+		assert_eq!(mode(&path), 0o600);
+	}
+	remove_dir_all(base_directory).unwrap();
+}
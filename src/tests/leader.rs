@@ -0,0 +1,89 @@
+use std::sync::Arc;
+use std::sync::atomic::AtomicU64;
+
+use crate::core::block::{BlockError, BlockValidity};
+use crate::core::genesis::GenesisSpec;
+use crate::core::Hashable;
+use crate::core::parameters::Parameters;
+use crate::tests::blockchain::{forge_block, new_block, Stakeholder, temporary_blockchain, temporary_blockchain_from, Wallet};
+
+#[test]
+fn leaders_must_win_the_lottery() {
+	let (small, big) = (Stakeholder::default_forger(), Stakeholder::random());
+	let genesis = GenesisSpec {
+		stake_distribution: vec![small.genesis_entry(1), big.genesis_entry(999_999_999)],
+		parameters: Parameters::default(),
+		..Default::default()
+	};
+	let (chain, directory) = temporary_blockchain_from(genesis);
+	let genesis_block = chain.get_last_block();
+	let reward_address = Wallet::random().address;
+
+	// A valid proof is not enough, the output must be under the threshold of the stake
+	let block = forge_block(&chain, &genesis_block, vec![], 1, &small, reward_address);
+	assert_eq!(chain.is_block_valid(&block), Err(BlockError::NotLeader));
+	let outsider = Stakeholder::random();
+	let block = forge_block(&chain, &genesis_block, vec![], 1, &outsider, reward_address);
+	assert_eq!(chain.is_block_valid(&block), Err(BlockError::NotLeader));
+
+	drop(chain);
+	std::fs::remove_dir_all(directory).ok();
+}
+
+#[test]
+fn vrf_proof_must_match_slot_and_key() {
+	let (chain, directory) = temporary_blockchain();
+	let genesis_block = chain.get_last_block();
+	let reward_address = Wallet::random().address;
+	assert_eq!(chain.is_block_valid(&new_block(&chain, &genesis_block, vec![], 3, reward_address)), Ok(()));
+
+	// The proof of a slot can not be used in another one
+	let mut moved = new_block(&chain, &genesis_block, vec![], 3, reward_address);
+	moved.header.slot = 4;
+	moved.update_hash();
+	assert_eq!(chain.is_block_valid(&moved), Err(BlockError::InvalidVRF));
+
+	// Nor claim the key of a stakeholder with a proof of another key
+	let mut impostor = forge_block(&chain, &genesis_block, vec![], 3, &Stakeholder::random(), reward_address);
+	impostor.header.forger_vrf_public_key = Stakeholder::default_forger().vrf_public_key();
+	impostor.update_hash();
+	assert_eq!(chain.is_block_valid(&impostor), Err(BlockError::InvalidVRF));
+
+	drop(chain);
+	std::fs::remove_dir_all(directory).ok();
+}
+
+#[test]
+fn slots_must_increase_and_not_be_in_the_future() {
+	let (mut chain, directory) = temporary_blockchain();
+	let reward_address = Wallet::random().address;
+	chain.set_current_slot(Arc::new(AtomicU64::new(5)));
+	let genesis_block = chain.get_last_block();
+
+	let first = new_block(&chain, &genesis_block, vec![], 2, reward_address);
+	assert_eq!(chain.add_block(&first), Ok(BlockValidity::Valid));
+	assert_eq!(chain.is_block_valid(&new_block(&chain, &first, vec![], 2, reward_address)), Err(BlockError::SlotNotIncreasing));
+	assert_eq!(chain.is_block_valid(&new_block(&chain, &first, vec![], 7, reward_address)), Err(BlockError::FutureSlot));
+	assert_eq!(chain.add_block(&new_block(&chain, &first, vec![], 7, reward_address)), Err(BlockError::FutureSlot));
+	assert_eq!(chain.check_header_chain(&[new_block(&chain, &first, vec![], 7, reward_address).header]), Err(BlockError::FutureSlot));
+	// Peers whose clock is ahead are not punished for it, unlike peers that send blocks that do not match their own hash
+	assert!(!BlockError::FutureSlot.is_provably_invalid());
+	assert!(BlockError::InvalidHash.is_provably_invalid());
+	// Nor are peers that follow another branch or run a newer version
+	assert!(!BlockError::ReorganizationFailed.is_provably_invalid());
+	assert!(!BlockError::UnsupportedVersion(u16::MAX).is_provably_invalid());
+	assert_eq!(chain.is_block_valid(&new_block(&chain, &first, vec![], 5, reward_address)), Ok(()));
+	// The clocks of the nodes may drift by one slot
+	assert_eq!(chain.is_block_valid(&new_block(&chain, &first, vec![], 6, reward_address)), Ok(()));
+	assert!(chain.is_header_chain_valid(&[new_block(&chain, &first, vec![], 6, reward_address).header]));
+
+	// Blocks must extend the last block
+	assert_eq!(chain.is_block_valid(&new_block(&chain, &genesis_block, vec![], 3, reward_address)), Err(BlockError::WrongParent));
+	let mut skipping = new_block(&chain, &first, vec![], 3, reward_address);
+	skipping.header.height += 1;
+	skipping.update_hash();
+	assert_eq!(chain.is_block_valid(&skipping), Err(BlockError::WrongHeight));
+
+	drop(chain);
+	std::fs::remove_dir_all(directory).ok();
+}
@@ -0,0 +1,133 @@
+use crate::core::address::P2PKHAddress;
+use crate::core::block::{BlockError, BlockHeader, BlockValidity};
+use crate::core::blockchain::BlockChain;
+use crate::core::genesis::GenesisSpec;
+use crate::core::light_chain::LightChain;
+use crate::tests::blockchain::{forge_block, forge_block_with_nonce, fund_wallets, get_utxos_of, new_block, spend, temporary_blockchain, temporary_blockchain_from, test_parameters, Stakeholder, Wallet};
+use crate::tests::temporary_directory;
+
+/// Opens a light chain of the same genesis as the full chain in a new temporary directory. Returns the chain and the directory
+fn temporary_light_chain(full: &BlockChain) -> (LightChain, String) {
+	let directory = temporary_directory("light-chain");
+	(LightChain::open(&directory, &full.genesis).expect("Unable to open the light chain"), directory)
+}
+
+/// The headers of the best chain of a full node, without the genesis
+fn headers_of(chain: &BlockChain) -> Vec<BlockHeader> {
+	(1..=chain.get_height()).map(|height| chain.get_block_at(height).unwrap().header).collect()
+}
+
+#[test]
+fn follows_the_headers_of_a_full_chain() {
+	let (mut full, full_directory) = temporary_blockchain();
+	let (mut light, light_directory) = temporary_light_chain(&full);
+	assert_eq!(light.get_chain_id(), full.get_chain_id());
+
+	let (alice, bob) = (Wallet::random(), Wallet::random());
+	fund_wallets(&full, std::slice::from_ref(&alice));
+	let transactions: Vec<_> = get_utxos_of(&full, &alice).iter().map(|utxo| spend(&full, utxo, &alice, &[&bob])).collect();
+	let block = new_block(&full, &full.get_last_block(), transactions, 1, bob.address);
+	full.add_block(&block).unwrap();
+	for slot in 2..5 {
+		let next = new_block(&full, &full.get_last_block(), vec![], slot, bob.address);
+		full.add_block(&next).unwrap();
+	}
+
+	assert_eq!(light.add_headers(&headers_of(&full)), Ok(4));
+	assert_eq!(light.get_height(), 4);
+	assert_eq!(light.get_last_header(), full.get_last_block().header);
+	assert_eq!(light.get_block_locator(), full.get_block_locator());
+	// Known headers are skipped
+	assert_eq!(light.add_headers(&headers_of(&full)), Ok(0));
+
+	let txid = block.transactions[1].id;
+	let proof = block.get_transaction_proof(&txid).unwrap();
+	assert_eq!(light.verify_transaction(&txid, &block.header.hash, &proof), Some(4));
+	assert_eq!(light.verify_transaction(&block.transactions[0].id, &block.header.hash, &proof), None);
+	// The proof must be against a header of the chain
+	assert_eq!(light.verify_transaction(&txid, &[1u8; 32], &proof), None);
+
+	drop((full, light));
+	std::fs::remove_dir_all(full_directory).ok();
+	std::fs::remove_dir_all(light_directory).ok();
+}
+
+#[test]
+fn rejects_invalid_headers() {
+	let (full, full_directory) = temporary_blockchain();
+	let (mut light, light_directory) = temporary_light_chain(&full);
+	let genesis = full.get_last_block();
+	let block = new_block(&full, &genesis, vec![], 2, P2PKHAddress::null());
+
+	let mut tampered = block.header.clone();
+	tampered.slot += 1;
+	assert_eq!(light.add_headers(&[tampered]), Err(BlockError::InvalidHash));
+
+	let wrong_nonce = forge_block_with_nonce(&full, &genesis, vec![], 2, &Stakeholder::default_forger(), P2PKHAddress::null(), [9u8; 32]);
+	assert_eq!(light.add_headers(&[wrong_nonce.header]), Err(BlockError::InvalidVRF));
+
+	let orphan = new_block(&full, &block, vec![], 3, P2PKHAddress::null());
+	assert_eq!(light.add_headers(&[orphan.header]), Err(BlockError::Orphan));
+
+	let same_slot = new_block(&full, &block, vec![], 2, P2PKHAddress::null());
+	assert_eq!(light.add_headers(&[block.header.clone(), same_slot.header]), Err(BlockError::SlotNotIncreasing));
+	// Nothing is stored if some header is invalid
+	assert_eq!(light.get_height(), 0);
+
+	drop((full, light));
+	std::fs::remove_dir_all(full_directory).ok();
+	std::fs::remove_dir_all(light_directory).ok();
+}
+
+#[test]
+fn better_fork_replaces_headers() {
+	let (full, full_directory) = temporary_blockchain();
+	let (mut light, light_directory) = temporary_light_chain(&full);
+	let genesis = full.get_last_block();
+	let a1 = new_block(&full, &genesis, vec![], 1, P2PKHAddress::null());
+	let b1 = new_block(&full, &genesis, vec![], 2, P2PKHAddress::null());
+	let b2 = new_block(&full, &b1, vec![], 3, P2PKHAddress::null());
+	assert_eq!(light.add_headers(std::slice::from_ref(&a1.header)), Ok(1));
+
+	let b1_added = light.add_headers(std::slice::from_ref(&b1.header)).unwrap();
+	assert_eq!(b1_added, if b1.header.vrf < a1.header.vrf { 1 } else { 0 });
+	assert_eq!(light.add_headers(&[b1.header.clone(), b2.header.clone()]), Ok(2 - b1_added));
+	assert_eq!(light.get_last_header(), b2.header);
+	assert_eq!(light.get_header_at(1), Some(b1.header));
+	assert!(!light.contains_header(&a1.header.hash));
+
+	drop((full, light));
+	std::fs::remove_dir_all(full_directory).ok();
+	std::fs::remove_dir_all(light_directory).ok();
+}
+
+#[test]
+fn follows_forgers_whose_stake_was_received_after_the_genesis() {
+	let (alice, bob) = (Stakeholder::default_forger(), Stakeholder::random());
+	let mut parameters = test_parameters();
+	parameters.technical_parameters.epoch_duration = 10;
+	let genesis = GenesisSpec {
+		stake_distribution: vec![alice.genesis_entry(1_000_000), bob.genesis_entry(0)],
+		parameters,
+		..Default::default()
+	};
+	let (mut full, full_directory) = temporary_blockchain_from(genesis.clone());
+	let (mut light, light_directory) = temporary_light_chain(&full);
+
+	// Alice gives all her stake to Bob, who has none in the genesis, and Bob forges the blocks of the next epoch
+	let to_bob = spend(&full, &genesis.get_utxos()[0], &alice.wallet, &[&bob.wallet]);
+	let first = forge_block(&full, &full.get_last_block(), vec![to_bob], 1, &alice, Wallet::random().address);
+	assert_eq!(full.add_block(&first), Ok(BlockValidity::Valid));
+	for slot in 10..13 {
+		let block = forge_block(&full, &full.get_last_block(), vec![], slot, &bob, bob.wallet.address);
+		assert_eq!(full.add_block(&block), Ok(BlockValidity::Valid));
+	}
+	assert_eq!(full.get_stake_distribution(1).get_stake(&alice.vrf_public_key()), 0);
+
+	assert_eq!(light.add_headers(&headers_of(&full)), Ok(4));
+	assert_eq!(light.get_last_header(), full.get_last_block().header);
+
+	drop((full, light));
+	std::fs::remove_dir_all(full_directory).ok();
+	std::fs::remove_dir_all(light_directory).ok();
+}
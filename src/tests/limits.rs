@@ -0,0 +1,170 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use actix_web::FromRequest;
+use actix_web::http::{header, StatusCode};
+use actix_web::test::TestRequest;
+use reqwest::{Client, Url};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+use crate::core::parameters::NetworkParameters;
+use crate::network::limits::{ENVELOPE_OVERHEAD, MAX_TRACKED_PEERS, PayloadLimit, RateLimiter, subnet_key};
+use crate::network::models::{GetData, GetHeaders, InvDataType};
+use crate::network::models::http_errors::ErrorType;
+use crate::network::sender::{ResponseTooLarge, Sender};
+use crate::network::standard::{BINCODE_DATA_TYPE, StandardExtractor, WireFormat};
+
+#[test]
+fn rate_limiter_limits_each_ip() {
+	let (first, second) = (IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)), IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2)));
+	let rate_limiter = RateLimiter::new(3, 1, 2);
+
+	// Only two requests are handled at once
+	let guards: Vec<_> = (0..2).map(|_| rate_limiter.acquire(first)).collect();
+	assert!(guards.iter().all(Result::is_ok));
+	assert!(matches!(rate_limiter.acquire(first), Err(ErrorType::TooManyConcurrentRequests(2))));
+	drop(guards);
+	// The burst is spent, so the next request has to wait for a new token
+	assert!(rate_limiter.acquire(first).is_ok());
+	assert!(matches!(rate_limiter.acquire(first), Err(ErrorType::RateLimited(1))));
+	// Other IPs have their own bucket
+	assert!(rate_limiter.acquire(second).is_ok());
+}
+
+#[test]
+fn rate_limiter_shares_buckets_of_the_same_host() {
+	let rate_limiter = RateLimiter::new(1, 0, 8);
+	let ip = |address: &str| address.parse::<IpAddr>().unwrap();
+
+	// Addresses of the same /64 network belong to the same host
+	assert!(rate_limiter.acquire(ip("2001:db8:1:2::1")).is_ok());
+	assert!(rate_limiter.acquire(ip("2001:db8:1:2:ffff::7")).is_err());
+	assert!(rate_limiter.acquire(ip("2001:db8:1:3::1")).is_ok());
+	// And an IPv4-mapped address is the IPv4 address
+	assert!(rate_limiter.acquire(ip("10.0.0.1")).is_ok());
+	assert!(rate_limiter.acquire(ip("::ffff:10.0.0.1")).is_err());
+}
+
+#[test]
+fn rate_limiter_forgets_the_least_recently_used_ips() {
+	let rate_limiter = RateLimiter::new(1, 0, 8);
+	let (first, second) = (IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)), IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2)));
+	// Without refills, the buckets of these IPs stay empty until they are forgotten
+	let guards = (rate_limiter.acquire(first), rate_limiter.acquire(second));
+	for i in 0..MAX_TRACKED_PEERS - 2 {
+		rate_limiter.acquire(IpAddr::V6(Ipv6Addr::from((i as u128 + 1) << 64))).ok();
+	}
+	assert!(rate_limiter.acquire(first).is_err());
+
+	// Even IPs with requests in flight are forgotten, starting with the least recently used one
+	assert!(rate_limiter.acquire(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 3))).is_ok());
+	assert!(rate_limiter.acquire(first).is_err());
+	assert!(rate_limiter.acquire(second).is_ok());
+	drop(guards);
+}
+
+#[actix_rt::test]
+async fn extractor_enforces_payload_limit() {
+	let msg = GetHeaders { version: 0, block_locator_object: vec![[7u8; 32]; 64] };
+	let body = WireFormat::Bincode.encode(&msg).unwrap();
+
+	let (req, mut payload) = TestRequest::default()
+		.insert_header((header::CONTENT_TYPE, BINCODE_DATA_TYPE))
+		.app_data(PayloadLimit(body.len() - ENVELOPE_OVERHEAD))
+		.set_payload(body.clone())
+		.to_http_parts();
+	assert!(StandardExtractor::<GetHeaders>::from_request(&req, &mut payload).await.is_ok());
+
+	let (req, mut payload) = TestRequest::default()
+		.insert_header((header::CONTENT_TYPE, BINCODE_DATA_TYPE))
+		.app_data(PayloadLimit(body.len() - ENVELOPE_OVERHEAD - 1))
+		.set_payload(body)
+		.to_http_parts();
+	let Err(err) = StandardExtractor::<GetHeaders>::from_request(&req, &mut payload).await else {
+		panic!("An oversized payload was accepted");
+	};
+	assert_eq!(err.as_response_error().status_code(), StatusCode::PAYLOAD_TOO_LARGE);
+
+	// Blocks can take the whole body and header size
+	let network_parameters = NetworkParameters::default();
+	let limit = PayloadLimit::blocks(&network_parameters).encoded_limit(WireFormat::Bincode);
+	assert!(limit > network_parameters.max_block_body_size + network_parameters.max_block_header_size);
+	assert!(PayloadLimit::transactions(&network_parameters).encoded_limit(WireFormat::Json) > PayloadLimit::transactions(&network_parameters).encoded_limit(WireFormat::Bincode));
+}
+
+#[test]
+fn subnets_are_the_ipv4_24_and_the_ipv6_48_networks() {
+	let v4 = |d| IpAddr::V4(Ipv4Addr::new(10, 0, 1, d));
+	assert_eq!(subnet_key(v4(1)), v4(0));
+	assert_eq!(subnet_key(v4(1)), subnet_key(v4(255)));
+	assert_ne!(subnet_key(v4(1)), subnet_key(IpAddr::V4(Ipv4Addr::new(10, 0, 2, 1))));
+	// IPv4-mapped addresses are in the network of their IPv4 address
+	assert_eq!(subnet_key(IpAddr::V6(Ipv4Addr::new(10, 0, 1, 7).to_ipv6_mapped())), v4(0));
+
+	let v6 = |third, last| IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, third, 5, 0, 0, 0, last));
+	assert_eq!(subnet_key(v6(1, 1)), IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 1, 0, 0, 0, 0, 0)));
+	assert_eq!(subnet_key(v6(1, 1)), subnet_key(v6(1, 2)));
+	assert_ne!(subnet_key(v6(1, 1)), subnet_key(v6(2, 1)));
+}
+
+/// Answers a single request with a body of the given size, either with its length or in chunks without it. Returns the address of the server
+async fn serve_body_once(size: usize, chunked: bool) -> Url {
+	let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+	let url = Url::parse(&format!("http://{}", listener.local_addr().unwrap())).unwrap();
+	tokio::spawn(async move {
+		let (mut stream, _) = listener.accept().await.unwrap();
+		// Only the head of the request is read, its body is ignored
+		let (mut request, mut buffer) = (Vec::new(), [0u8; 1024]);
+		while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+			match stream.read(&mut buffer).await {
+				Ok(0) | Err(_) => return,
+				Ok(read) => request.extend_from_slice(&buffer[..read]),
+			}
+		}
+		let length = if chunked { "Transfer-Encoding: chunked".to_string() } else { format!("Content-Length: {}", size) };
+		let head = format!("HTTP/1.1 200 OK\r\nContent-Type: {}\r\n{}\r\n\r\n", BINCODE_DATA_TYPE, length);
+		stream.write_all(head.as_bytes()).await.ok();
+		const CHUNK_SIZE: usize = 16 * 1024;
+		let mut sent = 0;
+		// The client may stop reading before the end, so write errors are ignored
+		while sent < size {
+			let chunk = vec![0u8; CHUNK_SIZE.min(size - sent)];
+			if chunked {
+				stream.write_all(format!("{:x}\r\n", chunk.len()).as_bytes()).await.ok();
+			}
+			if stream.write_all(&chunk).await.is_err() {
+				return;
+			}
+			if chunked {
+				stream.write_all(b"\r\n").await.ok();
+			}
+			sent += chunk.len();
+		}
+		if chunked {
+			stream.write_all(b"0\r\n\r\n").await.ok();
+		}
+	});
+	url
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn responses_larger_than_their_limit_are_rejected() {
+	let network_parameters = NetworkParameters::default();
+	let msg = GetData { version: 0, data_type: InvDataType::Block, hashes: vec![[1u8; 32]] };
+	let limit = PayloadLimit::block_bodies(&network_parameters, msg.hashes.len()).encoded_limit(WireFormat::Bincode);
+	let client = Client::new();
+
+	for chunked in [false, true] {
+		let peer = serve_body_once(10 * limit, chunked).await;
+		let err = Sender::get_data(&client, peer, msg.clone(), &network_parameters).await.err().expect("An oversized response was accepted");
+		assert_eq!(err.downcast_ref::<ResponseTooLarge>(), Some(&ResponseTooLarge(limit)));
+	}
+	// Bodies within the limit are decoded, even if their content is not valid
+	let peer = serve_body_once(limit, true).await;
+	let err = Sender::get_data(&client, peer, msg.clone(), &network_parameters).await.err().expect("Invalid data was decoded");
+	assert!(err.downcast_ref::<ResponseTooLarge>().is_none());
+
+	// Asking for more blocks allows larger responses, and headers have their own limit
+	assert!(PayloadLimit::block_bodies(&network_parameters, 64).0 > 64 * network_parameters.max_block_body_size);
+	assert!(PayloadLimit::headers(&network_parameters).0 > PayloadLimit::default().0);
+}
@@ -0,0 +1,94 @@
+use rand::{Rng, thread_rng};
+
+use crate::core::block::BlockError;
+use crate::core::utxo::sighash::SigHashType;
+use crate::crypto::hash::hash;
+use crate::crypto::hash::merkle::{calculate_merkle_root, MerkleProof};
+use crate::tests::blockchain::{fund_wallets, get_utxos_of, new_block, spend, temporary_blockchain, Wallet};
+
+fn random_leaves(count: usize) -> Vec<[u8; 32]> {
+	let mut rng = thread_rng();
+	(0..count).map(|_| rng.gen()).collect()
+}
+
+#[test]
+fn every_leaf_has_a_proof() {
+	for count in 1..=17 {
+		let leaves = random_leaves(count);
+		let root = calculate_merkle_root(leaves.clone());
+		for (i, leaf) in leaves.iter().enumerate() {
+			let proof = MerkleProof::generate(&leaves, i).unwrap();
+			assert!(proof.verify(leaf, &root), "Proof of leaf {} of {} is not valid", i, count);
+			// The proof only works for its leaf and its position
+			assert!(!proof.verify(&leaves[(i + 1) % count], &root) || count == 1);
+			let moved = MerkleProof { index: (i + 1) % count, ..proof.clone() };
+			assert!(!moved.verify(leaf, &root) || count == 1);
+		}
+		assert!(MerkleProof::generate(&leaves, count).is_none());
+	}
+}
+
+#[test]
+fn proofs_must_match_the_tree() {
+	let leaves = random_leaves(6);
+	let root = calculate_merkle_root(leaves.clone());
+	let proof = MerkleProof::generate(&leaves, 4).unwrap();
+
+	let mut tampered = proof.clone();
+	tampered.siblings[0][0] ^= 1;
+	assert!(!tampered.verify(&leaves[4], &root));
+	let mut extra_sibling = proof.clone();
+	extra_sibling.siblings.push([0u8; 32]);
+	assert!(!extra_sibling.verify(&leaves[4], &root));
+	let other_size = MerkleProof { leaf_count: 5, ..proof.clone() };
+	assert!(!other_size.verify(&leaves[4], &root));
+	let out_of_bounds = MerkleProof { index: 6, ..proof };
+	assert!(out_of_bounds.calculate_root(&leaves[4]).is_none());
+}
+
+#[test]
+fn inner_nodes_are_not_leaves() {
+	// Odd levels are not padded with a copy of the last leaf
+	let leaves = random_leaves(3);
+	let padded = [leaves.clone(), vec![leaves[2]]].concat();
+	assert_ne!(calculate_merkle_root(leaves.clone()), calculate_merkle_root(padded));
+
+	// A tree whose leaves are the inner nodes of another tree has a different root
+	let leaves = random_leaves(4);
+	let root = calculate_merkle_root(leaves.clone());
+	let proof = MerkleProof::generate(&leaves, 0).unwrap();
+	// Without domain separation, the pair of the first two leaves would be a leaf of a tree with the same root
+	let unprefixed_pair = hash(&[leaves[0], leaves[1]].concat());
+	assert_ne!(calculate_merkle_root(vec![unprefixed_pair, proof.siblings[1]]), root);
+	let forged = MerkleProof { index: 1, leaf_count: 2, siblings: vec![proof.siblings[1]] };
+	let inner_node = calculate_merkle_root(leaves[..2].to_vec());
+	assert!(!forged.verify(&inner_node, &root));
+	assert_eq!(calculate_merkle_root(vec![]), [0u8; 32]);
+}
+
+#[test]
+fn transactions_are_proven_against_headers() {
+	let (chain, directory) = temporary_blockchain();
+	let (alice, bob) = (Wallet::random(), Wallet::random());
+	fund_wallets(&chain, std::slice::from_ref(&alice));
+	let transactions: Vec<_> = get_utxos_of(&chain, &alice).iter().map(|utxo| spend(&chain, utxo, &alice, &[&bob])).collect();
+	let block = new_block(&chain, &chain.get_last_block(), transactions.clone(), 1, bob.address);
+
+	for tx in &block.transactions {
+		let proof = block.get_transaction_proof(&tx.id).unwrap();
+		assert!(block.header.verify_transaction_proof(&tx.id, &proof));
+		assert!(!chain.get_last_block().header.verify_transaction_proof(&tx.id, &proof));
+	}
+	assert!(block.get_transaction_proof(&[0u8; 32]).is_none());
+
+	// The header commits to the signatures, even though the ids do not depend on them
+	let mut malleated = block.clone();
+	malleated.transactions[1].input_list[0].sighash_type = SigHashType::AllAnyoneCanPay;
+	assert_eq!(malleated.calculate_merkle_tree(), block.header.merkle_root);
+	assert_eq!(malleated.is_correct(), Err(BlockError::InvalidWitnessRoot));
+	assert!(!malleated.matches_header());
+	assert!(block.matches_header());
+
+	drop(chain);
+	std::fs::remove_dir_all(directory).ok();
+}
@@ -2,10 +2,11 @@ use std::collections::HashSet;
 use std::time::{Duration, Instant};
 
 use log::info;
+use rand::{Rng, thread_rng};
 use reqwest::{Client, Url};
 use spin_sleep::sleep;
 
-use crate::core::parameters::Parameters;
+use crate::core::genesis::GenesisSpec;
 use crate::core::utxo::transaction::Transaction;
 use crate::data_storage::node_config_storage::node_config::NodeConfig;
 use crate::network::models::HttpScheme;
@@ -16,13 +17,36 @@ mod address;
 mod lottery;
 pub(crate) mod timing;
 mod data_sotrage;
+pub(crate) mod blockchain;
+mod sighash;
+mod genesis;
+mod stake;
+mod epoch_nonce;
+mod leader;
+mod wire;
+mod encoding;
+mod merkle;
+mod light_client;
+pub(crate) mod gossip;
+mod address_book;
+mod limits;
+mod protocol;
+mod identity;
+mod peer_url;
+mod discovery;
+mod keys;
+
+/// Returns a new path in the temporary directory of the system, named after the test and unique to each call. Nothing is created
+pub(crate) fn temporary_directory(name: &str) -> String {
+	let directory = std::env::temp_dir().join(format!("{}-test-{}", name, thread_rng().gen::<u64>()));
+	directory.to_str().expect("Invalid temporary directory").to_string()
+}
 
 #[tokio::test(flavor = "multi_thread")]
 async fn blockinfo_test() {
 	// env::set_var("RUST_BACKTRACE", "4");
 
-	let parameters = Parameters::default();
-	let mut node = Node::new(0, None, parameters).await;
+	let mut node = Node::new(0, None, GenesisSpec::default()).await.unwrap();
 	node.start();
 	let client = Client::new();
 	if let Ok(inf) = Sender::get_blockchain_info(&client, Url::parse("http://192.168.1.104:8000").expect("Unable to parse url")).await {
@@ -30,7 +54,7 @@ async fn blockinfo_test() {
 		assert_eq!(inf.mempool_size, 0);
 	}
 	let tx = Transaction::create_transaction(vec!(), vec!(), 31263);
-	assert!(node.new_transaction(tx).await);
+	assert_eq!(node.new_transaction(tx).await, Ok(()));
 	sleep(Duration::from_secs_f32(0.5));
 	for _ in 0..3 {
 		if let Ok(inf) = Sender::get_blockchain_info(&client, Url::parse("http://192.168.1.104:8000").expect("Unable to parse url")).await {
@@ -0,0 +1,49 @@
+use crate::network::models::http_errors::ErrorType;
+use crate::network::protocol::{Features, VersionInfo};
+use crate::network::standard::WireFormat;
+
+fn version_info(min_version: u32, max_version: u32, chain_id: [u8; 32]) -> VersionInfo {
+	VersionInfo {
+		min_version,
+		max_version,
+		features: Features::full_node(),
+		chain_id,
+	}
+}
+
+#[test]
+fn peers_use_the_highest_common_version() {
+	let own = version_info(2, 4, [1; 32]);
+	assert_eq!(own.negotiate(&version_info(1, 3, [1; 32])).ok(), Some(3));
+	assert_eq!(own.negotiate(&version_info(3, 7, [1; 32])).ok(), Some(4));
+	assert_eq!(own.negotiate(&version_info(4, 4, [1; 32])).ok(), Some(4));
+	// Both sides agree on the same version
+	assert_eq!(version_info(1, 3, [1; 32]).negotiate(&own).ok(), Some(3));
+
+	assert!(matches!(own.negotiate(&version_info(0, 1, [1; 32])), Err(ErrorType::WrongVersion(1, 2, 4))));
+	assert!(matches!(own.negotiate(&version_info(5, 6, [1; 32])), Err(ErrorType::WrongVersion(6, 2, 4))));
+	// Peers of other networks are refused even if they share a version
+	assert!(matches!(own.negotiate(&version_info(2, 4, [2; 32])), Err(ErrorType::WrongChain(..))));
+
+	assert!(own.supports(2) && own.supports(4));
+	assert!(!own.supports(1) && !own.supports(5));
+}
+
+#[test]
+fn features_are_kept_on_the_wire() {
+	let light = Features::none();
+	let full = Features::full_node();
+	assert!(full.contains(Features::FULL_CHAIN) && full.contains(Features::TX_PROOFS.with(Features::INV_RELAY)));
+	assert!(!light.contains(Features::FULL_CHAIN));
+	assert!(light.contains(Features::none()));
+
+	// Features unknown to this node survive a round trip
+	let info = VersionInfo {
+		features: full.with(serde_json::from_str("1024").unwrap()),
+		..version_info(1, 1, [3; 32])
+	};
+	for format in [WireFormat::Bincode, WireFormat::Json] {
+		let decoded: VersionInfo = format.decode(&format.encode(&info).unwrap()).unwrap();
+		assert_eq!(decoded, info);
+	}
+}
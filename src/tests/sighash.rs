@@ -0,0 +1,146 @@
+use crate::core::utxo::{Input, Output, UTXO};
+use crate::core::utxo::sighash::{signature_hash, SigHashType};
+use crate::core::utxo::transaction::Transaction;
+use crate::tests::blockchain::Wallet;
+
+const CHAIN_ID: [u8; 32] = [7u8; 32];
+
+/// Creates a transaction spending one UTxO of each owner to two new outputs, with unsigned inputs
+fn two_input_transaction(owners: &[&Wallet; 2]) -> (Transaction, Vec<UTXO>) {
+	let spent_utxos: Vec<UTXO> = owners.iter().enumerate().map(|(i, owner)| UTXO {
+		txid: [i as u8 + 1; 32],
+		output_index: i,
+		amount: 1_000_000,
+		recipient_address: owner.address,
+		coinbase_height: None,
+	}).collect();
+	let inputs = spent_utxos.iter().zip(owners).map(|(utxo, owner)| Input {
+		prev_txid: utxo.txid,
+		output_index: utxo.output_index,
+		signature: vec![],
+		public_key: owner.public_key.clone(),
+		sighash_type: SigHashType::All,
+	}).collect();
+	let outputs = vec![
+		Output { amount: 900_000, address: Wallet::random().address },
+		Output { amount: 900_000, address: Wallet::random().address },
+	];
+	(Transaction::create_transaction(inputs, outputs, 42), spent_utxos)
+}
+
+fn signed_transaction(sighash_type: SigHashType) -> (Transaction, Vec<UTXO>) {
+	let (alice, bob) = (Wallet::random(), Wallet::random());
+	let (mut tx, spent_utxos) = two_input_transaction(&[&alice, &bob]);
+	tx.sign_input(0, &alice.private_key, sighash_type, &spent_utxos, &CHAIN_ID).unwrap();
+	tx.sign_input(1, &bob.private_key, sighash_type, &spent_utxos, &CHAIN_ID).unwrap();
+	assert!(tx.verify_input_signatures(&spent_utxos, &CHAIN_ID).is_ok());
+	(tx, spent_utxos)
+}
+
+#[test]
+fn tampering_with_any_output_invalidates_signatures() {
+	let (tx, spent_utxos) = signed_transaction(SigHashType::All);
+	for i in 0..tx.output_list.len() {
+		let mut tampered = tx.clone();
+		tampered.output_list[i].amount -= 1;
+		assert!(tampered.verify_input_signatures(&spent_utxos, &CHAIN_ID).is_err());
+
+		let mut tampered = tx.clone();
+		tampered.output_list[i].address = Wallet::random().address;
+		assert!(tampered.verify_input_signatures(&spent_utxos, &CHAIN_ID).is_err());
+	}
+	let mut tampered = tx.clone();
+	tampered.output_list.push(Output { amount: 1, address: Wallet::random().address });
+	assert!(tampered.verify_input_signatures(&spent_utxos, &CHAIN_ID).is_err());
+
+	let mut tampered = tx.clone();
+	tampered.output_list.pop();
+	assert!(tampered.verify_input_signatures(&spent_utxos, &CHAIN_ID).is_err());
+
+	let mut tampered = tx.clone();
+	tampered.output_list.swap(0, 1);
+	assert!(tampered.verify_input_signatures(&spent_utxos, &CHAIN_ID).is_err());
+}
+
+#[test]
+fn tampering_with_inputs_invalidates_signatures() {
+	let (tx, spent_utxos) = signed_transaction(SigHashType::All);
+
+	let mut tampered = tx.clone();
+	tampered.input_list.swap(0, 1);
+	assert!(tampered.verify_input_signatures(&[spent_utxos[1], spent_utxos[0]], &CHAIN_ID).is_err());
+
+	let mut tampered = tx.clone();
+	tampered.input_list[1].output_index += 1;
+	let mut moved_utxos = spent_utxos.clone();
+	moved_utxos[1].output_index += 1;
+	assert!(tampered.verify_input_signatures(&moved_utxos, &CHAIN_ID).is_err());
+
+	let mut tampered = tx.clone();
+	tampered.input_list[0].sighash_type = SigHashType::AllAnyoneCanPay;
+	assert!(tampered.verify_input_signatures(&spent_utxos, &CHAIN_ID).is_err());
+}
+
+#[test]
+fn signatures_commit_to_spent_amounts_and_chain() {
+	let (tx, spent_utxos) = signed_transaction(SigHashType::All);
+
+	let mut other_amounts = spent_utxos.clone();
+	other_amounts[1].amount += 1;
+	assert!(tx.verify_input_signatures(&other_amounts, &CHAIN_ID).is_err());
+	assert!(tx.verify_input_signatures(&spent_utxos, &[8u8; 32]).is_err());
+	assert!(tx.verify_input_signatures(&spent_utxos[..1], &CHAIN_ID).is_err());
+}
+
+#[test]
+fn single_only_commits_to_its_output() {
+	let (tx, spent_utxos) = signed_transaction(SigHashType::Single);
+
+	let mut tampered = tx.clone();
+	tampered.output_list.push(Output { amount: 1, address: Wallet::random().address });
+	assert!(tampered.verify_input_signatures(&spent_utxos, &CHAIN_ID).is_ok());
+
+	let mut tampered = tx.clone();
+	tampered.output_list[0].amount -= 1;
+	assert!(tampered.verify_input_signatures(&spent_utxos, &CHAIN_ID).is_err());
+
+	// Single can not sign an input without an output at the same index
+	let mut tampered = tx.clone();
+	tampered.output_list.pop();
+	assert!(tampered.verify_input_signatures(&spent_utxos, &CHAIN_ID).is_err());
+	assert_eq!(signature_hash(&tampered, 1, &spent_utxos, SigHashType::Single, &CHAIN_ID), None);
+}
+
+#[test]
+fn anyone_can_pay_allows_adding_inputs() {
+	let alice = Wallet::random();
+	let (mut tx, spent_utxos) = two_input_transaction(&[&alice, &alice]);
+	tx.input_list.pop();
+	tx.sign_input(0, &alice.private_key, SigHashType::AllAnyoneCanPay, &spent_utxos[..1], &CHAIN_ID).unwrap();
+
+	let mut extended = tx.clone();
+	extended.input_list.push(Input {
+		prev_txid: spent_utxos[1].txid,
+		output_index: spent_utxos[1].output_index,
+		signature: vec![],
+		public_key: alice.public_key.clone(),
+		sighash_type: SigHashType::All,
+	});
+	extended.sign_input(1, &alice.private_key, SigHashType::All, &spent_utxos, &CHAIN_ID).unwrap();
+	assert!(extended.verify_input_signatures(&spent_utxos, &CHAIN_ID).is_ok());
+
+	let mut tampered = extended.clone();
+	tampered.output_list[1].amount -= 1;
+	assert!(tampered.verify_input_signatures(&spent_utxos, &CHAIN_ID).is_err());
+}
+
+#[test]
+fn sighash_is_deterministic() {
+	let alice = Wallet::random();
+	let (tx, spent_utxos) = two_input_transaction(&[&alice, &alice]);
+	let hash = signature_hash(&tx, 0, &spent_utxos, SigHashType::All, &CHAIN_ID).unwrap();
+	assert_eq!(Some(hash), signature_hash(&tx.clone(), 0, &spent_utxos, SigHashType::All, &CHAIN_ID));
+	assert_ne!(Some(hash), signature_hash(&tx, 1, &spent_utxos, SigHashType::All, &CHAIN_ID));
+	assert_ne!(Some(hash), signature_hash(&tx, 0, &spent_utxos, SigHashType::Single, &CHAIN_ID));
+	assert_eq!(signature_hash(&tx, 2, &spent_utxos, SigHashType::All, &CHAIN_ID), None);
+}
@@ -0,0 +1,109 @@
+use crate::consensus::stake::StakeDistribution;
+use crate::core::block::{BlockError, BlockValidity};
+use crate::core::genesis::GenesisSpec;
+use crate::tests::blockchain::{forge_block, spend, Stakeholder, temporary_blockchain_from, test_parameters, Wallet};
+
+const EPOCH_DURATION: u32 = 10;
+
+#[test]
+fn leaders_need_stake() {
+	let (alice, bob) = (Stakeholder::default_forger(), Stakeholder::random());
+	let mut parameters = test_parameters();
+	parameters.technical_parameters.epoch_duration = EPOCH_DURATION;
+	let genesis = GenesisSpec {
+		stake_distribution: vec![alice.genesis_entry(1_000_000), bob.genesis_entry(1_000_000)],
+		parameters,
+		..Default::default()
+	};
+	let (mut chain, directory) = temporary_blockchain_from(genesis.clone());
+	let genesis_distribution = chain.get_stake_distribution(0);
+	assert_eq!(genesis_distribution.get_stake(&alice.vrf_public_key()), 1_000_000);
+	assert_eq!(genesis_distribution.get_total_stake(), 2_000_000);
+
+	// Nodes that are not stakeholders can not forge
+	let outsider = Stakeholder::random();
+	let block = forge_block(&chain, &chain.get_last_block(), vec![], 1, &outsider, outsider.wallet.address);
+	assert_eq!(chain.add_block(&block), Err(BlockError::NotLeader));
+
+	// Alice gives all her stake to Bob in the first epoch
+	let alice_stake = genesis.get_utxos()[0];
+	let to_bob = spend(&chain, &alice_stake, &alice.wallet, &[&bob.wallet]);
+	let first = forge_block(&chain, &chain.get_last_block(), vec![to_bob], 1, &alice, Wallet::random().address);
+	assert_eq!(chain.add_block(&first), Ok(BlockValidity::Valid));
+
+	// The stake of an epoch does not change during the epoch
+	assert_eq!(chain.get_stake_distribution(0), genesis_distribution);
+	let second = forge_block(&chain, &first, vec![], 2, &alice, Wallet::random().address);
+	assert_eq!(chain.add_block(&second), Ok(BlockValidity::Valid));
+
+	// But Alice has no stake in the next epoch
+	let next_epoch = EPOCH_DURATION as u64;
+	let snapshot = chain.get_stake_distribution(1);
+	assert_eq!(snapshot.get_stake(&alice.vrf_public_key()), 0);
+	assert_eq!(snapshot.get_stake(&bob.vrf_public_key()), snapshot.get_total_stake());
+	let by_alice = forge_block(&chain, &second, vec![], next_epoch, &alice, alice.wallet.address);
+	assert_eq!(chain.add_block(&by_alice), Err(BlockError::NotLeader));
+
+	// The reward of the first block of the epoch only counts from the next one
+	let by_bob = forge_block(&chain, &second, vec![], next_epoch, &bob, bob.wallet.address);
+	assert_eq!(chain.add_block(&by_bob), Ok(BlockValidity::Valid));
+	assert_eq!(chain.get_stake_distribution(1), snapshot);
+	assert!(chain.get_stake_distribution(2).get_stake(&bob.vrf_public_key()) > snapshot.get_stake(&bob.vrf_public_key()));
+	// Epochs without blocks have the stake of the current UTxO set
+	assert_eq!(chain.get_stake_distribution(2), StakeDistribution::from_utxos(&chain.utxo_set.get_all(), &genesis.stake_distribution));
+
+	// Undoing the first block of an epoch removes its snapshot
+	assert!(chain.undo_block(&by_bob));
+	assert!(chain.undo_block(&second));
+	assert!(chain.undo_block(&first));
+	assert_eq!(chain.get_stake_distribution(0), genesis_distribution);
+	assert_eq!(chain.get_stake_distribution(1), genesis_distribution);
+	assert_eq!(chain.get_stake_distribution(2), genesis_distribution);
+
+	drop(chain);
+	std::fs::remove_dir_all(directory).ok();
+}
+
+#[test]
+fn forks_use_the_stake_of_their_own_branch() {
+	let (alice, bob) = (Stakeholder::default_forger(), Stakeholder::random());
+	let mut parameters = test_parameters();
+	parameters.technical_parameters.epoch_duration = EPOCH_DURATION;
+	let genesis = GenesisSpec {
+		stake_distribution: vec![alice.genesis_entry(1_000_000), bob.genesis_entry(1_000_000)],
+		parameters,
+		..Default::default()
+	};
+	let (mut chain, directory) = temporary_blockchain_from(genesis.clone());
+	let (mut fork, fork_directory) = temporary_blockchain_from(genesis.clone());
+	let next_epoch = EPOCH_DURATION as u64;
+
+	// In the best chain Alice gives all her stake to Bob
+	let to_bob = spend(&chain, &genesis.get_utxos()[0], &alice.wallet, &[&bob.wallet]);
+	let first = forge_block(&chain, &chain.get_last_block(), vec![to_bob], 1, &alice, Wallet::random().address);
+	assert_eq!(chain.add_block(&first), Ok(BlockValidity::Valid));
+	let second = forge_block(&chain, &first, vec![], next_epoch, &bob, bob.wallet.address);
+	assert_eq!(chain.add_block(&second), Ok(BlockValidity::Valid));
+	let third = forge_block(&chain, &second, vec![], next_epoch + 1, &bob, bob.wallet.address);
+	assert_eq!(chain.add_block(&third), Ok(BlockValidity::Valid));
+	assert_eq!(chain.get_stake_distribution(1).get_stake(&alice.vrf_public_key()), 0);
+
+	// In the fork Alice keeps her stake, so she is still a leader in the next epoch
+	let mut branch = vec![];
+	for slot in [2, next_epoch + 2, next_epoch + 3, next_epoch + 4] {
+		let block = forge_block(&fork, &fork.get_last_block(), vec![], slot, &alice, alice.wallet.address);
+		assert_eq!(fork.add_block(&block), Ok(BlockValidity::Valid));
+		branch.push(block);
+	}
+	assert_eq!(chain.add_block(&branch[0]), Ok(BlockValidity::Fork));
+	assert_eq!(chain.add_block(&branch[1]), Ok(BlockValidity::Fork));
+	// The chains have the same length, so the lowest VRF output decides which one is the best
+	assert!(chain.add_block(&branch[2]).is_ok());
+	assert!(chain.add_block(&branch[3]).is_ok());
+	assert_eq!(chain.get_last_block(), branch[3]);
+	assert_eq!(chain.get_stake_distribution(1), fork.get_stake_distribution(1));
+
+	drop((chain, fork));
+	std::fs::remove_dir_all(directory).ok();
+	std::fs::remove_dir_all(fork_directory).ok();
+}
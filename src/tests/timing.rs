@@ -5,7 +5,8 @@ use std::time::{Duration, Instant};
 
 use rsntp::{AsyncSntpClient, SntpClient, SynchronizationError};
 
-use crate::network::node::{Node, STARTING_SLOT_SECOND};
+use crate::core::genesis::STARTING_SLOT_SECOND;
+use crate::network::node::Node;
 use crate::network::timing;
 use crate::network::timing::sync_to_slot;
 
@@ -20,7 +21,7 @@ pub async fn timing_test() {
 		ntp.set_timeout(Duration::from_secs_f32(1.0));
 		sync_to_slot(&ntp, 100).await;
 		let start = Instant::now();
-		let mut slot = timing::get_accurate_slot(&ntp, 100).await.unwrap();
+		let mut slot = timing::get_accurate_slot(&ntp, 100, STARTING_SLOT_SECOND).await.unwrap();
 		spin_sleep::sleep(Duration::from_secs_f32(0.1) - start.elapsed());
 
 		for _ in 0..1000 {
@@ -38,7 +39,7 @@ pub async fn timing_test() {
 		ntp.set_timeout(Duration::from_secs_f32(1.0));
 		sync_to_slot(&ntp, 100).await;
 		let start = Instant::now();
-		let mut slot = timing::get_accurate_slot(&ntp, 100).await.unwrap();
+		let mut slot = timing::get_accurate_slot(&ntp, 100, STARTING_SLOT_SECOND).await.unwrap();
 		spin_sleep::sleep(Duration::from_secs_f32(0.1) - start.elapsed());
 
 		for _ in 0..1000 {
@@ -0,0 +1,74 @@
+use actix_web::FromRequest;
+use actix_web::http::header;
+use actix_web::test::TestRequest;
+
+use crate::core::block::Block;
+use crate::core::genesis::GenesisSpec;
+use crate::network::models::GetHeaders;
+use crate::network::standard::{BINCODE_DATA_TYPE, JSON_DATA_TYPE, StandardExtractor, WIRE_VERSION, WireFormat};
+
+#[test]
+fn wire_formats_round_trip() {
+	let block = GenesisSpec::default().get_block();
+	let bincode = WireFormat::Bincode.encode(&block).unwrap();
+	let json = WireFormat::Json.encode(&block).unwrap();
+	assert_eq!(WireFormat::Bincode.decode::<Block>(&bincode).unwrap(), block);
+	assert_eq!(WireFormat::Json.decode::<Block>(&json).unwrap(), block);
+	assert!(bincode.len() < json.len());
+
+	// Each format only reads its own encoding
+	assert!(WireFormat::Bincode.decode::<Block>(&json).is_err());
+	assert!(WireFormat::Json.decode::<Block>(&bincode).is_err());
+	// Trailing bytes are rejected
+	assert!(WireFormat::Bincode.decode::<Block>(&[bincode.clone(), vec![0]].concat()).is_err());
+
+	// Messages are wrapped in a versioned envelope
+	let envelope: serde_json::Value = serde_json::from_slice(&json).unwrap();
+	assert_eq!(envelope["wire_version"], WIRE_VERSION);
+	let other_version = serde_json::json!({ "wire_version": WIRE_VERSION + 1, "payload": envelope["payload"] });
+	assert!(WireFormat::Json.decode::<Block>(other_version.to_string().as_bytes()).is_err());
+}
+
+#[test]
+fn media_types() {
+	assert_eq!(WireFormat::from_media_type("application/json; charset=utf-8"), Some(WireFormat::Json));
+	assert_eq!(WireFormat::from_media_type(BINCODE_DATA_TYPE), Some(WireFormat::Bincode));
+	assert_eq!(WireFormat::from_media_type("text/plain"), None);
+	assert_eq!(WireFormat::from_accept("text/html, application/json;q=0.9, */*;q=0.8"), Some(WireFormat::Json));
+	assert_eq!(WireFormat::from_accept("*/*"), None);
+}
+
+#[actix_rt::test]
+async fn extractor_negotiates_format() {
+	let msg = GetHeaders { version: 0, block_locator_object: vec![[7u8; 32]] };
+
+	// JSON requests get JSON responses unless they ask for something else
+	let (req, mut payload) = TestRequest::default()
+		.insert_header((header::CONTENT_TYPE, JSON_DATA_TYPE))
+		.set_payload(WireFormat::Json.encode(&msg).unwrap())
+		.to_http_parts();
+	let extracted = StandardExtractor::<GetHeaders>::from_request(&req, &mut payload).await.unwrap();
+	assert_eq!(extracted.block_locator_object, msg.block_locator_object);
+	assert_eq!(extracted.response_format, WireFormat::Json);
+
+	let (req, mut payload) = TestRequest::default()
+		.insert_header((header::CONTENT_TYPE, BINCODE_DATA_TYPE))
+		.insert_header((header::ACCEPT, JSON_DATA_TYPE))
+		.set_payload(WireFormat::Bincode.encode(&msg).unwrap())
+		.to_http_parts();
+	let extracted = StandardExtractor::<GetHeaders>::from_request(&req, &mut payload).await.unwrap();
+	assert_eq!(extracted.block_locator_object, msg.block_locator_object);
+	assert_eq!(extracted.response_format, WireFormat::Json);
+
+	// Bodies that do not match their Content-Type and unknown content types are rejected
+	let (req, mut payload) = TestRequest::default()
+		.insert_header((header::CONTENT_TYPE, BINCODE_DATA_TYPE))
+		.set_payload(WireFormat::Json.encode(&msg).unwrap())
+		.to_http_parts();
+	assert!(StandardExtractor::<GetHeaders>::from_request(&req, &mut payload).await.is_err());
+	let (req, mut payload) = TestRequest::default()
+		.insert_header((header::CONTENT_TYPE, "text/plain"))
+		.set_payload(WireFormat::Json.encode(&msg).unwrap())
+		.to_http_parts();
+	assert!(StandardExtractor::<GetHeaders>::from_request(&req, &mut payload).await.is_err());
+}